@@ -7,17 +7,65 @@ use piece::Piece;
 pub enum Action {
     MovePiece(Piece, Coordinate, Coordinate),
     Capture(Piece, Piece, Coordinate, Coordinate),
-    Promotion(Piece, Piece, Coordinate, Coordinate),
+    // The pawn, what it becomes, the piece it takes on the way if it takes
+    // one, and where it comes from and goes to
+    Promotion(Piece, Piece, Option<Piece>, Coordinate, Coordinate),
+    // The king, where it comes from and goes to, and the rook it castles
+    // with. The rook ends up next to the king, on the inside.
+    Castle(Piece, Coordinate, Coordinate, Coordinate),
+    // A piece from the side's pocket put on an empty square, in Crazyhouse
+    Drop(Piece, Coordinate),
+}
+
+// Drops come from the pocket rather than a square, so they give the square
+// they land on.
+pub fn from_coordinate_for(action: &Action) -> &Coordinate {
+    match *action {
+        Action::MovePiece(_,ref from,_) => from,
+        Action::Capture(_,_,ref from,_) => from,
+        Action::Promotion(_,_,_,ref from,_) => from,
+        Action::Castle(_,ref from,_,_) => from,
+        Action::Drop(_,ref to) => to,
+    }
 }
 
 pub fn to_coordinate_for(action: &Action) -> &Coordinate {
     match *action {
         Action::MovePiece(_,_,ref to) => to,
         Action::Capture(_,_,_,ref to) => to,
-        _ => unimplemented!()
+        Action::Promotion(_,_,_,_,ref to) => to,
+        Action::Castle(_,_,ref to,_) => to,
+        Action::Drop(_,ref to) => to,
+    }
+}
+
+// The piece the action takes off the board, if it takes one.
+pub fn captured_piece_for(action: &Action) -> Option<Piece> {
+    match *action {
+        Action::Capture(_,target,_,_) => Some(target),
+        Action::Promotion(_,_,captured,_,_) => captured,
+        _ => None,
     }
 }
 
+// The square a player picks to make the action on. Castling is picked on
+// the rook's square, as long algebraic writes it, since in Chess960 the
+// king can castle to a square it could also step to, or to where it is.
+pub fn picked_coordinate_for(action: &Action) -> &Coordinate {
+    match *action {
+        Action::Castle(_,_,_,ref rook) => rook,
+        _ => to_coordinate_for(action),
+    }
+}
+
+// Where the rook goes when the king castles to the given square: the d file
+// when castling long to the c file, or else the file next to the king on
+// the inside, the f file on a usual board.
+pub fn castled_rook_coordinate(king_to: &Coordinate) -> Coordinate {
+    let column = if king_to.column() == 2 { 3 } else { king_to.column() - 1 };
+    Coordinate::new(king_to.row(), column)
+}
+
 impl fmt::Debug for Action {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -25,7 +73,12 @@ impl fmt::Debug for Action {
                 write!(f, "{:?} ::{}->{}", piece, from.to_human(), to.to_human()),
             Action::Capture(piece1, piece2, from, to) =>
                 write!(f, "CAPTURE {:?} {:?} :: {}-{}", piece1, piece2, from.to_human(), to.to_human()),
-            _ => unimplemented!()
+            Action::Promotion(pawn, promoted_to, captured, from, to) =>
+                write!(f, "PROMOTE {:?} {:?} {:?} :: {}-{}", pawn, promoted_to, captured, from.to_human(), to.to_human()),
+            Action::Castle(king, from, to, rook) =>
+                write!(f, "CASTLE {:?} :: {}-{} {}", king, from.to_human(), to.to_human(), rook.to_human()),
+            Action::Drop(piece, to) =>
+                write!(f, "DROP {:?} :: {}", piece, to.to_human()),
         }
     }
 }
@@ -1,52 +1,348 @@
+use std::cmp::Reverse;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use rand::{self, Rng};
+
 use Side;
 use game::{GameState};
-use action::Action;
+use action::{Action, captured_piece_for};
 use piece::{Piece, Rank};
 use engine;
+use polyglot::Book;
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Ai {
+    book: Option<Book>,
+    level: Level,
+}
+
+impl Ai {
+    pub fn new() -> Ai {
+        Ai {
+            book: None,
+            level: Level::Max,
+        }
+    }
+
+    pub fn with_book(mut self, book: Book) -> Ai {
+        self.book = Some(book);
+        self
+    }
+
+    pub fn with_level(mut self, level: Level) -> Ai {
+        self.level = level;
+        self
+    }
+
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    // Plays from the opening book while it has a move for the position,
+    // and searches otherwise.
+    pub fn make_move(&self, state: &mut GameState) -> Option<Action> {
+        if let Some(ref book) = self.book {
+            if let Some(action) = book.choose(state) {
+                return Some(action)
+            }
+        }
+
+        let settings = self.level.settings();
+        let mut search = Search::new(settings);
+        let ranked = rank_root_actions(state, &mut search);
+
+        pick_action(&ranked, &settings, &mut rand::thread_rng())
+    }
+}
 
+impl Default for Ai {
+    fn default() -> Ai {
+        Ai::new()
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Level {
+    Beginner,
+    Casual,
+    Intermediate,
+    Advanced,
+    Max,
+}
+
+// How a level weakens the search. Noise is in evaluation units, where a
+// pawn is worth 10.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Settings {
+    pub depth: u8,
+    pub node_limit: Option<u64>,
+    pub noise: i32,
+    pub top_n: usize,
+    pub mistake_chance: f64,
+}
+
+impl Level {
+    pub fn all() -> Vec<Level> {
+        vec![Level::Beginner, Level::Casual, Level::Intermediate, Level::Advanced, Level::Max]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Level::Beginner => "beginner",
+            Level::Casual => "casual",
+            Level::Intermediate => "intermediate",
+            Level::Advanced => "advanced",
+            Level::Max => "max",
+        }
+    }
+
+    pub fn settings(&self) -> Settings {
+        match *self {
+            Level::Beginner => Settings { depth: 0, node_limit: Some(2_000), noise: 30, top_n: 5, mistake_chance: 0.4 },
+            Level::Casual => Settings { depth: 1, node_limit: Some(10_000), noise: 15, top_n: 4, mistake_chance: 0.25 },
+            Level::Intermediate => Settings { depth: 2, node_limit: Some(50_000), noise: 5, top_n: 3, mistake_chance: 0.1 },
+            Level::Advanced => Settings { depth: 3, node_limit: Some(200_000), noise: 2, top_n: 2, mistake_chance: 0.05 },
+            Level::Max => Settings { depth: 3, node_limit: None, noise: 0, top_n: 1, mistake_chance: 0.0 },
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Level, String> {
+        Level::all()
+            .into_iter()
+            .find(|level| level.name() == name)
+            .ok_or_else(|| format!("Unknown level {}", name))
+    }
+}
+
+// Bookkeeping for a single search.
+struct Search {
+    settings: Settings,
+    nodes: u64,
+    stop: Option<Arc<AtomicBool>>,
+}
+
+impl Search {
+    fn new(settings: Settings) -> Search {
+        Search { settings, nodes: 0, stop: None }
+    }
+
+    fn stopped(&self) -> bool {
+        match self.stop {
+            Some(ref stop) => stop.load(Ordering::Relaxed),
+            None => false,
+        }
+    }
+
+    fn out_of_nodes(&self) -> bool {
+        let over_limit = match self.settings.node_limit {
+            Some(limit) => self.nodes >= limit,
+            None => false,
+        };
+        over_limit || self.stopped()
+    }
+
+    fn noise(&self) -> i32 {
+        if self.settings.noise == 0 {
+            0
+        } else {
+            rand::thread_rng().gen_range(-self.settings.noise, self.settings.noise + 1)
+        }
+    }
+}
+
+// Scores above this can only come from a line where a king gets captured,
+// or the game is won by the variant's rules.
+const MATE_THRESHOLD: i32 = 5000;
+// What winning by the variant's rules is worth, the same as taking a king.
+const VARIANT_WIN: i32 = 10000;
+
+// A search score from the point of view of the side to move. Mate(n) mates
+// in n moves, Mate(-n) gets mated in n.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Score {
+    Centipawns(i32),
+    Mate(i32),
+}
+
+impl Score {
+    fn from_search(score: i32, pv: &[Action]) -> Score {
+        let king_capture = pv.iter().position(|action| match captured_piece_for(action) {
+            Some(target) => target.rank() == Rank::King,
+            None => false,
+        });
+
+        match king_capture {
+            Some(ply) if score > MATE_THRESHOLD => Score::Mate(ply as i32 / 2),
+            Some(ply) if score < -MATE_THRESHOLD => Score::Mate(-(ply as i32 - 1) / 2),
+            // A win by the variant's rules ends the line on the winning move
+            None if score > MATE_THRESHOLD => Score::Mate((pv.len() as i32 + 1) / 2),
+            None if score < -MATE_THRESHOLD => Score::Mate(-(pv.len() as i32) / 2),
+            // A pawn is worth 10 in evaluation units
+            _ => Score::Centipawns(score * 10),
+        }
+    }
+}
+
+// What an iteration of the analysis found.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Progress {
+    pub depth: u8,
+    pub score: Score,
+    pub pv: Vec<Action>,
+    pub nodes: u64,
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    pub fn nodes_per_second(&self) -> u64 {
+        let millis = self.elapsed.as_secs() * 1000 + u64::from(self.elapsed.subsec_millis());
+        self.nodes * 1000 / millis.max(1)
+    }
+}
 
 pub fn make_move(state: &mut GameState) -> Option<Action> {
     root_alpha_beta(state)
 }
 
 pub fn root_alpha_beta(state: &mut GameState) -> Option<Action> {
+    let mut search = Search::new(Level::Max.settings());
+    rank_root_actions(state, &mut search).into_iter().next().map(|(action, _score)| action)
+}
+
+// The best move for the side to move along with its score, searched at
+// full strength regardless of any level.
+pub fn suggest_move(state: &mut GameState) -> Option<(Action, i32)> {
+    let mut search = Search::new(Level::Max.settings());
+    rank_root_actions(state, &mut search).into_iter().next()
+}
+
+// Searches one ply deeper each iteration, reporting the result of every
+// completed iteration until max_depth is reached or stop is set.
+pub fn iterative_deepening<F>(state: &mut GameState, max_depth: u8, stop: Arc<AtomicBool>, mut report: F) where F: FnMut(Progress) {
+    let started = Instant::now();
+    let mut search = Search::new(Level::Max.settings());
+    search.stop = Some(stop);
+
+    for depth in 0..max_depth {
+        search.settings.depth = depth;
+        let best = search_root(state, &mut search).into_iter().next();
+
+        if search.stopped() {
+            break;
+        }
+
+        match best {
+            Some((_action, score, pv)) => report(Progress {
+                depth: depth + 1,
+                score: Score::from_search(score, &pv),
+                pv,
+                nodes: search.nodes,
+                elapsed: started.elapsed(),
+            }),
+            None => break,
+        }
+    }
+}
+
+// Scores every root move, best first. Ties keep the last move generated
+// first, matching what max_by_key used to pick.
+fn rank_root_actions(state: &mut GameState, search: &mut Search) -> Vec<(Action, i32)> {
+    search_root(state, search)
+        .into_iter()
+        .map(|(action, score, _pv)| (action, score))
+        .collect()
+}
+
+// Like rank_root_actions, along with the principal variation of each move.
+fn search_root(state: &mut GameState, search: &mut Search) -> Vec<(Action, i32, Vec<Action>)> {
     let side = state.next_to_move();
-    let action = possible_actions(state, side).into_iter().max_by_key(|action| {
-        state.evaluate_with_action(action.clone(), |mut new_state| {
-            -alpha_beta(3, &mut new_state, side, <i32>::min_value()+1, <i32>::max_value())
+    let depth = search.settings.depth;
+
+    // Only legal moves at the root, so even a deliberate mistake can be
+    // played
+    let mut ranked: Vec<(Action, i32, Vec<Action>)> = engine::all_possible_actions(state)
+        .into_iter()
+        .map(|action| {
+            let mut pv = vec![action];
+            let score = state.evaluate_with_action(action, |new_state| {
+                -alpha_beta(depth, new_state, side, <i32>::min_value()+1, <i32>::max_value(), search, &mut pv)
+            });
+            (action, score, pv)
         })
-    });
-    action
+        .collect();
+
+    ranked.reverse();
+    ranked.sort_by_key(|&(_, score, _)| Reverse(score));
+    ranked
+}
+
+// Usually plays the best move, but every so often picks any of the top few
+// to give weaker levels a human feel.
+fn pick_action<R: Rng>(ranked: &[(Action, i32)], settings: &Settings, rng: &mut R) -> Option<Action> {
+    if ranked.is_empty() {
+        return None
+    }
+
+    let candidates = settings.top_n.max(1).min(ranked.len());
+    if candidates > 1 && rng.gen::<f64>() < settings.mistake_chance {
+        Some(ranked[rng.gen_range(0, candidates)].0)
+    } else {
+        Some(ranked[0].0)
+    }
 }
 
 fn possible_actions(state: &mut GameState, side: Side) -> Vec<Action> {
-    state.board().pieces_with_coordinates()
+    let mut actions: Vec<Action> = state.board().pieces_with_coordinates()
         .into_iter()
         .filter(|&(_coordinate, piece)| piece.side() == side)
         .map(|(coordinate, _piece)| coordinate)
         .flat_map(|coordinate| engine::enumerate_all_actions(&coordinate, state))
-        .collect()
+        .collect();
+    if side == state.next_to_move() {
+        actions.append(&mut state.variant().drops(state));
+    }
+    actions
 }
 
 
-fn alpha_beta(depth: u8, state: &mut GameState, my_side: Side, mut alpha: i32, beta: i32) -> i32 {
+// Appends the best line found below this node to pv.
+fn alpha_beta(depth: u8, state: &mut GameState, my_side: Side, mut alpha: i32, beta: i32, search: &mut Search, pv: &mut Vec<Action>) -> i32 {
+    search.nodes += 1;
+
     let next_to_move = state.next_to_move();
+    if let Some(winner) = state.variant().winner(state) {
+        // Sooner wins are better, and sooner losses worse
+        let score = VARIANT_WIN + depth as i32;
+        return if winner == next_to_move { score } else { -score };
+    }
+
     let mut actions = possible_actions(state, next_to_move);
-    actions.sort_by_key(|&a| { match a { Action::Capture(_, _, _, _) => 1, _ => 2 } });
-    if depth == 0 || actions.is_empty() {
-        return evaluate_board(state, next_to_move) - depth as i32; // penalty for games that end early
+    actions.sort_by_key(|&a| { match a { Action::Capture(_, _, _, _) | Action::Promotion(..) => 1, _ => 2 } });
+    if depth == 0 || actions.is_empty() || search.out_of_nodes() {
+        return evaluate_board(state, next_to_move) - depth as i32 + search.noise(); // penalty for games that end early
     }
 
     let mut score = <i32>::min_value()+1;
+    let mut best_line = vec![];
 
     for action in actions {
-        let value = state.evaluate_with_action(action.clone(), |mut new_state| {
-            -alpha_beta(depth - 1, &mut new_state, my_side, -beta, -alpha)
+        let mut line = vec![action];
+        let value = state.evaluate_with_action(action, |new_state| {
+            -alpha_beta(depth - 1, new_state, my_side, -beta, -alpha, search, &mut line)
         });
-        if value > score { score = value; }
+        if value > score { score = value; best_line = line; }
         if score > alpha { alpha = score; }
         if score >= beta { break; }
+        if search.out_of_nodes() { break; }
     }
+    pv.append(&mut best_line);
     score
 }
 
@@ -59,15 +355,24 @@ fn evaluate_piece(piece: Piece, my_side: Side) -> i32 {
         Rank::Rook => 5,
         Rank::Queen => 9,
         Rank::King => 1000,
+        Rank::Archbishop => 7,
+        Rank::Chancellor => 8,
+        Rank::Amazon => 12,
     };
     if piece.side() == my_side { score } else { -score }
 }
 
 fn evaluate_board(state: &mut GameState, my_side: Side) -> i32 {
-    let material_score: i32 = state.board().pieces_with_coordinates()
+    let on_board: i32 = state.board().pieces_with_coordinates()
         .into_iter()
         .map(|(_coordinate, piece)| evaluate_piece(piece, my_side))
         .sum();
+    // Pieces in hand are as good as on the board, if not better
+    let in_pockets: i32 = [Side::White, Side::Black].iter()
+        .flat_map(|&side| state.pockets().all_pieces(side))
+        .map(|piece| evaluate_piece(piece, my_side))
+        .sum();
+    let material_score = on_board + in_pockets;
     let mobility_score = possible_actions(state, my_side).len() as i32;
     10 * material_score + mobility_score
 }
@@ -77,6 +382,9 @@ fn evaluate_board(state: &mut GameState, my_side: Side) -> i32 {
 mod tests {
     use super::*;
     use board::{Board, Coordinate};
+    use game::Status;
+    use rand::XorShiftRng;
+    use variant::KingOfTheHill;
 
     macro_rules! coord {
         ($x:expr) => { Coordinate::from_human($x.to_string()).unwrap() }
@@ -117,24 +425,157 @@ mod tests {
         board.update(&coord!("h1"), Some(Piece::pack(Side::White, Rank::King))).unwrap();
         board.update(&coord!("a1"), Some(Piece::pack(Side::Black, Rank::King))).unwrap();
         let mut state = GameState::with_board(board);
-        println!("{:?}", state.board());
         let action1 = make_move(&mut state);
-        println!("{:?}", action1);
-        println!("{:?}", state.board());
-        state.advance(action1.clone().unwrap()).unwrap();
+        state.play(action1.unwrap()).unwrap();
         let action2 = make_move(&mut state);
-        println!("{:?}", action2);
-        println!("{:?}", state.board());
-        state.advance(action2.clone().unwrap()).unwrap();
-        let action3 = make_move(&mut state);
-        println!("{:?}", action3);
-        println!("{:?}", state.board());
-        state.advance(action3.clone().unwrap()).unwrap();
-        let action4 = make_move(&mut state);
-        println!("{:?}\n {:?}\n {:?}\n {:?}", action1, action2, action3, action4);
-        println!("{:?}", state.board());
-
-        assert!(matches!(action4, Some(Action::Capture(_, _, _, _))));
+        state.play(action2.unwrap()).unwrap();
+
+        // White's king can only be mated now, not captured
+        assert_eq!(state.status(), Status::Checkmate);
+        assert_eq!(make_move(&mut state), None);
+    }
+
+    #[test]
+    fn parses_levels_by_name() {
+        assert_eq!("beginner".parse::<Level>(), Ok(Level::Beginner));
+        assert_eq!("max".parse::<Level>(), Ok(Level::Max));
+        assert!("grandmaster".parse::<Level>().is_err());
+    }
+
+    #[test]
+    fn max_level_plays_the_best_move() {
+        let mut board = Board::empty();
+        board.update(&coord!("b5"), Some(Piece::pack(Side::White, Rank::Bishop))).unwrap();
+        board.update(&coord!("e8"), Some(Piece::pack(Side::Black, Rank::Queen))).unwrap();
+        board.update(&coord!("h8"), Some(Piece::pack(Side::Black, Rank::King))).unwrap();
+        board.update(&coord!("a1"), Some(Piece::pack(Side::White, Rank::King))).unwrap();
+        let mut state = GameState::with_board(board);
+
+        let action = Ai::new().with_level(Level::Max).make_move(&mut state);
+
+        assert_eq!(action, root_alpha_beta(&mut state));
+        assert!(matches!(action, Some(Action::Capture(_, _, _, _))));
+    }
+
+    #[test]
+    fn weaker_levels_only_pick_from_the_top_moves() {
+        let mut state = GameState::new();
+        let settings = Settings { depth: 0, node_limit: None, noise: 0, top_n: 3, mistake_chance: 1.0 };
+        let ranked = rank_root_actions(&mut state, &mut Search::new(settings));
+        let top_three: Vec<Action> = ranked.iter().take(3).map(|&(action, _)| action).collect();
+        let mut rng = XorShiftRng::new_unseeded();
+
+        for _ in 0..50 {
+            let action = pick_action(&ranked, &settings, &mut rng).unwrap();
+            assert!(top_three.contains(&action));
+        }
+    }
+
+    #[test]
+    fn node_limits_cut_the_search_short() {
+        let mut state = GameState::new();
+        let settings = Settings { depth: 3, node_limit: Some(100), noise: 0, top_n: 1, mistake_chance: 0.0 };
+        let mut search = Search::new(settings);
+
+        rank_root_actions(&mut state, &mut search);
+
+        // Each of the 20 root moves still gets evaluated once the budget is spent
+        assert!(search.nodes <= 100 + 20 + 3);
+    }
+
+    #[test]
+    fn suggests_the_best_move_with_its_score() {
+        let mut board = Board::empty();
+        board.update(&coord!("b5"), Some(Piece::pack(Side::White, Rank::Bishop))).unwrap();
+        board.update(&coord!("e8"), Some(Piece::pack(Side::Black, Rank::Queen))).unwrap();
+        board.update(&coord!("h8"), Some(Piece::pack(Side::Black, Rank::King))).unwrap();
+        board.update(&coord!("a1"), Some(Piece::pack(Side::White, Rank::King))).unwrap();
+        let mut state = GameState::with_board(board);
+
+        let (action, score) = suggest_move(&mut state).unwrap();
+
+        assert_eq!(action, Action::Capture(
+            Piece::pack(Side::White, Rank::Bishop),
+            Piece::pack(Side::Black, Rank::Queen),
+            coord!("b5"),
+            coord!("e8"),
+        ));
+        assert!(score > 0);
+        assert!(state.history().is_empty(), "The hint must not be played");
+    }
+
+    #[test]
+    fn reports_every_iteration_with_a_principal_variation() {
+        let mut board = Board::empty();
+        board.update(&coord!("b5"), Some(Piece::pack(Side::White, Rank::Bishop))).unwrap();
+        board.update(&coord!("e8"), Some(Piece::pack(Side::Black, Rank::Queen))).unwrap();
+        board.update(&coord!("h8"), Some(Piece::pack(Side::Black, Rank::King))).unwrap();
+        board.update(&coord!("a1"), Some(Piece::pack(Side::White, Rank::King))).unwrap();
+        let mut state = GameState::with_board(board);
+        let mut reports = vec![];
+
+        iterative_deepening(&mut state, 3, Arc::new(AtomicBool::new(false)), |progress| reports.push(progress));
+
+        assert_eq!(reports.iter().map(|progress| progress.depth).collect::<Vec<u8>>(), vec![1, 2, 3]);
+        for progress in reports {
+            assert_eq!(progress.pv.len(), progress.depth as usize);
+            assert!(matches!(progress.pv[0], Action::Capture(_, _, _, _)));
+            assert!(matches!(progress.score, Score::Centipawns(cp) if cp > 0));
+        }
+    }
+
+    #[test]
+    fn stops_when_asked_to() {
+        let mut state = GameState::new();
+        let mut reports = 0;
+
+        iterative_deepening(&mut state, 3, Arc::new(AtomicBool::new(true)), |_progress| reports += 1);
+
+        assert_eq!(reports, 0);
+    }
+
+    #[test]
+    fn reports_mate_scores_from_the_line() {
+        let capture_king = Action::Capture(
+            Piece::pack(Side::White, Rank::Rook),
+            Piece::pack(Side::Black, Rank::King),
+            coord!("a8"),
+            coord!("h8"),
+        );
+        let quiet = Action::MovePiece(Piece::pack(Side::White, Rank::Rook), coord!("a1"), coord!("a8"));
+
+        assert_eq!(Score::from_search(9000, &[quiet, quiet, capture_king]), Score::Mate(1));
+        assert_eq!(Score::from_search(-9000, &[quiet, quiet, quiet, capture_king]), Score::Mate(-1));
+        assert_eq!(Score::from_search(12, &[quiet]), Score::Centipawns(120));
+    }
+
+    #[test]
+    fn takes_an_immediate_variant_win() {
+        let mut state = GameState::from_variant_fen("1k6/8/8/8/8/4K1n1/8/7Q w - - 0 1", &KingOfTheHill).unwrap();
+        let king_to_the_hill = Action::MovePiece(Piece::pack(Side::White, Rank::King), coord!("e3"), coord!("d4"));
+
+        assert_eq!(make_move(&mut state), Some(king_to_the_hill));
+        for level in Level::all() {
+            let ranked = rank_root_actions(&mut state, &mut Search::new(level.settings()));
+            assert_eq!(ranked[0].0, king_to_the_hill, "{:?}", level);
+        }
+
+        let mut reports = vec![];
+        iterative_deepening(&mut state, 2, Arc::new(AtomicBool::new(false)), |progress| reports.push(progress));
+        assert!(reports.iter().all(|progress| progress.score == Score::Mate(1)));
+    }
+
+    #[test]
+    fn plays_book_moves_in_the_opening() {
+        let book = Book::from_bytes(include_bytes!("../tests/fixtures/tiny.bin")).unwrap();
+        let ai = Ai::new().with_book(book);
+        let mut state = GameState::new();
+        state.play(Action::MovePiece(Piece::pack(Side::White, Rank::Pawn), coord!("e2"), coord!("e4"))).unwrap();
+
+        assert_eq!(
+            ai.make_move(&mut state),
+            Some(Action::MovePiece(Piece::pack(Side::Black, Rank::Pawn), coord!("e7"), coord!("e5")))
+        );
     }
 }
 
@@ -0,0 +1,166 @@
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+use action::Action;
+use game::GameState;
+use ai::{self, Ai, Progress};
+
+// How many plies deep the analysis searches before it settles.
+const MAX_DEPTH: u8 = 6;
+
+// Runs the engine on a background thread for one position, collecting the
+// progress of each completed iteration. Dropping it stops the search.
+pub struct Analysis {
+    state: GameState,
+    latest: Option<Progress>,
+    receiver: Receiver<Progress>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Analysis {
+    pub fn start(state: &GameState) -> Analysis {
+        let (sender, receiver) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let mut search_state = state.clone();
+        let search_stop = stop.clone();
+        thread::spawn(move || {
+            ai::iterative_deepening(&mut search_state, MAX_DEPTH, search_stop, |progress| {
+                // The receiving end going away just means nobody is watching anymore
+                let _ = sender.send(progress);
+            });
+        });
+
+        Analysis {
+            state: state.clone(),
+            latest: None,
+            receiver,
+            stop,
+        }
+    }
+
+    pub fn state(&self) -> &GameState {
+        &self.state
+    }
+
+    pub fn latest(&self) -> Option<&Progress> {
+        self.latest.as_ref()
+    }
+
+    // Picks up any finished iterations. Returns whether anything changed.
+    pub fn poll(&mut self) -> bool {
+        let mut updated = false;
+        while let Ok(progress) = self.receiver.try_recv() {
+            self.latest = Some(progress);
+            updated = true;
+        }
+        updated
+    }
+}
+
+impl Drop for Analysis {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl PartialEq for Analysis {
+    fn eq(&self, other: &Analysis) -> bool {
+        self.state == other.state && self.latest == other.latest
+    }
+}
+
+impl fmt::Debug for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Analysis {{ latest: {:?} }}", self.latest)
+    }
+}
+
+// Looks for a move to play on a background thread, with the opponent's
+// settings or at full strength without one. The search can't be stopped,
+// so dropping it only means nobody waits for the move.
+pub struct MoveSearch {
+    state: GameState,
+    receiver: Receiver<Option<Action>>,
+}
+
+impl MoveSearch {
+    pub fn start(opponent: Option<Ai>, state: &GameState) -> MoveSearch {
+        let (sender, receiver) = channel();
+
+        let mut search_state = state.clone();
+        thread::spawn(move || {
+            let action = match opponent {
+                Some(opponent) => opponent.make_move(&mut search_state),
+                None => ai::make_move(&mut search_state),
+            };
+            let _ = sender.send(action);
+        });
+
+        MoveSearch {
+            state: state.clone(),
+            receiver,
+        }
+    }
+
+    pub fn state(&self) -> &GameState {
+        &self.state
+    }
+
+    // The move found, if there is one, once the search is done. None while
+    // it is still going.
+    pub fn poll(&self) -> Option<Option<Action>> {
+        self.receiver.try_recv().ok()
+    }
+
+    // Blocks until the search is done.
+    pub fn wait(self) -> Option<Action> {
+        self.receiver.recv().unwrap_or(None)
+    }
+}
+
+impl PartialEq for MoveSearch {
+    fn eq(&self, other: &MoveSearch) -> bool {
+        self.state == other.state
+    }
+}
+
+impl fmt::Debug for MoveSearch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MoveSearch {{ to_move: {:?} }}", self.state.next_to_move())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn reports_progress_from_the_background() {
+        let mut analysis = Analysis::start(&GameState::new());
+
+        for _ in 0..100 {
+            if analysis.poll() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        let progress = analysis.latest().expect("No progress reported");
+        assert!(progress.depth >= 1);
+        assert!(!progress.pv.is_empty());
+    }
+
+    #[test]
+    fn finds_a_move_in_the_background() {
+        let mut state = GameState::new();
+        let search = MoveSearch::start(None, &state);
+
+        let action = search.wait().expect("No move found");
+        assert!(state.legal_moves().contains(&action));
+    }
+}
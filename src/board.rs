@@ -1,96 +1,139 @@
 use piece::{Piece, Rank};
 use Side;
 use fmt;
+use error::InvalidCoordinate;
+use std::slice::Chunks;
+use std::str::FromStr;
+use rand::{self, Rng};
 
+// The squares are stored a rank at a time from the first rank up, so a
+// board can be any size up to MAX_SIZE on either side.
 #[derive(PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct Board {
-    data: [[Option<Piece>; 8]; 8],
+    width: usize,
+    squares: Vec<Option<Piece>>,
 }
 
+// The most files or ranks a board can have, lettered a to p and numbered
+// 1 to 16.
+pub const MAX_SIZE: usize = 16;
+
+// What piece_at gives for squares off the board.
+static OFF_THE_BOARD: Option<Piece> = None;
+
+impl Default for Board {
+    fn default() -> Board {
+        Board::chess960(518).unwrap()
+    }
+}
+
+// Where the knights go in Chess960 numbering, among the five squares left
+// once the bishops and queen are placed.
+const CHESS960_KNIGHTS: [(usize, usize); 10] = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+
 impl Board {
-    pub fn default() -> Board {
-        Board {
-            data: [
-                [
-                    Some(Piece::pack(Side::White, Rank::Rook)),
-                    Some(Piece::pack(Side::White, Rank::Knight)),
-                    Some(Piece::pack(Side::White, Rank::Bishop)),
-                    Some(Piece::pack(Side::White, Rank::Queen)),
-                    Some(Piece::pack(Side::White, Rank::King)),
-                    Some(Piece::pack(Side::White, Rank::Bishop)),
-                    Some(Piece::pack(Side::White, Rank::Knight)),
-                    Some(Piece::pack(Side::White, Rank::Rook)),
-                ],
-                [
-                    Some(Piece::pack(Side::White, Rank::Pawn)),
-                    Some(Piece::pack(Side::White, Rank::Pawn)),
-                    Some(Piece::pack(Side::White, Rank::Pawn)),
-                    Some(Piece::pack(Side::White, Rank::Pawn)),
-                    Some(Piece::pack(Side::White, Rank::Pawn)),
-                    Some(Piece::pack(Side::White, Rank::Pawn)),
-                    Some(Piece::pack(Side::White, Rank::Pawn)),
-                    Some(Piece::pack(Side::White, Rank::Pawn)),
-                ],
-                [None, None, None, None, None, None, None, None],
-                [None, None, None, None, None, None, None, None],
-                [None, None, None, None, None, None, None, None],
-                [None, None, None, None, None, None, None, None],
-                [
-                    Some(Piece::pack(Side::Black, Rank::Pawn)),
-                    Some(Piece::pack(Side::Black, Rank::Pawn)),
-                    Some(Piece::pack(Side::Black, Rank::Pawn)),
-                    Some(Piece::pack(Side::Black, Rank::Pawn)),
-                    Some(Piece::pack(Side::Black, Rank::Pawn)),
-                    Some(Piece::pack(Side::Black, Rank::Pawn)),
-                    Some(Piece::pack(Side::Black, Rank::Pawn)),
-                    Some(Piece::pack(Side::Black, Rank::Pawn)),
-                ],
-                [
-                    Some(Piece::pack(Side::Black, Rank::Rook)),
-                    Some(Piece::pack(Side::Black, Rank::Knight)),
-                    Some(Piece::pack(Side::Black, Rank::Bishop)),
-                    Some(Piece::pack(Side::Black, Rank::Queen)),
-                    Some(Piece::pack(Side::Black, Rank::King)),
-                    Some(Piece::pack(Side::Black, Rank::Bishop)),
-                    Some(Piece::pack(Side::Black, Rank::Knight)),
-                    Some(Piece::pack(Side::Black, Rank::Rook)),
-                ],
-            ],
+    // One of the 960 Fischer Random starting positions, numbered as usual
+    // from 0 to 959. Number 518 is the standard starting position.
+    pub fn chess960(number: usize) -> Option<Board> {
+        if number >= 960 {
+            return None
+        }
+
+        let white = |rank| Some(Piece::pack(Side::White, rank));
+        let mut back_rank: [Option<Piece>; 8] = [None, None, None, None, None, None, None, None];
+        back_rank[2 * (number % 4) + 1] = white(Rank::Bishop);
+        back_rank[2 * (number / 4 % 4)] = white(Rank::Bishop);
+
+        let mut empty: Vec<usize> = (0..8).filter(|&column| back_rank[column].is_none()).collect();
+        back_rank[empty.remove(number / 16 % 6)] = white(Rank::Queen);
+
+        let (first_knight, second_knight) = CHESS960_KNIGHTS[number / 96];
+        back_rank[empty[first_knight]] = white(Rank::Knight);
+        back_rank[empty[second_knight]] = white(Rank::Knight);
+
+        // The king goes between the rooks on whatever is left
+        let rest: Vec<usize> = empty.into_iter().filter(|&column| back_rank[column].is_none()).collect();
+        back_rank[rest[0]] = white(Rank::Rook);
+        back_rank[rest[1]] = white(Rank::King);
+        back_rank[rest[2]] = white(Rank::Rook);
+
+        let mut board = Board::empty();
+        for (column, piece) in back_rank.iter().enumerate() {
+            let rank = piece.expect("Every square is filled").rank();
+            board.squares[column] = *piece;
+            board.squares[8 + column] = Some(Piece::pack(Side::White, Rank::Pawn));
+            board.squares[48 + column] = Some(Piece::pack(Side::Black, Rank::Pawn));
+            board.squares[56 + column] = Some(Piece::pack(Side::Black, rank));
         }
+
+        Some(board)
+    }
+
+    pub fn random_chess960() -> Board {
+        Board::chess960(rand::thread_rng().gen_range(0, 960)).unwrap()
     }
 
     pub fn empty() -> Board {
-        Board {
-            data: [
-                [None, None, None, None, None, None, None, None],
-                [None, None, None, None, None, None, None, None],
-                [None, None, None, None, None, None, None, None],
-                [None, None, None, None, None, None, None, None],
-                [None, None, None, None, None, None, None, None],
-                [None, None, None, None, None, None, None, None],
-                [None, None, None, None, None, None, None, None],
-                [None, None, None, None, None, None, None, None]
-            ]
+        Board::with_size(8, 8)
+    }
+
+    // An empty board with the given number of files and ranks.
+    pub fn with_size(width: usize, height: usize) -> Board {
+        Board { width, squares: vec![None; width * height] }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.squares.len() / self.width
+    }
+
+    pub fn contains(&self, coordinate: Coordinate) -> bool {
+        coordinate.row() < self.height() && coordinate.column() < self.width
+    }
+
+    pub fn check(&self, coordinate: &Coordinate) -> Result<(), InvalidCoordinate> {
+        if self.contains(*coordinate) {
+            Ok(())
+        } else {
+            Err(InvalidCoordinate::OffTheBoard { row: coordinate.row(), column: coordinate.column() })
         }
     }
 
+    // The rank each side's pieces start on.
+    pub fn home_row(&self, side: Side) -> usize {
+        match side {
+            Side::White => 0,
+            Side::Black => self.height() - 1,
+        }
+    }
+
+    // Squares off the board are empty.
     pub fn piece_at(&self, coordinate: Coordinate) -> &Option<Piece> {
-        &self.data[coordinate.row()][coordinate.column()]
+        if self.contains(coordinate) {
+            &self.squares[coordinate.row() * self.width + coordinate.column()]
+        } else {
+            &OFF_THE_BOARD
+        }
     }
 
     pub fn pieces_with_coordinates(&self) -> Vec<(Coordinate, Piece)> {
-        self.data.into_iter()
-                 .flat_map(|x| x)
+        self.squares.iter()
                  .enumerate()
-                 .filter_map(|(index, piece)| match *piece {
-                     Some(p) => Some((Coordinate::new(index / 8, index % 8), p)),
-                     None => None
-                 })
+                 .filter_map(|(index, piece)| piece.map(|p| (Coordinate::new(index / self.width, index % self.width), p)))
                  .collect()
     }
 
-    pub fn rows(&self) -> &[[Option<Piece>; 8]] {
-        &self.data
+    // Every square on the board, a rank at a time from the first rank.
+    pub fn coordinates(&self) -> Vec<Coordinate> {
+        (0..self.squares.len()).map(|index| Coordinate::new(index / self.width, index % self.width)).collect()
+    }
+
+    pub fn rows<'a>(&'a self) -> Chunks<'a, Option<Piece>> {
+        self.squares.chunks(self.width)
     }
 
     pub fn is_empty(&self, coordinate: Coordinate) -> bool {
@@ -100,14 +143,26 @@ impl Board {
         }
     }
 
-    pub fn update(&mut self, coordinate: &Coordinate, piece: Option<Piece>) -> Result<(), String> {
-        coordinate.check()?;
+    pub fn update(&mut self, coordinate: &Coordinate, piece: Option<Piece>) -> Result<(), InvalidCoordinate> {
+        self.check(coordinate)?;
 
-        self.data[coordinate.row()][coordinate.column()] = piece;
+        self.squares[coordinate.row() * self.width + coordinate.column()] = piece;
 
         Ok(())
     }
 
+    // The outermost rook on the side's home rank, towards the h file from
+    // the king for short castling or the a file for long castling. This is
+    // the rook KQkq castling rights in FEN refer to.
+    pub fn castling_rook(&self, side: Side, short: bool) -> Option<Coordinate> {
+        let row = self.home_row(side);
+        let king = self.find_pieces(Piece::pack(side, Rank::King)).into_iter().find(|king| king.row() == row)?;
+        let rook = Some(Piece::pack(side, Rank::Rook));
+
+        let columns: Vec<usize> = if short { (king.column() + 1..self.width).rev().collect() } else { (0..king.column()).collect() };
+        columns.into_iter().map(|column| Coordinate::new(row, column)).find(|coordinate| *self.piece_at(*coordinate) == rook)
+    }
+
     pub fn find_pieces(&self, target_piece: Piece) -> Vec<Coordinate> {
         self.pieces_with_coordinates()
             .into_iter()
@@ -128,47 +183,35 @@ impl Coordinate {
         Coordinate { row, column }
     }
 
-    pub fn new_safe(row: usize, column: usize) -> Result<Coordinate, String> {
-        // NOTE: usize cannot be negative
-        if row <= 7 && column <= 7 {
-            Ok(Coordinate::new(row, column))
-        } else {
-            Err("Invalid coordinates".to_string())
-        }
+    pub fn new_safe(row: usize, column: usize) -> Result<Coordinate, InvalidCoordinate> {
+        let coordinate = Coordinate::new(row, column);
+        coordinate.check()?;
+        Ok(coordinate)
     }
 
-    pub fn from_human(string: String) -> Result<Coordinate, String> {
-        if string.len() != 2 {
-            return Err("Coordinate string must be 2 characters long.".to_string())
-        }
-
-        let mut chars = string.chars();
-
-        let column = match chars.next().unwrap().to_string().as_ref() {
-            "a" => 0,
-            "b" => 1,
-            "c" => 2,
-            "d" => 3,
-            "e" => 4,
-            "f" => 5,
-            "g" => 6,
-            "h" => 7,
-            _ => return Err(format!("Bad coordinate {}", string)),
+    // Reads squares like "e4", or "j10" on bigger boards.
+    pub fn from_human(string: String) -> Result<Coordinate, InvalidCoordinate> {
+        let file = match string.chars().next() {
+            Some(letter) if letter >= 'a' && (letter as usize) < 'a' as usize + MAX_SIZE => letter,
+            _ => return Err(InvalidCoordinate::Unreadable(string)),
         };
 
-        let row = match chars.next().unwrap().to_string().parse::<usize>() {
-            Ok(r) => r - 1,
-            Err(e) => return Err(e.to_string()),
+        let number = &string[1..];
+        let row = match number.parse::<usize>() {
+            Ok(rank) if rank != 0 && rank <= MAX_SIZE && !number.starts_with('0') && !number.starts_with('+') => rank - 1,
+            _ => return Err(InvalidCoordinate::Unreadable(string)),
         };
 
-        Ok(Coordinate { row, column })
+        Ok(Coordinate { row, column: file as usize - 'a' as usize })
     }
 
-    pub fn check(&self) -> Result<(), String> {
-        if self.row <= 7 && self.column <= 7 {
+    // Whether the coordinate could be on a board at all. Board::check says
+    // whether it is on a particular one.
+    pub fn check(&self) -> Result<(), InvalidCoordinate> {
+        if self.row < MAX_SIZE && self.column < MAX_SIZE {
             Ok(())
         } else {
-            Err(format!("Invalid board coordinates: {}, {}", self.row, self.column))
+            Err(InvalidCoordinate::OffTheBoard { row: self.row, column: self.column })
         }
     }
 
@@ -181,21 +224,22 @@ impl Coordinate {
     }
 
     pub fn to_human(&self) -> String {
-        let col = match self.column {
-            0 => "a",
-            1 => "b",
-            2 => "c",
-            3 => "d",
-            4 => "e",
-            5 => "f",
-            6 => "g",
-            7 => "h",
-            _ => panic!(format!("Bad column index in coordinates: {}", self.column)),
-        };
+        if self.column >= MAX_SIZE {
+            panic!(format!("Bad column index in coordinates: {}", self.column));
+        }
+
+        format!("{}{}", (b'a' + self.column as u8) as char, self.row + 1)
+    }
+}
 
-        format!("{}{}", col, self.row + 1)
+impl FromStr for Coordinate {
+    type Err = InvalidCoordinate;
+
+    fn from_str(text: &str) -> Result<Coordinate, InvalidCoordinate> {
+        Coordinate::from_human(text.to_string())
     }
 }
+
 impl fmt::Debug for Coordinate {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Coordinate {{ row: {}, column: {}, (notation: {}) }}", self.row(), self.column(), self.to_human())
@@ -211,30 +255,27 @@ fn format_pos(pos: &Option<Piece>) -> String {
 
 impl fmt::Debug for Board {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "\n   ---------------------------------------")?;
-        write!(f, "\n8 | {} | {} | {} | {} | {} | {} | {} | {} |", format_pos(&self.data[7][0]), format_pos(&self.data[7][1]), format_pos(&self.data[7][2]), format_pos(&self.data[7][3]), format_pos(&self.data[7][4]), format_pos(&self.data[7][5]), format_pos(&self.data[7][6]), format_pos(&self.data[7][7]))?;
-        write!(f, "\n  |---------------------------------------|")?;
-        write!(f, "\n7 | {} | {} | {} | {} | {} | {} | {} | {} |", format_pos(&self.data[6][0]), format_pos(&self.data[6][1]), format_pos(&self.data[6][2]), format_pos(&self.data[6][3]), format_pos(&self.data[6][4]), format_pos(&self.data[6][5]), format_pos(&self.data[6][6]), format_pos(&self.data[6][7]))?;
-        write!(f, "\n  |---------------------------------------|")?;
-        write!(f, "\n6 | {} | {} | {} | {} | {} | {} | {} | {} |", format_pos(&self.data[5][0]), format_pos(&self.data[5][1]), format_pos(&self.data[5][2]), format_pos(&self.data[5][3]), format_pos(&self.data[5][4]), format_pos(&self.data[5][5]), format_pos(&self.data[5][6]), format_pos(&self.data[5][7]))?;
-        write!(f, "\n  |---------------------------------------|")?;
-        write!(f, "\n5 | {} | {} | {} | {} | {} | {} | {} | {} |", format_pos(&self.data[4][0]), format_pos(&self.data[4][1]), format_pos(&self.data[4][2]), format_pos(&self.data[4][3]), format_pos(&self.data[4][4]), format_pos(&self.data[4][5]), format_pos(&self.data[4][6]), format_pos(&self.data[4][7]))?;
-        write!(f, "\n  |---------------------------------------|")?;
-        write!(f, "\n4 | {} | {} | {} | {} | {} | {} | {} | {} |", format_pos(&self.data[3][0]), format_pos(&self.data[3][1]), format_pos(&self.data[3][2]), format_pos(&self.data[3][3]), format_pos(&self.data[3][4]), format_pos(&self.data[3][5]), format_pos(&self.data[3][6]), format_pos(&self.data[3][7]))?;
-        write!(f, "\n  |---------------------------------------|")?;
-        write!(f, "\n3 | {} | {} | {} | {} | {} | {} | {} | {} |", format_pos(&self.data[2][0]), format_pos(&self.data[2][1]), format_pos(&self.data[2][2]), format_pos(&self.data[2][3]), format_pos(&self.data[2][4]), format_pos(&self.data[2][5]), format_pos(&self.data[2][6]), format_pos(&self.data[2][7]))?;
-        write!(f, "\n  |---------------------------------------|")?;
-        write!(f, "\n2 | {} | {} | {} | {} | {} | {} | {} | {} |", format_pos(&self.data[1][0]), format_pos(&self.data[1][1]), format_pos(&self.data[1][2]), format_pos(&self.data[1][3]), format_pos(&self.data[1][4]), format_pos(&self.data[1][5]), format_pos(&self.data[1][6]), format_pos(&self.data[1][7]))?;
-        write!(f, "\n  |---------------------------------------|")?;
-        write!(f, "\n1 | {} | {} | {} | {} | {} | {} | {} | {} |", format_pos(&self.data[0][0]), format_pos(&self.data[0][1]), format_pos(&self.data[0][2]), format_pos(&self.data[0][3]), format_pos(&self.data[0][4]), format_pos(&self.data[0][5]), format_pos(&self.data[0][6]), format_pos(&self.data[0][7]))?;
-        write!(f, "\n   ---------------------------------------")?;
-        write!(f, "\n     A    B    C    D    E    F    G    H\n")
+        let line = "-".repeat(5 * self.width - 1);
+        write!(f, "\n   {}", line)?;
+        for (index, row) in self.rows().enumerate().rev() {
+            write!(f, "\n{:<2}|", index + 1)?;
+            for square in row {
+                write!(f, " {} |", format_pos(square))?;
+            }
+            if index > 0 {
+                write!(f, "\n  |{}|", line)?;
+            }
+        }
+        write!(f, "\n   {}", line)?;
+        let files: String = (0..self.width).map(|column| format!("    {}", (b'A' + column as u8) as char)).collect();
+        write!(f, "\n {}\n", files)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use fen;
 
     macro_rules! coord {
         ($x:expr) => { Coordinate::from_human($x.to_string()).unwrap() }
@@ -257,6 +298,23 @@ mod tests {
         assert_eq!(coord.column(), 7, "Incorrect column index parserd");
     }
 
+    #[test]
+    fn reads_squares_on_bigger_boards() {
+        assert_eq!(coord!("j10"), Coordinate::new(9, 9));
+        assert_eq!(Coordinate::new(9, 9).to_human(), "j10");
+        assert_eq!(coord!("p16"), Coordinate::new(15, 15));
+        assert!(Coordinate::from_human("q1".to_string()).is_err());
+        assert!(Coordinate::from_human("a17".to_string()).is_err());
+        assert!(Coordinate::from_human("a01".to_string()).is_err());
+
+        let board = Board::with_size(10, 8);
+        assert!(board.contains(coord!("j8")));
+        assert!(!board.contains(coord!("j9")));
+        assert!(!board.contains(coord!("k1")));
+        assert_eq!(*board.piece_at(coord!("k1")), None);
+        assert_eq!(board.home_row(Side::Black), 7);
+    }
+
     #[test]
     fn pieces_are_at_correct_starting_position() {
         let board = Board::default();
@@ -315,4 +373,36 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn numbers_chess960_positions_as_usual() {
+        let back_rank = |number| fen::write(&Board::chess960(number).unwrap(), Side::White, &[], 0, 1)[0..8].to_string();
+
+        assert_eq!(back_rank(0), "bbqnnrkr");
+        assert_eq!(back_rank(1), "bqnbnrkr");
+        assert_eq!(back_rank(959), "rkrnnqbb");
+        assert!(Board::chess960(518).unwrap() == Board::default());
+        assert!(Board::chess960(960).is_none());
+    }
+
+    #[test]
+    fn chess960_positions_are_all_different_and_follow_the_rules() {
+        let mut back_ranks = vec![];
+
+        for number in 0..960 {
+            let board = Board::chess960(number).unwrap();
+            let pieces = |rank| board.find_pieces(Piece::pack(Side::White, rank));
+
+            let bishops = pieces(Rank::Bishop);
+            assert!(bishops[0].column() % 2 != bishops[1].column() % 2, "Position {}", number);
+            let (rooks, king) = (pieces(Rank::Rook), pieces(Rank::King)[0]);
+            assert!(rooks[0].column() < king.column() && king.column() < rooks[1].column(), "Position {}", number);
+
+            back_ranks.push(board.rows().next().unwrap().to_vec());
+        }
+
+        back_ranks.sort_by_key(|rank| format!("{:?}", rank));
+        back_ranks.dedup();
+        assert_eq!(back_ranks.len(), 960);
+    }
 }
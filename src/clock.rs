@@ -0,0 +1,502 @@
+use std::cmp;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use Side;
+
+// What a player gets back for each move they make.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Increment {
+    None,
+    // Added after every move
+    Fischer(Duration),
+    // Whatever was used of it is given back after every move
+    Bronstein(Duration),
+}
+
+// One period of a time control: `moves` moves in `time`, or the rest of the
+// game when there is no move count.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Stage {
+    pub moves: Option<u32>,
+    pub time: Duration,
+    pub increment: Increment,
+}
+
+// Written like "5+3" (5 minutes plus 3 seconds a move), "5d3" (3 seconds of
+// Bronstein delay) or "40/90+30:30+30" (40 moves in 90 minutes, then 30
+// minutes for the rest, with 30 seconds a move throughout).
+#[derive(PartialEq, Clone, Debug)]
+pub struct TimeControl {
+    stages: Vec<Stage>,
+}
+
+impl TimeControl {
+    pub fn new(stages: Vec<Stage>) -> Result<TimeControl, String> {
+        if stages.is_empty() {
+            return Err("A time control needs at least one stage".to_string())
+        }
+        if stages[..stages.len() - 1].iter().any(|stage| stage.moves.is_none()) {
+            return Err("Only the last stage can last for the rest of the game".to_string())
+        }
+        Ok(TimeControl { stages })
+    }
+
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    // The stage a player's nth move (counting from 0) is made in. A last
+    // stage with a move count repeats.
+    fn stage_for_move(&self, move_number: u32) -> &Stage {
+        let mut moves_so_far = 0;
+        for stage in &self.stages {
+            match stage.moves {
+                Some(moves) if move_number < moves_so_far + moves => return stage,
+                Some(moves) => moves_so_far += moves,
+                None => return stage,
+            }
+        }
+        &self.stages[self.stages.len() - 1]
+    }
+
+    // The time added once a player has made this many moves, if a stage
+    // just ended.
+    fn time_added_after(&self, moves_made: u32) -> Option<Duration> {
+        let mut moves_so_far = 0;
+        for (index, stage) in self.stages.iter().enumerate() {
+            let moves = stage.moves?;
+            moves_so_far += moves;
+            if moves_made == moves_so_far {
+                return Some(self.stages.get(index + 1).unwrap_or(stage).time)
+            }
+            if moves_made < moves_so_far {
+                return None
+            }
+        }
+
+        // Past every stage, the last one repeats
+        let last = &self.stages[self.stages.len() - 1];
+        let moves = last.moves?;
+        let into_stage = (moves_made - moves_so_far) % moves;
+        if into_stage == 0 { Some(last.time) } else { None }
+    }
+}
+
+impl FromStr for TimeControl {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<TimeControl, String> {
+        let stages = text.split(':').map(parse_stage).collect::<Result<Vec<Stage>, String>>()?;
+        TimeControl::new(stages).map_err(|e| format!("Invalid time control {}: {}", text, e))
+    }
+}
+
+fn parse_stage(text: &str) -> Result<Stage, String> {
+    let invalid = || format!("Invalid time control {}, expected something like 5+3, 5d3 or 40/90+30", text);
+
+    let (moves, rest) = match text.find('/') {
+        Some(index) => (Some(text[..index].parse::<u32>().map_err(|_| invalid())?), &text[index + 1..]),
+        None => (None, text),
+    };
+    if moves == Some(0) {
+        return Err(invalid())
+    }
+
+    let (minutes, increment) = match rest.find(&['+', 'd'][..]) {
+        Some(index) => {
+            let seconds = Duration::from_secs(rest[index + 1..].parse::<u64>().map_err(|_| invalid())?);
+            let increment = if &rest[index..index + 1] == "+" { Increment::Fischer(seconds) } else { Increment::Bronstein(seconds) };
+            (&rest[..index], increment)
+        },
+        None => (rest, Increment::None),
+    };
+
+    let minutes = minutes.parse::<f64>().map_err(|_| invalid())?;
+    if minutes.is_nan() || minutes <= 0.0 {
+        return Err(invalid())
+    }
+    let time = Duration::from_millis((minutes * 60_000.0) as u64);
+
+    Ok(Stage { moves, time, increment })
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, stage) in self.stages.iter().enumerate() {
+            if index > 0 {
+                write!(f, ":")?;
+            }
+            if let Some(moves) = stage.moves {
+                write!(f, "{}/", moves)?;
+            }
+            write!(f, "{}", millis(stage.time) as f64 / 60_000.0)?;
+            match stage.increment {
+                Increment::None => {},
+                Increment::Fischer(seconds) => write!(f, "+{}", seconds.as_secs())?,
+                Increment::Bronstein(seconds) => write!(f, "d{}", seconds.as_secs())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// A chess clock for both players. Only one side's time runs at once; the
+// times passed in are when things happened, so the clock never looks at
+// the real time itself.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Clock {
+    control: TimeControl,
+    remaining: [Duration; 2],
+    moves_made: [u32; 2],
+    running: Option<(Side, Instant)>,
+    flagged: Option<Side>,
+    // What each move earned in increments and new stages, for takebacks
+    credits: Vec<(Side, Duration)>,
+    // Credits of moves taken back, most recently taken back last
+    taken_back: Vec<(Side, Duration)>,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Clock {
+        let time = control.stages[0].time;
+
+        Clock {
+            control,
+            remaining: [time, time],
+            moves_made: [0, 0],
+            running: None,
+            flagged: None,
+            credits: vec![],
+            taken_back: vec![],
+        }
+    }
+
+    // A clock part way through a game, stopped until it is started again.
+    // A side with no time left has already lost on time.
+    pub fn resume(control: TimeControl, remaining: [Duration; 2], moves_made: [u32; 2]) -> Clock {
+        let flagged = if remaining[0] == Duration::from_secs(0) {
+            Some(Side::White)
+        } else if remaining[1] == Duration::from_secs(0) {
+            Some(Side::Black)
+        } else {
+            None
+        };
+
+        Clock { control, remaining, moves_made, running: None, flagged, credits: vec![], taken_back: vec![] }
+    }
+
+    pub fn control(&self) -> &TimeControl {
+        &self.control
+    }
+
+    pub fn running(&self) -> Option<Side> {
+        self.running.map(|(side, _)| side)
+    }
+
+    // The side whose time ran out, if any.
+    pub fn flagged(&self) -> Option<Side> {
+        self.flagged
+    }
+
+    pub fn remaining(&self, side: Side, now: Instant) -> Duration {
+        let remaining = self.remaining[index(side)];
+        match self.running {
+            Some((running, since)) if running == side => remaining.checked_sub(now - since).unwrap_or_default(),
+            _ => remaining,
+        }
+    }
+
+    pub fn moves_made(&self, side: Side) -> u32 {
+        self.moves_made[index(side)]
+    }
+
+    pub fn start(&mut self, side: Side, now: Instant) {
+        self.stop(now);
+        if self.flagged.is_none() {
+            self.running = Some((side, now));
+        }
+    }
+
+    // Charges the running side for its time so far and stops the clock.
+    pub fn stop(&mut self, now: Instant) {
+        self.charge(now);
+        self.running = None;
+    }
+
+    // The running side has made a move: it gets its increment and any time
+    // from starting a new stage, and the other side's time starts.
+    pub fn press(&mut self, now: Instant) {
+        let side = match self.running {
+            Some((side, _)) => side,
+            None => return,
+        };
+
+        let used = self.charge(now);
+        self.running = None;
+        if self.flagged.is_some() {
+            return
+        }
+
+        let credit = self.credit(self.moves_made[index(side)], used);
+        self.remaining[index(side)] += credit;
+        self.moves_made[index(side)] += 1;
+        self.credits.push((side, credit));
+        self.taken_back.clear();
+
+        self.running = Some((!side, now));
+    }
+
+    // Takes back the side's last move, which no longer counts towards the
+    // time control, and takes off what it earned, down to no time left.
+    // Taking back a move never flags, and the time spent on it isn't given
+    // back.
+    pub fn take_back(&mut self, side: Side, now: Instant) {
+        self.stop(now);
+        if self.moves_made[index(side)] == 0 {
+            return
+        }
+
+        self.moves_made[index(side)] -= 1;
+        // A resumed clock doesn't know what Bronstein delay gave back
+        let credit = pop_credit(&mut self.credits, side)
+            .unwrap_or_else(|| self.credit(self.moves_made[index(side)], Duration::from_secs(0)));
+        // Only what was actually taken off comes back on a replay
+        let taken = credit.min(self.remaining[index(side)]);
+        self.remaining[index(side)] -= taken;
+        self.taken_back.push((side, taken));
+    }
+
+    // Plays the side's last taken back move again, earning what it did the
+    // first time.
+    pub fn replay(&mut self, side: Side, now: Instant) {
+        self.stop(now);
+        let credit = pop_credit(&mut self.taken_back, side)
+            .unwrap_or_else(|| self.credit(self.moves_made[index(side)], Duration::from_secs(0)));
+        self.remaining[index(side)] += credit;
+        self.moves_made[index(side)] += 1;
+        self.credits.push((side, credit));
+    }
+
+    // Notices the running side's time having run out. Returns whether it
+    // just happened.
+    pub fn check_flag(&mut self, now: Instant) -> bool {
+        match self.running {
+            Some((side, _)) if self.remaining(side, now) == Duration::from_secs(0) => {
+                self.stop(now);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    // What a side's nth move (counting from 0) earns: its increment, and
+    // the next stage's time if it ends a stage.
+    fn credit(&self, move_number: u32, used: Duration) -> Duration {
+        let increment = match self.control.stage_for_move(move_number).increment {
+            Increment::None => Duration::from_secs(0),
+            Increment::Fischer(increment) => increment,
+            Increment::Bronstein(delay) => cmp::min(used, delay),
+        };
+        increment + self.control.time_added_after(move_number + 1).unwrap_or_default()
+    }
+
+    // Takes the running side's time off its remaining time, returning how
+    // long it had been running.
+    fn charge(&mut self, now: Instant) -> Duration {
+        let (side, since) = match self.running {
+            Some(running) => running,
+            None => return Duration::from_secs(0),
+        };
+
+        let used = now - since;
+        let remaining = &mut self.remaining[index(side)];
+        match remaining.checked_sub(used) {
+            Some(left) if left > Duration::from_secs(0) => *remaining = left,
+            _ => {
+                *remaining = Duration::from_secs(0);
+                self.flagged = Some(side);
+            },
+        }
+
+        self.running = Some((side, now));
+        used
+    }
+}
+
+// Minutes and seconds, with tenths once it gets tight.
+pub fn format_clock(time: Duration) -> String {
+    let millis = millis(time);
+    let seconds = millis / 1000;
+
+    if seconds < 10 {
+        format!("0:{:02}.{}", seconds, millis % 1000 / 100)
+    } else if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds % 3600 / 60, seconds % 60)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+fn millis(time: Duration) -> u64 {
+    time.as_secs() * 1000 + time.subsec_millis() as u64
+}
+
+fn pop_credit(credits: &mut Vec<(Side, Duration)>, side: Side) -> Option<Duration> {
+    match credits.last() {
+        Some(&(credit_side, _)) if credit_side == side => credits.pop().map(|(_, credit)| credit),
+        _ => None,
+    }
+}
+
+fn index(side: Side) -> usize {
+    match side {
+        Side::White => 0,
+        Side::Black => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn parses_time_controls() {
+        let blitz: TimeControl = "5+3".parse().unwrap();
+        assert_eq!(blitz.stages(), &[Stage { moves: None, time: secs(300), increment: Increment::Fischer(secs(3)) }]);
+
+        let delay: TimeControl = "5d2".parse().unwrap();
+        assert_eq!(delay.stages()[0].increment, Increment::Bronstein(secs(2)));
+
+        let classical: TimeControl = "40/90+30:30+30".parse().unwrap();
+        assert_eq!(classical.stages(), &[
+            Stage { moves: Some(40), time: secs(5400), increment: Increment::Fischer(secs(30)) },
+            Stage { moves: None, time: secs(1800), increment: Increment::Fischer(secs(30)) },
+        ]);
+        assert_eq!(classical.to_string(), "40/90+30:30+30");
+
+        assert!("".parse::<TimeControl>().is_err());
+        assert!("5+".parse::<TimeControl>().is_err());
+        assert!("0+3".parse::<TimeControl>().is_err());
+        assert!("90:40/30".parse::<TimeControl>().is_err());
+    }
+
+    #[test]
+    fn runs_down_for_the_side_to_move() {
+        let start = Instant::now();
+        let mut clock = Clock::new("5".parse().unwrap());
+        clock.start(Side::White, start);
+
+        clock.press(start + secs(10));
+        assert_eq!(clock.remaining(Side::White, start + secs(30)), secs(290));
+        assert_eq!(clock.remaining(Side::Black, start + secs(30)), secs(280));
+        assert_eq!(clock.running(), Some(Side::Black));
+    }
+
+    #[test]
+    fn adds_fischer_increments_and_bronstein_delay() {
+        let start = Instant::now();
+
+        let mut fischer = Clock::new("1+5".parse().unwrap());
+        fischer.start(Side::White, start);
+        fischer.press(start + secs(2));
+        assert_eq!(fischer.remaining(Side::White, start + secs(2)), secs(63));
+
+        let mut bronstein = Clock::new("1d5".parse().unwrap());
+        bronstein.start(Side::White, start);
+        bronstein.press(start + secs(2));
+        bronstein.press(start + secs(12));
+        assert_eq!(bronstein.remaining(Side::White, start + secs(12)), secs(60));
+        assert_eq!(bronstein.remaining(Side::Black, start + secs(12)), secs(55));
+    }
+
+    #[test]
+    fn adds_time_for_each_new_stage() {
+        let start = Instant::now();
+        let mut clock = Clock::new("2/1:1".parse().unwrap());
+        clock.start(Side::White, start);
+
+        for _ in 0..4 {
+            clock.press(start);
+        }
+        assert_eq!(clock.remaining(Side::White, start), secs(120));
+        assert_eq!(clock.remaining(Side::Black, start), secs(120));
+    }
+
+    #[test]
+    fn takes_back_what_a_move_earned() {
+        let start = Instant::now();
+        let mut clock = Clock::new("1/1d5:1".parse().unwrap());
+        clock.start(Side::White, start);
+
+        clock.press(start + secs(2));
+        assert_eq!(clock.remaining(Side::White, start + secs(2)), secs(120));
+
+        clock.take_back(Side::White, start + secs(5));
+        assert_eq!(clock.moves_made(Side::White), 0);
+        assert_eq!(clock.remaining(Side::White, start + secs(5)), secs(58));
+
+        clock.replay(Side::White, start + secs(5));
+        assert_eq!(clock.moves_made(Side::White), 1);
+        assert_eq!(clock.remaining(Side::White, start + secs(5)), secs(120));
+
+        // Resumed clocks take back the increment and stage time they can work out
+        let mut resumed = Clock::resume("1/1+5:1".parse().unwrap(), [secs(125), secs(60)], [1, 0]);
+        resumed.take_back(Side::White, start);
+        assert_eq!(resumed.remaining(Side::White, start), secs(60));
+        assert_eq!(resumed.moves_made(Side::White), 0);
+    }
+
+    #[test]
+    fn takes_back_no_more_than_is_left() {
+        let start = Instant::now();
+        let mut clock = Clock::resume("1/1+5:1".parse().unwrap(), [secs(30), secs(60)], [1, 0]);
+
+        clock.take_back(Side::White, start);
+        assert_eq!(clock.remaining(Side::White, start), secs(0));
+        assert_eq!(clock.flagged(), None);
+
+        clock.replay(Side::White, start);
+        assert_eq!(clock.remaining(Side::White, start), secs(30));
+    }
+
+    #[test]
+    fn flags_the_side_that_runs_out() {
+        let start = Instant::now();
+        let mut clock = Clock::new("1".parse().unwrap());
+        clock.start(Side::White, start);
+
+        assert!(!clock.check_flag(start + secs(59)));
+        assert!(clock.check_flag(start + secs(61)));
+        assert_eq!(clock.flagged(), Some(Side::White));
+        assert_eq!(clock.running(), None);
+        assert_eq!(clock.remaining(Side::White, start + secs(61)), secs(0));
+    }
+
+    #[test]
+    fn resumes_with_the_time_that_was_left() {
+        let start = Instant::now();
+        let mut clock = Clock::resume("2/1:1".parse().unwrap(), [secs(30), secs(45)], [1, 1]);
+        assert_eq!(clock.running(), None);
+
+        clock.start(Side::White, start);
+        clock.press(start + secs(10));
+        assert_eq!(clock.remaining(Side::White, start + secs(10)), secs(80));
+        assert_eq!(clock.moves_made(Side::White), 2);
+
+        let flagged = Clock::resume("1".parse().unwrap(), [secs(10), secs(0)], [0, 0]);
+        assert_eq!(flagged.flagged(), Some(Side::Black));
+    }
+
+    #[test]
+    fn formats_remaining_time() {
+        assert_eq!(format_clock(secs(300)), "5:00");
+        assert_eq!(format_clock(secs(5400)), "1:30:00");
+        assert_eq!(format_clock(Duration::from_millis(9450)), "0:09.4");
+    }
+}
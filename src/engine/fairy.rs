@@ -0,0 +1,112 @@
+use game::{GameState};
+use action::Action;
+use board::{Coordinate};
+use engine::{find_moves_in_direction, find_opposing_piece_in_direction, Mover};
+use piece::Rank;
+
+// How a piece moves, built from Mover steps of so many squares north and
+// east. It slides along each of its lines until something is in the way,
+// and jumps straight to each of its leaps over anything in between.
+pub struct Movement {
+    pub slides: &'static [(i8, i8)],
+    pub leaps: &'static [(i8, i8)],
+}
+
+const LINES: &[(i8, i8)] = &[(1, 0), (0, 1), (-1, 0), (0, -1)];
+const DIAGONALS: &[(i8, i8)] = &[(1, 1), (-1, 1), (-1, -1), (1, -1)];
+const LINES_AND_DIAGONALS: &[(i8, i8)] = &[(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+const KNIGHT_LEAPS: &[(i8, i8)] = &[(2, -1), (2, 1), (1, 2), (-1, 2), (-2, 1), (-2, -1), (-1, -2), (1, -2)];
+
+// The movement of each fairy piece. The usual pieces have their own modules.
+pub fn movement(rank: &Rank) -> Option<Movement> {
+    match *rank {
+        Rank::Archbishop => Some(Movement { slides: DIAGONALS, leaps: KNIGHT_LEAPS }),
+        Rank::Chancellor => Some(Movement { slides: LINES, leaps: KNIGHT_LEAPS }),
+        Rank::Amazon => Some(Movement { slides: LINES_AND_DIAGONALS, leaps: KNIGHT_LEAPS }),
+        _ => None,
+    }
+}
+
+pub fn possible_actions(from: &Coordinate, state: &GameState) -> Vec<Action> {
+    let piece = state.piece_at(*from).unwrap();
+    let movement = match movement(&piece.rank()) {
+        Some(movement) => movement,
+        None => return vec![],
+    };
+    let side = piece.side();
+    let board = state.board();
+
+    let mut moves = vec![];
+    let mut captures = vec![];
+    for &(north, east) in movement.slides {
+        moves.append(&mut find_moves_in_direction(from, side, board, |mover| mover.step(north, east)));
+        captures.extend(find_opposing_piece_in_direction(from, side, board, |mover| mover.step(north, east)));
+    }
+
+    for &(north, east) in movement.leaps {
+        if let Ok(to) = Mover::new(side).move_to(from).step(north, east).make(board) {
+            match *board.piece_at(to) {
+                None => moves.push(to),
+                Some(target) if target.side() != side => captures.push(to),
+                Some(_) => {},
+            }
+        }
+    }
+
+    let mut actions: Vec<Action> = moves
+        .into_iter()
+        .map(|to| Action::MovePiece(piece, *from, to))
+        .collect();
+    actions.extend(captures.into_iter().map(|to| Action::Capture(piece, board.piece_at(to).unwrap(), *from, to)));
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Side;
+    use board::Board;
+    use piece::Piece;
+
+    macro_rules! coord {
+        ($x:expr) => { Coordinate::from_human($x.to_string()).unwrap() }
+    }
+
+    fn targets(board: Board, from: &str) -> Vec<String> {
+        let state = GameState::with_board(board);
+        let mut squares: Vec<String> = possible_actions(&coord!(from), &state)
+            .iter()
+            .map(|action| ::action::to_coordinate_for(action).to_human())
+            .collect();
+        squares.sort();
+        squares
+    }
+
+    #[test]
+    fn moves_as_a_bishop_or_a_knight() {
+        let mut board = Board::empty();
+        board.update(&coord!("a1"), Some(Piece::pack(Side::White, Rank::Archbishop))).unwrap();
+        board.update(&coord!("c3"), Some(Piece::pack(Side::Black, Rank::Pawn))).unwrap();
+        board.update(&coord!("b3"), Some(Piece::pack(Side::White, Rank::Pawn))).unwrap();
+
+        assert_eq!(targets(board, "a1"), vec!["b2", "c2", "c3"]);
+    }
+
+    #[test]
+    fn moves_as_a_rook_or_a_knight_on_a_wide_board() {
+        let mut board = Board::with_size(10, 8);
+        board.update(&coord!("j1"), Some(Piece::pack(Side::White, Rank::Chancellor))).unwrap();
+        board.update(&coord!("j3"), Some(Piece::pack(Side::White, Rank::Pawn))).unwrap();
+        board.update(&coord!("f1"), Some(Piece::pack(Side::Black, Rank::Knight))).unwrap();
+
+        assert_eq!(targets(board, "j1"), vec!["f1", "g1", "h1", "h2", "i1", "i3", "j2"]);
+    }
+
+    #[test]
+    fn moves_as_a_queen_or_a_knight() {
+        let mut board = Board::empty();
+        board.update(&coord!("d4"), Some(Piece::pack(Side::White, Rank::Amazon))).unwrap();
+
+        assert_eq!(targets(board, "d4").len(), 27 + 8);
+    }
+}
@@ -1,7 +1,8 @@
 use game::{GameState};
-use action::Action;
-use board::{Coordinate};
-use engine::{Mover};
+use action::{Action, castled_rook_coordinate};
+use board::Coordinate;
+use engine::{self, Mover};
+use piece::{Piece, Rank};
 
 pub fn possible_actions(from: &Coordinate, state: &GameState) -> Vec<Action> {
     let mut actions = vec![];
@@ -15,21 +16,21 @@ pub fn possible_moves(from: &Coordinate, state: &GameState) -> Vec<Action> {
     let side = state.piece_at(*from).unwrap().side();
     let mut moves = vec![];
     // North
-    moves.push(Mover::new(side).move_to(from).north().make());
+    moves.push(Mover::new(side).move_to(from).north().make(state.board()));
     // North East
-    moves.push(Mover::new(side).move_to(from).north().east().make());
+    moves.push(Mover::new(side).move_to(from).north().east().make(state.board()));
     // East
-    moves.push(Mover::new(side).move_to(from).east().make());
+    moves.push(Mover::new(side).move_to(from).east().make(state.board()));
     // South East
-    moves.push(Mover::new(side).move_to(from).south().east().make());
+    moves.push(Mover::new(side).move_to(from).south().east().make(state.board()));
     // South
-    moves.push(Mover::new(side).move_to(from).south().make());
+    moves.push(Mover::new(side).move_to(from).south().make(state.board()));
     // South West
-    moves.push(Mover::new(side).move_to(from).south().west().make());
+    moves.push(Mover::new(side).move_to(from).south().west().make(state.board()));
     // West
-    moves.push(Mover::new(side).move_to(from).west().make());
+    moves.push(Mover::new(side).move_to(from).west().make(state.board()));
     // North West
-    moves.push(Mover::new(side).move_to(from).north().west().make());
+    moves.push(Mover::new(side).move_to(from).north().west().make(state.board()));
 
     moves
     .into_iter()
@@ -45,21 +46,21 @@ pub fn possible_captures(from: &Coordinate, state: &GameState) -> Vec<Action> {
     let side = state.piece_at(*from).unwrap().side();
     let mut moves = vec![];
     // North
-    moves.push(Mover::new(side).move_to(from).north().make());
+    moves.push(Mover::new(side).move_to(from).north().make(state.board()));
     // North East
-    moves.push(Mover::new(side).move_to(from).north().east().make());
+    moves.push(Mover::new(side).move_to(from).north().east().make(state.board()));
     // East
-    moves.push(Mover::new(side).move_to(from).east().make());
+    moves.push(Mover::new(side).move_to(from).east().make(state.board()));
     // South East
-    moves.push(Mover::new(side).move_to(from).south().east().make());
+    moves.push(Mover::new(side).move_to(from).south().east().make(state.board()));
     // South
-    moves.push(Mover::new(side).move_to(from).south().make());
+    moves.push(Mover::new(side).move_to(from).south().make(state.board()));
     // South West
-    moves.push(Mover::new(side).move_to(from).south().west().make());
+    moves.push(Mover::new(side).move_to(from).south().west().make(state.board()));
     // West
-    moves.push(Mover::new(side).move_to(from).west().make());
+    moves.push(Mover::new(side).move_to(from).west().make(state.board()));
     // North West
-    moves.push(Mover::new(side).move_to(from).north().west().make());
+    moves.push(Mover::new(side).move_to(from).north().west().make(state.board()));
 
     moves
     .into_iter()
@@ -72,12 +73,67 @@ pub fn possible_captures(from: &Coordinate, state: &GameState) -> Vec<Action> {
     .collect()
 }
 
+// Castling the Chess960 way, which covers the usual rules too: the king goes
+// to the g or c file and the rook to the f or d file. Every square either
+// of them crosses must be empty but for the two of them, and the king can't
+// castle out of, through or into check.
+pub fn possible_castles(from: &Coordinate, state: &GameState) -> Vec<Action> {
+    let king = state.piece_at(*from).unwrap();
+    let side = king.side();
+    let rook_piece = Some(Piece::pack(side, Rank::Rook));
+    if from.row() != state.board().home_row(side) {
+        return vec![]
+    }
+
+    state.castling_rights()
+        .into_iter()
+        .filter(|rook| rook.row() == from.row() && *state.piece_at(*rook) == rook_piece)
+        .filter_map(|rook| {
+            let to = Coordinate::new(from.row(), if rook.column() > from.column() { state.board().width() - 2 } else { 2 });
+
+            let mut without_them = state.board().clone();
+            without_them.update(from, None).unwrap();
+            without_them.update(&rook, None).unwrap();
+
+            let mut crossed = squares_between(from, &to);
+            crossed.append(&mut squares_between(&rook, &castled_rook_coordinate(&to)));
+            if !crossed.into_iter().all(|square| without_them.is_empty(square)) {
+                return None
+            }
+
+            // The squares the king starts on and passes are looked at as they
+            // are now, and the one it lands on as it will be after castling.
+            // The king may not move at all, so it could be both.
+            let attacked = |square: Coordinate, castled: bool| {
+                let mut board = state.board().clone();
+                board.update(from, None).unwrap();
+                if castled {
+                    board.update(&rook, None).unwrap();
+                    board.update(&castled_rook_coordinate(&to), rook_piece).unwrap();
+                }
+                board.update(&square, Some(king)).unwrap();
+                engine::is_in_check(&GameState::with_board(board).with_variant(state.variant()), side)
+            };
+            let king_is_safe = !attacked(*from, false) &&
+                squares_between(from, &to).into_iter().all(|square| square == to || !attacked(square, false)) &&
+                !attacked(to, true);
+
+            if king_is_safe { Some(Action::Castle(king, *from, to, rook)) } else { None }
+        })
+        .collect()
+}
+
+// The squares on a rank from one coordinate to the other, both included.
+fn squares_between(from: &Coordinate, to: &Coordinate) -> Vec<Coordinate> {
+    let (first, last) = if from.column() < to.column() { (from.column(), to.column()) } else { (to.column(), from.column()) };
+    (first..last + 1).map(|column| Coordinate::new(from.row(), column)).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use Side;
     use board::Board;
-    use piece::{Piece, Rank};
 
     macro_rules! coord {
         ($x:expr) => { Coordinate::from_human($x.to_string()).unwrap() }
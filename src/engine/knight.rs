@@ -1,8 +1,9 @@
 use game::{GameState};
 use action::Action;
-use board::{Coordinate};
+use board::{Board, Coordinate};
 use engine::Mover;
 use Side;
+use error::InvalidCoordinate;
 
 pub fn possible_actions(from: &Coordinate, state: &GameState) -> Vec<Action> {
     let mut actions = vec![];
@@ -14,7 +15,7 @@ pub fn possible_actions(from: &Coordinate, state: &GameState) -> Vec<Action> {
 
 fn possible_moves(from: &Coordinate, state: &GameState) -> Vec<Action> {
     let side = state.piece_at(*from).unwrap().side();
-    let moves = knight_moves(from, side);
+    let moves = knight_moves(from, side, state.board());
 
     moves
         .into_iter()
@@ -25,33 +26,33 @@ fn possible_moves(from: &Coordinate, state: &GameState) -> Vec<Action> {
 
 fn possible_captures(from: &Coordinate, state: &GameState) -> Vec<Action> {
     let side = state.piece_at(*from).unwrap().side();
-    let moves = knight_moves(from, side);
+    let moves = knight_moves(from, side, state.board());
 
     moves
         .into_iter()
         .filter(|c| match *state.piece_at(*c) {
-            Some(p) => p.side() != state.next_to_move(),
+            Some(p) => p.side() != side,
             None => false
         })
         .map(|c| Action::Capture(state.piece_at(*from).unwrap().clone(), state.piece_at(c).unwrap().clone(), from.clone(), c))
         .collect()
 }
 
-fn knight_moves(from: &Coordinate, side: Side) -> Vec<Coordinate> {
-    let mut moves: Vec<Result<Coordinate, String>> = Vec::new();
+fn knight_moves(from: &Coordinate, side: Side, board: &Board) -> Vec<Coordinate> {
+    let mut moves: Vec<Result<Coordinate, InvalidCoordinate>> = Vec::new();
 
     // North moves
-    moves.push(Mover::new(side).move_to(from).north().north().west().make());
-    moves.push(Mover::new(side).move_to(from).north().north().east().make());
+    moves.push(Mover::new(side).move_to(from).north().north().west().make(board));
+    moves.push(Mover::new(side).move_to(from).north().north().east().make(board));
     // East
-    moves.push(Mover::new(side).move_to(from).east().east().north().make());
-    moves.push(Mover::new(side).move_to(from).east().east().south().make());
+    moves.push(Mover::new(side).move_to(from).east().east().north().make(board));
+    moves.push(Mover::new(side).move_to(from).east().east().south().make(board));
     // South
-    moves.push(Mover::new(side).move_to(from).south().south().east().make());
-    moves.push(Mover::new(side).move_to(from).south().south().west().make());
+    moves.push(Mover::new(side).move_to(from).south().south().east().make(board));
+    moves.push(Mover::new(side).move_to(from).south().south().west().make(board));
     // West
-    moves.push(Mover::new(side).move_to(from).west().west().south().make());
-    moves.push(Mover::new(side).move_to(from).west().west().north().make());
+    moves.push(Mover::new(side).move_to(from).west().west().south().make(board));
+    moves.push(Mover::new(side).move_to(from).west().west().north().make(board));
 
     moves
         .into_iter()
@@ -4,6 +4,8 @@ use piece::{Rank};
 use Side;
 use action::Action;
 use piece::Piece;
+use error::InvalidCoordinate;
+use std::cmp;
 
 mod pawn;
 mod knight;
@@ -11,6 +13,7 @@ mod bishop;
 mod rook;
 mod queen;
 mod king;
+pub mod fairy;
 
 pub fn possible_actions(
     from: &Coordinate,
@@ -21,9 +24,63 @@ pub fn possible_actions(
         .collect()
 }
 
+// All legal actions for the side that is next to move. There are none once
+// the variant's own rules have ended the game.
+pub fn all_possible_actions(state: &mut GameState) -> Vec<Action> {
+    if state.variant().winner(state).is_some() {
+        return vec![]
+    }
+
+    let side = state.next_to_move();
+    let my_coordinates = state.board().pieces_with_coordinates()
+                                .into_iter()
+                                .filter(|&(_coordinate, piece)| piece.side() == side)
+                                .map(|(coordinate, _piece)| coordinate)
+                                .collect::<Vec<Coordinate>>();
+
+    let mut actions: Vec<Action> = my_coordinates
+        .into_iter()
+        .flat_map(|coordinate| possible_actions(&coordinate, state))
+        .collect();
+    actions.append(&mut possible_drops(state));
+    actions
+}
+
+// The drops the side to move can make without leaving its king in check.
+pub fn possible_drops(state: &mut GameState) -> Vec<Action> {
+    state.variant().drops(state).into_iter()
+        .filter(|action| leads_out_of_check(action, state))
+        .collect()
+}
+
+// What the piece could do by the rules of the game's variant, whether or
+// not it leaves the king in check.
 pub fn enumerate_all_actions(
     from: &Coordinate,
     state: &GameState,
+) -> Vec<Action> {
+    state.variant().actions(from, state)
+}
+
+// The usual moves, castling included.
+pub fn standard_actions(
+    from: &Coordinate,
+    state: &GameState,
+) -> Vec<Action> {
+    let mut actions = enumerate_attacks(from, state);
+    if let &Some(piece) = state.piece_at(*from) {
+        if piece.rank() == Rank::King {
+            actions.append(&mut king::possible_castles(from, state));
+        }
+    }
+
+    actions
+}
+
+// Everything but castling, which never captures and so can't give check.
+fn enumerate_attacks(
+    from: &Coordinate,
+    state: &GameState,
 ) -> Vec<Action> {
     if let &Some(piece) = state.piece_at(*from) {
         match piece.rank() {
@@ -32,32 +89,53 @@ pub fn enumerate_all_actions(
             Rank::Bishop => bishop::possible_actions(from, state),
             Rank::Rook => rook::possible_actions(from, state),
             Rank::Queen => queen::possible_actions(from, state),
-            Rank::King => king::possible_actions(from, state)
+            Rank::King => king::possible_actions(from, state),
+            Rank::Archbishop | Rank::Chancellor | Rank::Amazon => fairy::possible_actions(from, state),
         }
     } else {
         vec![]
     }
 }
 
-fn leads_out_of_check(action: &Action, state: &mut GameState) -> bool {
+// Whether the side making the action isn't left in check by it, or in any
+// other position the variant doesn't allow.
+pub fn leads_out_of_check(action: &Action, state: &mut GameState) -> bool {
     let next_to_move = state.next_to_move();
-    state.evaluate_with_action(action.clone(), |new_state| !is_in_check(&new_state, next_to_move))
+    let variant = state.variant();
+    state.evaluate_with_action(action.clone(), |new_state| variant.is_legal_after(&new_state, next_to_move))
 }
 
 pub fn is_in_check(state: &GameState, side: Side) -> bool {
+    state.variant().is_in_check(state, side)
+}
+
+// Whether the other side could capture the side's king, the usual meaning
+// of check. A side without a king can't be in check.
+pub fn king_is_attacked(state: &GameState, side: Side) -> bool {
     let my_king = Piece::pack(side, Rank::King);
     let all_my_king_coordinates = state.board().find_pieces(my_king);
 
-    let king_coordinate = all_my_king_coordinates.get(0).expect("No king on the board");
+    let king_coordinate = match all_my_king_coordinates.get(0) {
+        Some(coordinate) => coordinate,
+        None => return false,
+    };
 
     state.board().pieces_with_coordinates()
         .into_iter()
         .filter(|&(_coordinate, piece)| piece.side() != side)
-        .flat_map(|(coordinate, _piece)| enumerate_all_actions(&coordinate, &state))
+        .flat_map(|(coordinate, _piece)| enumerate_attacks(&coordinate, &state))
         .any(|action| action_matches_coordinate(&action, &king_coordinate))
 }
 
 pub fn is_in_checkmate(state: &mut GameState, side: Side) -> bool {
+    is_in_check(state, side) && !has_any_legal_action(state, side)
+}
+
+pub fn is_in_stalemate(state: &mut GameState, side: Side) -> bool {
+    !is_in_check(state, side) && !has_any_legal_action(state, side)
+}
+
+fn has_any_legal_action(state: &mut GameState, side: Side) -> bool {
     let my_coords_and_pieces = state.board().pieces_with_coordinates()
                                 .into_iter()
                                 .filter(|&(_coordinate, piece)| piece.side() == side)
@@ -65,14 +143,16 @@ pub fn is_in_checkmate(state: &mut GameState, side: Side) -> bool {
 
     my_coords_and_pieces
         .into_iter()
-        .all(|(coordinate, _piece)| possible_actions(&coordinate, state).is_empty())
+        .any(|(coordinate, _piece)| !possible_actions(&coordinate, state).is_empty())
+        || (side == state.next_to_move() && !possible_drops(state).is_empty())
 }
 
 fn action_matches_coordinate(action: &Action, coord: &Coordinate) -> bool {
     match *action {
         Action::MovePiece(_, __, to) => *coord == to,
         Action::Capture(_, _, _, to) => *coord == to,
-        Action::Promotion(_, _, _, to) => *coord == to,
+        Action::Promotion(_, _, _, _, to) => *coord == to,
+        Action::Castle(..) | Action::Drop(..) => false,
     }
 }
 
@@ -127,6 +207,20 @@ impl Mover {
         self
     }
 
+    // Steps the given number of squares north and east, going south or west
+    // for negative numbers.
+    fn step(self, north: i8, east: i8) -> Self {
+        let mut mover = self;
+        for _ in 0..north.abs() {
+            mover = if north > 0 { mover.north() } else { mover.south() };
+        }
+        for _ in 0..east.abs() {
+            mover = if east > 0 { mover.east() } else { mover.west() };
+        }
+
+        mover
+    }
+
     fn move_to(mut self, coordinate: &Coordinate) -> Self {
         self.current_column = coordinate.column() as i8;
         self.current_row = coordinate.row() as i8;
@@ -134,8 +228,10 @@ impl Mover {
         self
     }
 
-    fn make(self) -> Result<Coordinate, String> {
-        Coordinate::new_safe(self.current_row as usize, self.current_column as usize)
+    fn make(self, board: &Board) -> Result<Coordinate, InvalidCoordinate> {
+        let coordinate = Coordinate::new_safe(self.current_row as usize, self.current_column as usize)?;
+        board.check(&coordinate)?;
+        Ok(coordinate)
     }
 }
 
@@ -152,11 +248,11 @@ where
 {
     let mut accumulator = vec![];
     let mut current_coord = starting_coordinate.clone();
-    for _ in 0..7 {
+    for _ in 0..cmp::max(board.width(), board.height()) {
         let mut mover = Mover::new(side).move_to(&current_coord);
         mover = closure(mover);
 
-        if let Ok(next) = mover.make() {
+        if let Ok(next) = mover.make(board) {
             if board.is_empty(next) {
                 accumulator.push(next);
                 current_coord = next;
@@ -181,11 +277,11 @@ where
 {
     let mut current_coord = starting_coordinate.clone();
     let mut result = None;
-    for _ in 0..7 {
+    for _ in 0..cmp::max(board.width(), board.height()) {
         let mut mover = Mover::new(side).move_to(&current_coord);
         mover = closure(mover);
 
-        if let Ok(next) = mover.make() {
+        if let Ok(next) = mover.make(board) {
             if let &Some(piece) = board.piece_at(next) {
                 if piece.side() != side {
                     result = Some(next);
@@ -294,4 +390,65 @@ mod tests {
 
         assert!(is_in_checkmate(&mut state, Side::White));
     }
+
+    #[test]
+    fn detects_stalemate() {
+        let mut board = Board::empty();
+
+        board.update(&coord!("a1"), Some(Piece::pack(Side::White, Rank::King))).unwrap();
+        board.update(&coord!("h8"), Some(Piece::pack(Side::Black, Rank::King))).unwrap();
+        board.update(&coord!("b3"), Some(Piece::pack(Side::Black, Rank::Queen))).unwrap();
+
+        let mut state = GameState::with_board(board);
+
+        assert!(is_in_stalemate(&mut state, Side::White));
+        assert!(!is_in_checkmate(&mut state, Side::White));
+    }
+
+    #[test]
+    fn knights_give_check_whoever_is_to_move() {
+        // Smothered mate, with black to move
+        let mut state = GameState::from_fen("6rk/5Npp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+
+        assert!(is_in_check(&state, Side::Black));
+        assert!(is_in_checkmate(&mut state, Side::Black));
+    }
+
+    // Counts the positions reached after the number of moves. The expected
+    // counts come from other move generators, minus any promotions or en
+    // passant captures as we don't play those.
+    fn perft(state: &mut GameState, depth: usize) -> usize {
+        let actions = all_possible_actions(state);
+        if depth == 1 {
+            return actions.len()
+        }
+
+        actions.into_iter().map(|action| state.evaluate_with_action(action, |state| perft(state, depth - 1))).sum()
+    }
+
+    #[test]
+    fn perft_from_starting_positions() {
+        // 518 is the usual starting position
+        for &(number, nodes) in &[(518, 8902), (0, 9006)] {
+            let mut state = GameState::starting_from(Board::chess960(number).unwrap());
+            assert_eq!(perft(&mut state, 3), nodes, "Position {}", number);
+        }
+    }
+
+    #[test]
+    fn perft_with_chess960_castling() {
+        let positions = [
+            // The king castles long without moving, and short past its rook
+            ("r2br1bn/2pkpppp/p1qp2B1/4P3/npP3PP/P2P4/1P3PNB/QRK2R1N w KQ - 1 14", 926),
+            // The rook castles short without moving
+            ("r2k1r1b/pp2pp2/8/5qpp/1n1B2bP/PP1pPN2/2PN1PP1/R2K1RQB b KQkq - 2 17", 1131),
+            // The king castles short onto its rook's square
+            ("nrk3rq/1p1pp1pp/p1p1n1b1/b3Pp2/P6P/2N5/1PPP1PP1/NRKBB1RQ b KQkq - 0 7", 710),
+        ];
+
+        for &(fen, nodes) in &positions {
+            let mut state = GameState::from_fen(fen).unwrap();
+            assert_eq!(perft(&mut state, 2), nodes, "{}", fen);
+        }
+    }
 }
@@ -1,8 +1,10 @@
 use game::{GameState};
 use action::Action;
-use board::Coordinate;
+use board::{Board, Coordinate};
 use Side;
+use error::InvalidCoordinate;
 use engine::Mover;
+use piece::Piece;
 
 pub fn possible_actions(from: &Coordinate, state: &GameState) -> Vec<Action> {
     let mut actions = vec![];
@@ -10,28 +12,53 @@ pub fn possible_actions(from: &Coordinate, state: &GameState) -> Vec<Action> {
     actions.append(&mut possible_captures(from, state));
 
     actions
+        .into_iter()
+        .flat_map(|action| promotions(action, state))
+        .collect()
+}
+
+// A pawn reaching the far rank becomes one of the pieces the variant allows
+// instead, the most valuable first.
+fn promotions(action: Action, state: &GameState) -> Vec<Action> {
+    let (pawn, captured, from, to) = match action {
+        Action::MovePiece(pawn, from, to) => (pawn, None, from, to),
+        Action::Capture(pawn, target, from, to) => (pawn, Some(target), from, to),
+        _ => return vec![action],
+    };
+    let far_row = match pawn.side() {
+        Side::White => state.board().height() - 1,
+        Side::Black => 0,
+    };
+    if to.row() != far_row {
+        return vec![action]
+    }
+
+    state.variant().promotions()
+        .into_iter()
+        .map(|rank| Action::Promotion(pawn, Piece::pack(pawn.side(), rank), captured, from, to))
+        .collect()
 }
 
 fn possible_moves(from: &Coordinate, state: &GameState) -> Vec<Action> {
-    let mut moves: Vec<Result<Coordinate, String>> = Vec::new();
+    let mut moves: Vec<Result<Coordinate, InvalidCoordinate>> = Vec::new();
     let side = state.piece_at(*from).unwrap().side();
 
     // TODO: Consider refactoring this mess
     let one_square_forward = Mover::new(side)
                                 .move_to(from)
                                 .north()
-                                .make();
+                                .make(state.board());
     // Moves forward
     moves.push(one_square_forward.clone());
 
-    if is_starting_coordinate(from, side) && state.board().is_empty(one_square_forward.unwrap()) {
+    if is_starting_coordinate(from, side, state.board()) && state.board().is_empty(one_square_forward.unwrap()) {
         // Moves forward twice
         moves.push(
             Mover::new(side)
                 .move_to(from)
                 .north()
                 .north()
-                .make(),
+                .make(state.board()),
         );
     }
 
@@ -44,7 +71,7 @@ fn possible_moves(from: &Coordinate, state: &GameState) -> Vec<Action> {
 }
 
 fn possible_captures(from: &Coordinate, state: &GameState) -> Vec<Action> {
-    let mut moves: Vec<Result<Coordinate, String>> = Vec::new();
+    let mut moves: Vec<Result<Coordinate, InvalidCoordinate>> = Vec::new();
     let side = state.piece_at(*from).unwrap().side();
 
     moves.push(
@@ -52,14 +79,14 @@ fn possible_captures(from: &Coordinate, state: &GameState) -> Vec<Action> {
             .move_to(from)
             .north()
             .east()
-            .make(),
+            .make(state.board()),
     );
     moves.push(
         Mover::new(side)
             .move_to(from)
             .north()
             .west()
-            .make(),
+            .make(state.board()),
     );
     // TODO: Ampasant
 
@@ -74,10 +101,11 @@ fn possible_captures(from: &Coordinate, state: &GameState) -> Vec<Action> {
         .collect()
 }
 
-fn is_starting_coordinate(coordinate: &Coordinate, side: Side) -> bool {
+// The rank in front of the side's home rank.
+fn is_starting_coordinate(coordinate: &Coordinate, side: Side, board: &Board) -> bool {
     match side {
         Side::White => coordinate.row() == 1,
-        Side::Black => coordinate.row() == 6,
+        Side::Black => coordinate.row() == board.height() - 2,
     }
 }
 
@@ -85,7 +113,7 @@ fn is_starting_coordinate(coordinate: &Coordinate, side: Side) -> bool {
 mod tests {
     use super::*;
     use board::Board;
-    use piece::{Piece, Rank};
+    use piece::Rank;
 
     macro_rules! coord {
         ($x:expr) => { Coordinate::from_human($x.to_string()).unwrap() }
@@ -137,6 +165,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn promotes_to_any_piece_on_the_far_rank() {
+        let mut board = Board::empty();
+        board.update(&coord!("e7"), Some(Piece::pack(Side::White, Rank::Pawn))).unwrap();
+        board.update(&coord!("d8"), Some(Piece::pack(Side::Black, Rank::Rook))).unwrap();
+        board.update(&coord!("b2"), Some(Piece::pack(Side::Black, Rank::Pawn))).unwrap();
+
+        let state = GameState::with_board(board);
+        let white = Piece::pack(Side::White, Rank::Pawn);
+        let rook = Piece::pack(Side::Black, Rank::Rook);
+
+        assert_eq!(possible_actions(&coord!("e7"), &state), vec![
+            Action::Promotion(white, Piece::pack(Side::White, Rank::Queen), None, coord!("e7"), coord!("e8")),
+            Action::Promotion(white, Piece::pack(Side::White, Rank::Rook), None, coord!("e7"), coord!("e8")),
+            Action::Promotion(white, Piece::pack(Side::White, Rank::Bishop), None, coord!("e7"), coord!("e8")),
+            Action::Promotion(white, Piece::pack(Side::White, Rank::Knight), None, coord!("e7"), coord!("e8")),
+            Action::Promotion(white, Piece::pack(Side::White, Rank::Queen), Some(rook), coord!("e7"), coord!("d8")),
+            Action::Promotion(white, Piece::pack(Side::White, Rank::Rook), Some(rook), coord!("e7"), coord!("d8")),
+            Action::Promotion(white, Piece::pack(Side::White, Rank::Bishop), Some(rook), coord!("e7"), coord!("d8")),
+            Action::Promotion(white, Piece::pack(Side::White, Rank::Knight), Some(rook), coord!("e7"), coord!("d8")),
+        ]);
+        // Black promotes on the first rank
+        assert_eq!(possible_actions(&coord!("b2"), &state).len(), 4);
+    }
+
     #[test]
     fn finds_correct_captures() {
         let mut board = Board::empty();
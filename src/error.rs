@@ -0,0 +1,92 @@
+use std::error::Error;
+use std::fmt;
+
+use action::Action;
+use notation;
+
+// A square that isn't on the board, or text that doesn't name one.
+#[derive(PartialEq, Clone, Debug)]
+pub enum InvalidCoordinate {
+    OffTheBoard { row: usize, column: usize },
+    Unreadable(String),
+}
+
+impl fmt::Display for InvalidCoordinate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InvalidCoordinate::OffTheBoard { row, column } => write!(f, "Off the board: row {}, column {}", row, column),
+            InvalidCoordinate::Unreadable(ref text) => write!(f, "Not a square: {}", text),
+        }
+    }
+}
+
+impl Error for InvalidCoordinate {
+    fn description(&self) -> &str {
+        "invalid coordinate"
+    }
+}
+
+// Why a move can't be played in a position.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum IllegalReason {
+    EmptySquare,
+    NotYourTurn,
+    // The pieces in the move aren't the ones on the board
+    WrongPiece,
+    // The piece doesn't move like that, or something is in the way
+    NotPossible,
+    LeavesKingInCheck,
+    // The variant's own rules have already ended the game
+    GameOver,
+}
+
+impl fmt::Display for IllegalReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match *self {
+            IllegalReason::EmptySquare => "there is no piece there",
+            IllegalReason::NotYourTurn => "it is the other side's turn",
+            IllegalReason::WrongPiece => "the pieces don't match the board",
+            IllegalReason::NotPossible => "the piece can't move there",
+            IllegalReason::LeavesKingInCheck => "it leaves the king in check",
+            IllegalReason::GameOver => "the game is over",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum MoveError {
+    IllegalMove { action: Action, reason: IllegalReason },
+    InvalidCoordinate(InvalidCoordinate),
+    // A typed move that can't be read
+    NotAMove(String),
+    // A typed move that no legal move matches
+    NoSuchMove(String),
+    // A typed move that more than one legal move matches
+    AmbiguousMove(String),
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MoveError::IllegalMove { ref action, reason } =>
+                write!(f, "Illegal move {}: {}", notation::to_long_algebraic(action), reason),
+            MoveError::InvalidCoordinate(ref e) => write!(f, "{}", e),
+            MoveError::NotAMove(ref text) => write!(f, "Not a move: {}", text),
+            MoveError::NoSuchMove(ref text) => write!(f, "Illegal move: {}", text),
+            MoveError::AmbiguousMove(ref text) => write!(f, "Ambiguous move: {}", text),
+        }
+    }
+}
+
+impl Error for MoveError {
+    fn description(&self) -> &str {
+        "invalid move"
+    }
+}
+
+impl From<InvalidCoordinate> for MoveError {
+    fn from(e: InvalidCoordinate) -> MoveError {
+        MoveError::InvalidCoordinate(e)
+    }
+}
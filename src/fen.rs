@@ -0,0 +1,431 @@
+use std::error::Error;
+use std::fmt;
+
+use Side;
+use board::{Board, Coordinate, MAX_SIZE};
+use piece::{Piece, Rank};
+use pocket::Pockets;
+use variant::Variant;
+
+// Why a FEN string could not be read.
+#[derive(PartialEq, Clone, Debug)]
+pub enum FenError {
+    // Fewer than the four fields up to the en passant square
+    MissingFields,
+    // A rank that isn't as wide as the board, or the wrong number of ranks,
+    // counting from the last one
+    InvalidRank(String),
+    UnknownPiece(char),
+    // Each side needs exactly one king, or as many as the variant plays with
+    InvalidKings(Side),
+    InvalidSideToMove(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidCounter(String),
+    // Pieces in a Crazyhouse pocket that can't be read, or a king
+    InvalidPocket(String),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FenError::MissingFields => write!(f, "FEN needs at least the board, side to move, castling and en passant fields"),
+            FenError::InvalidRank(ref rank) => write!(f, "Invalid rank in FEN: {}", rank),
+            FenError::UnknownPiece(letter) => write!(f, "Unknown piece in FEN: {}", letter),
+            FenError::InvalidKings(side) => write!(f, "{} has the wrong number of kings", side_name(side)),
+            FenError::InvalidSideToMove(ref side) => write!(f, "Invalid side to move in FEN: {}, expected w or b", side),
+            FenError::InvalidCastling(ref castling) => write!(f, "Invalid castling rights in FEN: {}", castling),
+            FenError::InvalidEnPassant(ref square) => write!(f, "Invalid en passant square in FEN: {}", square),
+            FenError::InvalidCounter(ref counter) => write!(f, "Invalid move counter in FEN: {}", counter),
+            FenError::InvalidPocket(ref pocket) => write!(f, "Invalid pocket in FEN: {}", pocket),
+        }
+    }
+}
+
+impl Error for FenError {
+    fn description(&self) -> &str {
+        "invalid FEN"
+    }
+}
+
+// The board, side to move, the rooks that can castle and the halfmove and
+// fullmove counters from a FEN string, with as many kings as the variant
+// plays with. There is no en passant yet, so that field is checked but
+// otherwise ignored. Missing counters start at 0 and 1.
+pub fn parse_for(text: &str, variant: &dyn Variant) -> Result<(Board, Side, Vec<Coordinate>, usize, usize), FenError> {
+    let fields: Vec<&str> = text.split_whitespace().collect();
+    if fields.len() < 4 {
+        return Err(FenError::MissingFields)
+    }
+
+    let board = parse_board(fields[0], variant)?;
+
+    let side = match fields[1] {
+        "w" => Side::White,
+        "b" => Side::Black,
+        other => return Err(FenError::InvalidSideToMove(other.to_string())),
+    };
+
+    let castling = parse_castling(fields[2], &board)?;
+
+    let on_board = |square: Coordinate| board.contains(square);
+    if fields[3] != "-" && !Coordinate::from_human(fields[3].to_string()).map(on_board).unwrap_or(false) {
+        return Err(FenError::InvalidEnPassant(fields[3].to_string()))
+    }
+
+    let halfmoves = parse_counter(fields.get(4), 0)?;
+    let fullmoves = parse_counter(fields.get(5), 1)?;
+
+    Ok((board, side, castling, halfmoves, fullmoves))
+}
+
+// Castling rights as KQkq, X-FEN or Shredder-FEN: K and Q stand for the
+// outermost rook on that side of the king, and a file letter for the rook
+// on that file, as Chess960 needs when there's a rook further out. Rights
+// for a rook that isn't there are dropped, as other readers do.
+fn parse_castling(text: &str, board: &Board) -> Result<Vec<Coordinate>, FenError> {
+    let mut rooks = vec![];
+    if text == "-" {
+        return Ok(rooks)
+    }
+
+    for letter in text.chars() {
+        let side = if letter.is_ascii_uppercase() { Side::White } else { Side::Black };
+        let has_king = board.castling_rook(side, true).is_some() || board.castling_rook(side, false).is_some();
+
+        let rook = match letter.to_ascii_lowercase() {
+            'k' => board.castling_rook(side, true),
+            'q' => board.castling_rook(side, false),
+            file if is_file(file, board) && has_king => {
+                let rook = Coordinate::new(board.home_row(side), file as usize - 'a' as usize);
+                if *board.piece_at(rook) == Some(Piece::pack(side, Rank::Rook)) { Some(rook) } else { None }
+            },
+            file if is_file(file, board) => None,
+            _ => return Err(FenError::InvalidCastling(text.to_string())),
+        };
+
+        if let Some(rook) = rook {
+            if !rooks.contains(&rook) {
+                rooks.push(rook);
+            }
+        }
+    }
+
+    Ok(rooks)
+}
+
+fn is_file(letter: char, board: &Board) -> bool {
+    letter >= 'a' && (letter as usize - 'a' as usize) < board.width()
+}
+
+fn parse_counter(field: Option<&&str>, default: usize) -> Result<usize, FenError> {
+    match field {
+        Some(text) => text.parse().map_err(|_| FenError::InvalidCounter(text.to_string())),
+        None => Ok(default),
+    }
+}
+
+// The pockets in brackets after the board, as "[QRp]", and the squares of
+// the promoted pieces, marked with a ~ after their letter. Both are only
+// written for variants that have pockets.
+pub fn parse_pockets(text: &str) -> Result<(Pockets, Vec<Coordinate>), FenError> {
+    let placement = text.split_whitespace().next().unwrap_or("");
+    let (ranks, held) = match placement.find('[') {
+        Some(start) if placement.ends_with(']') => (&placement[..start], &placement[start + 1..placement.len() - 1]),
+        Some(start) => return Err(FenError::InvalidPocket(placement[start..].to_string())),
+        None => (placement, ""),
+    };
+
+    let mut pockets = Pockets::new();
+    for letter in held.chars() {
+        match piece_from_letter(letter) {
+            Some(piece) if piece.rank() != Rank::King => pockets.add(piece),
+            _ => return Err(FenError::InvalidPocket(held.to_string())),
+        }
+    }
+
+    let ranks: Vec<&str> = ranks.split('/').collect();
+    let mut promoted = vec![];
+    for (index, rank) in ranks.iter().enumerate() {
+        let (letters, _width) = read_rank(rank, MAX_SIZE)?;
+        for (column, letter) in letters {
+            if letter == '~' {
+                promoted.push(Coordinate::new(ranks.len() - 1 - index, column));
+            }
+        }
+    }
+
+    Ok((pockets, promoted))
+}
+
+fn parse_board(text: &str, variant: &dyn Variant) -> Result<Board, FenError> {
+    let text = match text.find('[') {
+        Some(start) => &text[..start],
+        None => text,
+    };
+    let (width, height) = variant.board_size();
+    let ranks: Vec<&str> = text.split('/').collect();
+    if ranks.len() != height {
+        return Err(FenError::InvalidRank(text.to_string()))
+    }
+
+    let mut board = Board::with_size(width, height);
+    for (index, rank) in ranks.iter().enumerate() {
+        let row = height - 1 - index;
+        let (letters, rank_width) = read_rank(rank, width)?;
+
+        for (column, letter) in letters {
+            if letter == '~' {
+                continue;
+            }
+            if column >= width {
+                return Err(FenError::InvalidRank(rank.to_string()))
+            }
+            let piece = piece_from_letter(letter).ok_or(FenError::UnknownPiece(letter))?;
+            board.update(&Coordinate::new(row, column), Some(piece)).expect("Square is on the board");
+        }
+
+        if rank_width != width {
+            return Err(FenError::InvalidRank(rank.to_string()))
+        }
+    }
+
+    for side in &[Side::White, Side::Black] {
+        if board.find_pieces(Piece::pack(*side, Rank::King)).len() != variant.kings(*side) {
+            return Err(FenError::InvalidKings(*side))
+        }
+    }
+
+    Ok(board)
+}
+
+// The letters in a rank with the column of the square each is on, where a
+// ~ is on the square of the piece before it, and how many squares the rank
+// covers. Gaps can take more than one digit, as the 10 of a wide board, but
+// a rank can't cover more than width squares.
+fn read_rank(rank: &str, width: usize) -> Result<(Vec<(usize, char)>, usize), FenError> {
+    let invalid = || FenError::InvalidRank(rank.to_string());
+    let mut letters = vec![];
+    let mut column = 0;
+    let mut gap = 0usize;
+
+    for letter in rank.chars() {
+        if let Some(digit) = letter.to_digit(10) {
+            gap = gap.checked_mul(10)
+                .and_then(|gap| gap.checked_add(digit as usize))
+                .filter(|gap| column + gap <= width)
+                .ok_or_else(invalid)?;
+            continue;
+        }
+        column += gap;
+        gap = 0;
+
+        if letter == '~' {
+            letters.push((column.saturating_sub(1), letter));
+        } else {
+            letters.push((column, letter));
+            column += 1;
+        }
+        if column > width {
+            return Err(invalid())
+        }
+    }
+
+    Ok((letters, column + gap))
+}
+
+pub fn write(board: &Board, side: Side, castling: &[Coordinate], halfmoves: usize, fullmoves: usize) -> String {
+    format!("{} {}", write_placement(board, &[]), write_rest(board, side, castling, halfmoves, fullmoves))
+}
+
+// Like write, with the pockets after the board and a ~ after each promoted
+// piece.
+pub fn write_with_pockets(board: &Board, promoted: &[Coordinate], pockets: &Pockets, side: Side, castling: &[Coordinate], halfmoves: usize, fullmoves: usize) -> String {
+    let held: String = [Side::White, Side::Black]
+        .iter()
+        .flat_map(|&side| pockets.all_pieces(side))
+        .map(piece_letter)
+        .collect();
+
+    format!("{}[{}] {}", write_placement(board, promoted), held, write_rest(board, side, castling, halfmoves, fullmoves))
+}
+
+fn write_placement(board: &Board, promoted: &[Coordinate]) -> String {
+    let ranks: Vec<String> = board.rows().enumerate().rev().map(|(row_index, row)| {
+        let mut rank = String::new();
+        let mut gap = 0;
+
+        for (column, square) in row.iter().enumerate() {
+            match *square {
+                Some(piece) => {
+                    if gap > 0 {
+                        rank.push_str(&gap.to_string());
+                        gap = 0;
+                    }
+                    rank.push(piece_letter(piece));
+                    if promoted.contains(&Coordinate::new(row_index, column)) {
+                        rank.push('~');
+                    }
+                },
+                None => gap += 1,
+            }
+        }
+        if gap > 0 {
+            rank.push_str(&gap.to_string());
+        }
+        rank
+    }).collect();
+
+    ranks.join("/")
+}
+
+fn write_rest(board: &Board, side: Side, castling: &[Coordinate], halfmoves: usize, fullmoves: usize) -> String {
+    let side = match side {
+        Side::White => "w",
+        Side::Black => "b",
+    };
+
+    format!("{} {} - {} {}", side, write_castling(board, castling), halfmoves, fullmoves)
+}
+
+// X-FEN: KQkq where that names the rook, the file of the rook where it
+// doesn't. White comes first, and the short side before the long one.
+fn write_castling(board: &Board, castling: &[Coordinate]) -> String {
+    let mut letters = String::new();
+
+    for side in &[Side::White, Side::Black] {
+        for &(short, letter) in &[(true, 'k'), (false, 'q')] {
+            let outermost = board.castling_rook(*side, short);
+            let king_column = board.find_pieces(Piece::pack(*side, Rank::King))
+                .into_iter()
+                .find(|king| king.row() == board.home_row(*side))
+                .map(|king| king.column());
+
+            let rooks = castling.iter().filter(|rook| {
+                rook.row() == board.home_row(*side) &&
+                    king_column.iter().any(|&column| (rook.column() > column) == short)
+            });
+            for rook in rooks {
+                let letter = if Some(*rook) == outermost { letter } else { rook.to_human().remove(0) };
+                letters.push(if *side == Side::White { letter.to_ascii_uppercase() } else { letter });
+            }
+        }
+    }
+
+    if letters.is_empty() { "-".to_string() } else { letters }
+}
+
+fn piece_letter(piece: Piece) -> char {
+    let letter = match piece.rank() {
+        Rank::Pawn => 'p',
+        Rank::Knight => 'n',
+        Rank::Bishop => 'b',
+        Rank::Rook => 'r',
+        Rank::Queen => 'q',
+        Rank::King => 'k',
+        Rank::Archbishop => 'a',
+        Rank::Chancellor => 'c',
+        Rank::Amazon => 'm',
+    };
+
+    match piece.side() {
+        Side::White => letter.to_ascii_uppercase(),
+        Side::Black => letter,
+    }
+}
+
+fn piece_from_letter(letter: char) -> Option<Piece> {
+    let rank = match letter.to_ascii_lowercase() {
+        'p' => Rank::Pawn,
+        'n' => Rank::Knight,
+        'b' => Rank::Bishop,
+        'r' => Rank::Rook,
+        'q' => Rank::Queen,
+        'k' => Rank::King,
+        'a' => Rank::Archbishop,
+        'c' => Rank::Chancellor,
+        'm' => Rank::Amazon,
+        _ => return None,
+    };
+    let side = if letter.is_ascii_uppercase() { Side::White } else { Side::Black };
+
+    Some(Piece::pack(side, rank))
+}
+
+fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::White => "White",
+        Side::Black => "Black",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use variant::{Horde, Standard};
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    fn parse(text: &str) -> Result<(Board, Side, Vec<Coordinate>, usize, usize), FenError> {
+        parse_for(text, &Standard)
+    }
+
+    #[test]
+    fn reads_and_writes_the_starting_position() {
+        let (board, side, castling, halfmoves, fullmoves) = parse(START).unwrap();
+        assert!(board == Board::default());
+        assert_eq!((side, halfmoves, fullmoves), (Side::White, 0, 1));
+
+        assert_eq!(write(&board, side, &castling, 0, 1), START);
+        assert_eq!(write(&board, side, &[], 0, 1), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1");
+    }
+
+    #[test]
+    fn reads_and_writes_chess960_castling_rights() {
+        let h1 = Coordinate::new(0, 7);
+        let b1 = Coordinate::new(0, 1);
+        let b8 = Coordinate::new(7, 1);
+
+        // White has a rook on a1 further out than the b1 one
+        let fen = "1r4k1/8/8/8/8/8/8/RR4KR w KBb - 0 1";
+        let (board, _, castling, _, _) = parse(fen).unwrap();
+        assert_eq!(castling, vec![h1, b1, b8]);
+        assert_eq!(parse("1r4k1/8/8/8/8/8/8/RR4KR w HBb - 0 1").unwrap().2, vec![h1, b1, b8]);
+        assert_eq!(write(&board, Side::White, &castling, 0, 1), "1r4k1/8/8/8/8/8/8/RR4KR w KBq - 0 1");
+
+        // Rights for rooks that aren't there are dropped
+        assert_eq!(parse("1r4k1/8/8/8/8/8/8/RR4KR w Kk - 0 1").unwrap().2, vec![h1]);
+        assert_eq!(parse("1r4k1/8/8/8/8/8/8/RR4KR w Kx - 0 1"), Err(FenError::InvalidCastling("Kx".to_string())));
+    }
+
+    #[test]
+    fn rejects_broken_fen() {
+        assert_eq!(parse("8/8/8/8/8/8/8/8 w"), Err(FenError::MissingFields));
+        assert_eq!(parse("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w - -"), Err(FenError::InvalidRank("9".to_string())));
+        assert_eq!(parse("rnbqkbnr/pppppppp/99999999999999999999999/8/8/8/PPPPPPPP/RNBQKBNR w - -"), Err(FenError::InvalidRank("99999999999999999999999".to_string())));
+        assert_eq!(parse_pockets("4k3/18446744073709551615/8/8/8/8/8/4K3 w - -"), Err(FenError::InvalidRank("18446744073709551615".to_string())));
+        assert_eq!(parse("rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - -"), Err(FenError::UnknownPiece('x')));
+        assert_eq!(parse("rnbq1bnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - -"), Err(FenError::InvalidKings(Side::Black)));
+        assert_eq!(parse("4k3/8/8/8/8/8/8/PPPPPPPP w - -"), Err(FenError::InvalidKings(Side::White)));
+        assert!(parse_for("4k3/8/8/8/8/8/8/PPPPPPPP w - -", &Horde).is_ok());
+        assert_eq!(parse_for(START, &Horde), Err(FenError::InvalidKings(Side::White)));
+        assert_eq!(parse("4k3/8/8/8/8/8/8/4K3 x - -"), Err(FenError::InvalidSideToMove("x".to_string())));
+        assert_eq!(parse("4k3/8/8/8/8/8/8/4K3 w - e9"), Err(FenError::InvalidEnPassant("e9".to_string())));
+        assert_eq!(parse("4k3/8/8/8/8/8/8/4K3 w - - x 1"), Err(FenError::InvalidCounter("x".to_string())));
+    }
+
+    #[test]
+    fn reads_and_writes_crazyhouse_pockets() {
+        let fen = "4k3/8/8/8/8/8/3Q~4/4K3[NNp] w - - 0 1";
+        let (pockets, promoted) = parse_pockets(fen).unwrap();
+        let (board, side, castling, _, _) = parse(fen).unwrap();
+
+        assert_eq!(pockets.all_pieces(Side::White).len(), 2);
+        assert_eq!(pockets.pieces(Side::Black), vec![Piece::pack(Side::Black, Rank::Pawn)]);
+        assert_eq!(promoted, vec![Coordinate::new(1, 3)]);
+        assert_eq!(write_with_pockets(&board, &promoted, &pockets, side, &castling, 0, 1), fen);
+
+        assert_eq!(parse_pockets("4k3/8/8/8/8/8/8/4K3[K] w - -"), Err(FenError::InvalidPocket("K".to_string())));
+        assert_eq!(parse_pockets("4k3/8/8/8/8/8/8/4K3[Q w - -"), Err(FenError::InvalidPocket("[Q".to_string())));
+    }
+}
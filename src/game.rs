@@ -1,39 +1,254 @@
-use piece::{Piece};
+use piece::{Piece, Rank};
 use Side;
 use board::{Board, Coordinate};
 use engine;
-use action::Action;
+use action::{Action, captured_piece_for, castled_rook_coordinate, from_coordinate_for, to_coordinate_for};
+use clock::Clock;
+use error::{IllegalReason, InvalidCoordinate, MoveError};
+use fen::{self, FenError};
+use notation;
+use pocket::Pockets;
+use variant::{self, Consequences, Variant};
+
+use std::time::Instant;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Status {
+    InProgress,
+    Check,
+    Checkmate,
+    Stalemate,
+}
+
+// How a game ended. Sides are the winners.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Outcome {
+    Checkmate(Side),
+    Stalemate,
+    Timeout(Side),
+    // The side that ran out of time can't lose to pieces that can't mate
+    TimeoutVsInsufficientMaterial,
+    // Won by the variant's own rules, like a king reaching the hill
+    VariantWin(Side),
+}
 
 #[derive(PartialEq, Clone, Debug)]
 pub struct GameState {
     next_to_move: Side,
     history: Vec<Action>,
-    board: Board
+    board: Board,
+    // The FEN halfmove and fullmove counters when the position was set up
+    setup_counters: (usize, usize),
+    // The rooks that could castle when the position was set up. Moves take
+    // the rights away, so see castling_rights for the ones left now.
+    castling: Vec<Coordinate>,
+    variant: &'static dyn Variant,
+    // What the variant's rules did on top of each action in the history
+    consequences: Vec<Consequences>,
+    // Pieces in hand to drop, for variants with pockets
+    pockets: Pockets,
+    // Squares of pieces that were promoted pawns when the position was set
+    // up. See is_promoted for the ones now.
+    promoted: Vec<Coordinate>,
 }
 
 
 #[allow(dead_code)] // TODO: Remove
 impl GameState {
     pub fn new() -> GameState {
+        GameState::starting_from(Board::default())
+    }
+
+    // A position nobody can castle in, for setting up boards by hand.
+    pub fn with_board(board: Board) -> GameState {
         GameState {
             next_to_move: Side::White,
             history: vec![],
-            board: Board::default(),
+            board: board,
+            setup_counters: (0, 1),
+            castling: vec![],
+            variant: &variant::Standard,
+            consequences: vec![],
+            pockets: Pockets::new(),
+            promoted: vec![],
         }
     }
 
-    pub fn with_board(board: Board) -> GameState {
-        GameState {
-            next_to_move: Side::White,
+    // A new game of the variant, from its usual starting position.
+    pub fn for_variant(variant: &'static dyn Variant) -> GameState {
+        GameState::starting_from(variant.starting_board()).with_variant(variant)
+    }
+
+    // A new game from a starting position, such as one from
+    // Board::chess960, where the outermost rooks either side of each king
+    // can castle.
+    pub fn starting_from(board: Board) -> GameState {
+        let rooks = [
+            board.castling_rook(Side::White, true),
+            board.castling_rook(Side::White, false),
+            board.castling_rook(Side::Black, true),
+            board.castling_rook(Side::Black, false),
+        ];
+
+        GameState::with_board(board).with_castling(rooks.iter().filter_map(|&rook| rook).collect())
+    }
+
+    // Lets the rooks on these squares castle, as long as they and their
+    // king are still on their home rank.
+    pub fn with_castling(mut self, rooks: Vec<Coordinate>) -> GameState {
+        self.castling = rooks;
+        self
+    }
+
+    // Plays the position by the variant's rules instead of the usual ones.
+    pub fn with_variant(mut self, variant: &'static dyn Variant) -> GameState {
+        self.variant = variant;
+        self
+    }
+
+    pub fn from_fen(text: &str) -> Result<GameState, FenError> {
+        GameState::from_variant_fen(text, &variant::Standard)
+    }
+
+    // A position from a FEN string, played by the variant's rules. Pockets
+    // come after the board in brackets, and promoted pieces are marked with
+    // a ~, as in "rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQ~KBNR[Qp] w KQkq -".
+    pub fn from_variant_fen(text: &str, variant: &'static dyn Variant) -> Result<GameState, FenError> {
+        let (board, side, castling, halfmoves, fullmoves) = fen::parse_for(text, variant)?;
+        let (pockets, promoted) = fen::parse_pockets(text)?;
+
+        Ok(GameState {
+            next_to_move: side,
             history: vec![],
             board: board,
+            setup_counters: (halfmoves, fullmoves),
+            castling,
+            variant,
+            consequences: vec![],
+            pockets,
+            promoted,
+        })
+    }
+
+    // Castling rights are written as KQkq, or as the rook's file when a
+    // Chess960 rook isn't the outermost one. En passant is always "-" as it
+    // isn't played yet. The counters carry on from the ones the position
+    // was set up with.
+    pub fn to_fen(&self) -> String {
+        let setup_halfmoves = self.setup_counters.0;
+
+        let quiet_moves = self.history.iter().rev().take_while(|action| match **action {
+            Action::MovePiece(piece, _, _) | Action::Drop(piece, _) => piece.rank() != Rank::Pawn,
+            Action::Castle(..) => true,
+            _ => false,
+        }).count();
+        let halfmoves = if quiet_moves == self.history.len() { setup_halfmoves + quiet_moves } else { quiet_moves };
+        let fullmoves = self.fullmove_number();
+
+        if self.variant.has_pockets() {
+            let promoted: Vec<Coordinate> = self.board.pieces_with_coordinates()
+                .into_iter()
+                .map(|(coordinate, _piece)| coordinate)
+                .filter(|coordinate| self.is_promoted(*coordinate))
+                .collect();
+            fen::write_with_pockets(&self.board, &promoted, &self.pockets, self.next_to_move, &self.castling_rights(), halfmoves, fullmoves)
+        } else {
+            fen::write(&self.board, self.next_to_move, &self.castling_rights(), halfmoves, fullmoves)
         }
     }
 
+    // The number of the move being played now, counting on from the one
+    // the position was set up at. It goes up after each of Black's moves.
+    pub fn fullmove_number(&self) -> usize {
+        let started_with_black = (self.next_to_move == Side::Black) != (self.history.len() % 2 == 1);
+        self.setup_counters.1 + (self.history.len() + if started_with_black { 1 } else { 0 }) / 2
+    }
+
+    // The rooks that can still castle, one day if not right now. Moving the
+    // king gives up both rights, and moving or losing a rook gives up its own.
+    pub fn castling_rights(&self) -> Vec<Coordinate> {
+        let removed = |rook: &Coordinate| self.consequences
+            .iter()
+            .any(|consequences| consequences.removed.iter().any(|&(coordinate, _)| coordinate == *rook));
+
+        self.castling
+            .iter()
+            .filter(|rook| {
+                let side = if rook.row() == self.board.home_row(Side::White) { Side::White } else { Side::Black };
+                let king = Piece::pack(side, Rank::King);
+
+                !self.history.iter().any(|action| match *action {
+                    Action::Castle(piece, _, _, _) => piece == king,
+                    Action::Drop(..) => false,
+                    Action::MovePiece(piece, from, to) | Action::Capture(piece, _, from, to) | Action::Promotion(piece, _, _, from, to) =>
+                        piece == king || from == **rook || to == **rook,
+                }) && !removed(rook)
+            })
+            .cloned()
+            .collect()
+    }
+
     pub fn history(&self) -> &Vec<Action> {
         &self.history
     }
 
+    pub fn variant(&self) -> &'static dyn Variant {
+        self.variant
+    }
+
+    pub fn pockets(&self) -> &Pockets {
+        &self.pockets
+    }
+
+    // Whether the piece on the square got there by promotion, following it
+    // back through the history. Captured promoted pieces go back to being
+    // pawns in a Crazyhouse pocket.
+    pub fn is_promoted(&self, square: Coordinate) -> bool {
+        self.traces_back_to_promotion(square, &self.history)
+    }
+
+    // Whether the piece the last move captured had been promoted.
+    pub fn captured_promoted_piece(&self) -> bool {
+        match self.history.split_last() {
+            Some((last, earlier)) if captured_piece_for(last).is_some() => self.traces_back_to_promotion(*to_coordinate_for(last), earlier),
+            _ => false,
+        }
+    }
+
+    fn traces_back_to_promotion(&self, square: Coordinate, history: &[Action]) -> bool {
+        let mut square = square;
+        for action in history.iter().rev() {
+            match *action {
+                Action::Promotion(_, _, _, _, to) if to == square => return true,
+                Action::MovePiece(_, from, to) | Action::Capture(_, _, from, to) if to == square => square = from,
+                Action::Castle(_, _, to, _) | Action::Drop(_, to) if to == square => return false,
+                Action::Castle(_, _, to, rook) if castled_rook_coordinate(&to) == square => square = rook,
+                _ => {}
+            }
+        }
+
+        self.promoted.contains(&square)
+    }
+
+    // How many times the side has given check, which only variants that
+    // count checks keep track of.
+    pub fn checks_given(&self, side: Side) -> usize {
+        self.history
+            .iter()
+            .zip(self.consequences.iter())
+            .filter(|&(action, consequences)| consequences.gave_check && notation::moving_piece(action).side() == side)
+            .count()
+    }
+
+    // The position before any of the moves in the history were played.
+    pub fn starting_position(&self) -> GameState {
+        let mut start = self.clone();
+        while !start.history.is_empty() {
+            start.undo().expect("Could not rewind the game");
+        }
+        start
+    }
+
     pub fn next_to_move(&self) -> Side {
         self.next_to_move
     }
@@ -50,20 +265,151 @@ impl GameState {
         engine::possible_actions(&coordinate, self)
     }
 
+    // Every move the side to move can make.
+    pub fn legal_moves(&mut self) -> Vec<Action> {
+        engine::all_possible_actions(self)
+    }
+
+    // The move in SAN, as played from this position.
+    pub fn san(&mut self, action: &Action) -> String {
+        notation::to_san(action, self)
+    }
+
+    // Reads a move in SAN or long algebraic notation.
+    pub fn parse_move(&mut self, text: &str) -> Result<Action, MoveError> {
+        notation::parse_move(text, self)
+    }
+
+    // The opposing pieces the side has captured so far, in order.
+    pub fn captured_by(&self, side: Side) -> Vec<Piece> {
+        self.history
+            .iter()
+            .filter_map(|action| match *action {
+                Action::Capture(capturer, target, _, _) if capturer.side() == side => Some(target),
+                _ => None,
+            })
+            .collect()
+    }
+
     pub fn has_completed(&mut self) -> bool {
+        self.outcome().is_some()
+    }
+
+    // How the game ended over the board, if it has. Time is up to the Game.
+    pub fn outcome(&mut self) -> Option<Outcome> {
+        if let Some(winner) = self.variant.winner(self) {
+            return Some(Outcome::VariantWin(winner))
+        }
+
+        match self.status() {
+            Status::Checkmate => Some(Outcome::Checkmate(!self.next_to_move)),
+            Status::Stalemate => Some(Outcome::Stalemate),
+            Status::InProgress | Status::Check => None,
+        }
+    }
+
+    // Whether the side has more than a lone king, or a king and a single
+    // knight or bishop.
+    pub fn has_mating_material(&self, side: Side) -> bool {
+        let pieces: Vec<Piece> = self.board.pieces_with_coordinates()
+            .into_iter()
+            .map(|(_coordinate, piece)| piece)
+            .filter(|piece| piece.side() == side && piece.rank() != Rank::King)
+            .collect();
+
+        match pieces.len() {
+            0 => false,
+            1 => pieces[0].rank() != Rank::Knight && pieces[0].rank() != Rank::Bishop,
+            _ => true,
+        }
+    }
+
+    // How things stand for the side to move.
+    pub fn status(&mut self) -> Status {
         let side = self.next_to_move();
-        engine::is_in_checkmate(self, side)
+
+        if engine::is_in_checkmate(self, side) {
+            Status::Checkmate
+        } else if engine::is_in_stalemate(self, side) {
+            Status::Stalemate
+        } else if engine::is_in_check(self, side) {
+            Status::Check
+        } else {
+            Status::InProgress
+        }
     }
 
     pub fn peek_into_the_future(&self) -> GameState {
         GameState {
             next_to_move: !self.next_to_move,
             history: self.history.clone(),
-            board: self.board.clone()
+            board: self.board.clone(),
+            setup_counters: self.setup_counters,
+            castling: self.castling.clone(),
+            variant: self.variant,
+            consequences: self.consequences.clone(),
+            pockets: self.pockets.clone(),
+            promoted: self.promoted.clone(),
         }
     }
 
-    pub fn advance(&mut self, action: Action) -> Result<(), String> {
+    // Makes the move, as long as it is legal and the pieces in it are the
+    // ones on the board. undo unmakes it again.
+    pub fn play(&mut self, action: Action) -> Result<(), MoveError> {
+        self.check_legal(&action)?;
+        self.advance_unchecked(action);
+        Ok(())
+    }
+
+    // The name play had before it was added, kept so code outside the crate
+    // still builds.
+    #[deprecated(note = "use play, which does the same")]
+    pub fn advance(&mut self, action: Action) -> Result<(), MoveError> {
+        self.play(action)
+    }
+
+    fn check_legal(&mut self, action: &Action) -> Result<(), MoveError> {
+        let from = *from_coordinate_for(action);
+        self.board.check(&from)?;
+        self.board.check(to_coordinate_for(action))?;
+
+        let reason = match (*action, *self.piece_at(from)) {
+            _ if self.variant.winner(self).is_some() => Some(IllegalReason::GameOver),
+            (Action::Drop(piece, _), _) if piece.side() != self.next_to_move => Some(IllegalReason::NotYourTurn),
+            (Action::Drop(..), Some(_)) => Some(IllegalReason::NotPossible),
+            (Action::Drop(..), None) => {
+                if !self.variant.drops(self).contains(action) {
+                    Some(IllegalReason::NotPossible)
+                } else if engine::leads_out_of_check(action, self) {
+                    None
+                } else {
+                    Some(IllegalReason::LeavesKingInCheck)
+                }
+            },
+            (_, None) => Some(IllegalReason::EmptySquare),
+            (_, Some(piece)) if piece.side() != self.next_to_move => Some(IllegalReason::NotYourTurn),
+            (_, Some(_)) => {
+                let possible = engine::enumerate_all_actions(&from, self);
+                if possible.contains(action) {
+                    if engine::leads_out_of_check(action, self) { None } else { Some(IllegalReason::LeavesKingInCheck) }
+                } else if possible.iter().any(|other| to_coordinate_for(other) == to_coordinate_for(action)) {
+                    Some(IllegalReason::WrongPiece)
+                } else {
+                    Some(IllegalReason::NotPossible)
+                }
+            }
+        };
+
+        match reason {
+            Some(reason) => Err(MoveError::IllegalMove { action: *action, reason }),
+            None => Ok(()),
+        }
+    }
+
+    // Plays the action without asking whether it is legal, for the search
+    // and for checking whether a move leaves the king in check. Anything
+    // else should use play: a bad action here corrupts the board.
+    pub fn advance_unchecked(&mut self, action: Action) {
         match action {
             Action::MovePiece(piece, from, to) => {
                 self
@@ -76,8 +422,6 @@ impl GameState {
                     Action::MovePiece(piece.clone(), from.clone(), to.clone()),
                     );
                 self.toggle_side();
-
-                Ok(())
             }
             Action::Capture(capturer, target, from, to) => {
                 self
@@ -93,52 +437,105 @@ impl GameState {
                         to.clone(),
                         ));
                 self.toggle_side();
-
-                Ok(())
             }
-            _ => unimplemented!(),
+            Action::Castle(king, from, to, rook) => {
+                let rook_piece = self.piece_at(rook).expect("Bad move found. Bug");
+                self.update_board(&from, None).expect("Bad move found. Bug");
+                self.update_board(&rook, None).expect("Bad move found. Bug");
+                self.update_board(&to, Some(king)).expect("Bad move found. Bug");
+                self.update_board(&castled_rook_coordinate(&to), Some(rook_piece)).expect("Bad move found. Bug");
+                self.add_action_to_history(action);
+                self.toggle_side();
+            }
+            Action::Promotion(_pawn, promoted_to, _captured, from, to) => {
+                self.update_board(&to, Some(promoted_to)).expect("Bad move found. Bug");
+                self.update_board(&from, None).expect("Bad move found. Bug");
+                self.add_action_to_history(action);
+                self.toggle_side();
+            }
+            Action::Drop(piece, to) => {
+                self.update_board(&to, Some(piece)).expect("Bad move found. Bug");
+                self.pockets.take(piece);
+                self.add_action_to_history(action);
+                self.toggle_side();
+            }
+        }
+
+        let consequences = self.variant.consequences(&action, self);
+        for &(coordinate, _piece) in &consequences.removed {
+            self.update_board(&coordinate, None).expect("Bad move found. Bug");
         }
+        if let Some(piece) = consequences.pocketed {
+            self.pockets.add(piece);
+        }
+        self.consequences.push(consequences);
     }
 
-    pub fn undo(&mut self) -> Result<(), String> {
+    pub fn undo(&mut self) -> Result<(), MoveError> {
+        // Pieces the variant took away go back before the move is unmade,
+        // which puts back the ones it moved
+        if let Some(consequences) = self.consequences.pop() {
+            for &(coordinate, piece) in &consequences.removed {
+                self.update_board(&coordinate, Some(piece))?;
+            }
+            if let Some(piece) = consequences.pocketed {
+                self.pockets.take(piece);
+            }
+        }
+
         let last_action = self.history.pop();
         match last_action {
             Some(Action::MovePiece(piece, from, to)) => {
-                self
-                    .update_board(&from, Some(piece.clone()))
-                    .expect("Bad move found. Bug");
-                self
-                    .update_board(&to, None)
-                    .expect("Bad move found. Bug");
+                self.update_board(&from, Some(piece.clone()))?;
+                self.update_board(&to, None)?;
                 self.toggle_side();
 
                 Ok(())
             },
             Some(Action::Capture(capturer, target, from, to)) => {
-                self
-                    .update_board(&from, Some(capturer.clone()))
-                    .expect("Bad move found. Bug");
-                self
-                    .update_board(&to, Some(target.clone()))
-                    .expect("Bad move found. Bug");
+                self.update_board(&from, Some(capturer.clone()))?;
+                self.update_board(&to, Some(target.clone()))?;
                 self.toggle_side();
 
                 Ok(())
             }
-            None => Ok(()),
-            _ => unimplemented!(),
+            Some(Action::Castle(king, from, to, rook)) => {
+                let rook_to = castled_rook_coordinate(&to);
+                let rook_piece = *self.piece_at(rook_to);
+                self.update_board(&to, None)?;
+                self.update_board(&rook_to, None)?;
+                self.update_board(&from, Some(king))?;
+                self.update_board(&rook, rook_piece)?;
+                self.toggle_side();
+
+                Ok(())
+            }
+            Some(Action::Promotion(pawn, _promoted_to, captured, from, to)) => {
+                self.update_board(&from, Some(pawn))?;
+                self.update_board(&to, captured)?;
+                self.toggle_side();
+
+                Ok(())
+            }
+            Some(Action::Drop(piece, to)) => {
+                self.update_board(&to, None)?;
+                self.pockets.add(piece);
+                self.toggle_side();
 
+                Ok(())
+            }
+            None => Ok(()),
         }
     }
 
-    pub fn evaluate_with_action<F, T>(&mut self, action: Action, evaluation_fn: F) -> T where F: Fn(&mut GameState) -> T {
-        self.advance(action).expect("Invalid action");
+    pub fn evaluate_with_action<F, T>(&mut self, action: Action, evaluation_fn: F) -> T where F: FnOnce(&mut GameState) -> T {
+        self.advance_unchecked(action);
         let evaluation_result = evaluation_fn(self);
         self.undo().expect("Invalid action");
         evaluation_result
     }
 
-    fn update_board(&mut self, coordinate: &Coordinate, piece: Option<Piece>) -> Result<(), String> {
+    fn update_board(&mut self, coordinate: &Coordinate, piece: Option<Piece>) -> Result<(), InvalidCoordinate> {
        self.board.update(coordinate, piece)
     }
 
@@ -154,19 +551,51 @@ impl GameState {
     }
 }
 
+impl Default for GameState {
+    fn default() -> GameState {
+        GameState::new()
+    }
+}
+
 #[derive(PartialEq, Debug)]
 pub struct Game {
     current_state: GameState,
+    // Undone actions, most recently undone last
+    redo_stack: Vec<Action>,
+    clock: Option<Clock>,
 }
 
 #[allow(dead_code)] // TODO: Remove
 impl Game {
     pub fn new() -> Game {
+        Game::starting_from(GameState::new())
+    }
+
+    // A game from a position other than the usual one, like a Chess960
+    // start or a FEN.
+    pub fn starting_from(state: GameState) -> Game {
         Game {
-            current_state: GameState::new(),
+            current_state: state,
+            redo_stack: vec![],
+            clock: None,
         }
     }
 
+    // Plays with a clock, starting the time for the side to move.
+    pub fn with_clock(mut self, clock: Clock) -> Game {
+        self.set_clock(clock);
+        self
+    }
+
+    pub fn set_clock(&mut self, mut clock: Clock) {
+        clock.start(self.current_turn(), Instant::now());
+        self.clock = Some(clock);
+    }
+
+    pub fn clock(&self) -> Option<&Clock> {
+        self.clock.as_ref()
+    }
+
     pub fn current_turn(&self) -> Side {
         self.current_state.next_to_move
     }
@@ -187,19 +616,103 @@ impl Game {
         &mut self.current_state
     }
 
-    pub fn advance(&mut self, action: Action) -> Result<(), String> {
-        self.current_state.advance(action)
+    // Playing a new move abandons anything that could have been redone.
+    pub fn advance(&mut self, action: Action) -> Result<(), MoveError> {
+        self.current_state.play(action)?;
+        self.redo_stack.clear();
+        if let Some(ref mut clock) = self.clock {
+            clock.press(Instant::now());
+        }
+        Ok(())
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history().is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    // Takes back the last action, returning it if there was one.
+    pub fn undo(&mut self) -> Result<Option<Action>, MoveError> {
+        let last_action = match self.history().last() {
+            Some(action) => *action,
+            None => return Ok(None),
+        };
+
+        self.current_state.undo()?;
+        self.redo_stack.push(last_action);
+        let side = self.current_turn();
+        if let Some(ref mut clock) = self.clock {
+            clock.take_back(side, Instant::now());
+        }
+        self.hand_clock_to_side_to_move();
+        Ok(Some(last_action))
+    }
+
+    // Replays the most recently undone action, returning it if there was one.
+    pub fn redo(&mut self) -> Result<Option<Action>, MoveError> {
+        match self.redo_stack.pop() {
+            Some(action) => {
+                let side = self.current_turn();
+                self.current_state.play(action)?;
+                if let Some(ref mut clock) = self.clock {
+                    clock.replay(side, Instant::now());
+                }
+                self.hand_clock_to_side_to_move();
+                Ok(Some(action))
+            },
+            None => Ok(None),
+        }
+    }
+
+    // Notices a flag falling. Returns whether it just did.
+    pub fn check_flag(&mut self) -> bool {
+        match self.clock {
+            Some(ref mut clock) => clock.check_flag(Instant::now()),
+            None => false,
+        }
+    }
+
+    pub fn outcome(&mut self) -> Option<Outcome> {
+        if let Some(flagged) = self.clock.as_ref().and_then(|clock| clock.flagged()) {
+            return if self.current_state.has_mating_material(!flagged) {
+                Some(Outcome::Timeout(!flagged))
+            } else {
+                Some(Outcome::TimeoutVsInsufficientMaterial)
+            }
+        }
+
+        self.current_state.outcome()
     }
 
     pub fn has_completed(&mut self) -> bool {
-        self.current_state.has_completed()
+        self.outcome().is_some()
+    }
+
+    // Restarts the clock for whoever is to move after a take back or replay.
+    // The clock has already taken off or given back what those moves earned.
+    fn hand_clock_to_side_to_move(&mut self) {
+        let side = self.current_turn();
+        if let Some(ref mut clock) = self.clock {
+            clock.start(side, Instant::now());
+        }
+    }
+}
+
+impl Default for Game {
+    fn default() -> Game {
+        Game::new()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use piece::Rank;
+    use rand::{Rng, XorShiftRng};
+    use std::thread;
+    use std::time::Duration;
 
     macro_rules! coord {
         ($x:expr) => { Coordinate::from_human($x.to_string()).unwrap() }
@@ -235,7 +748,7 @@ mod tests {
     fn can_undo_moves() {
         let mut state = GameState::new();
 
-        state.advance(Action::MovePiece(
+        state.play(Action::MovePiece(
             Piece::pack(Side::White, Rank::Pawn),
             Coordinate::from_human("e2".to_owned()).unwrap(),
             Coordinate::from_human("e3".to_owned()).unwrap(),
@@ -257,7 +770,7 @@ mod tests {
 
         let mut new_state = state.clone();
 
-        new_state.advance(Action::Capture(
+        new_state.play(Action::Capture(
             Piece::pack(Side::White, Rank::Queen),
             Piece::pack(Side::Black, Rank::Rook),
             Coordinate::from_human("h8".to_owned()).unwrap(),
@@ -269,6 +782,113 @@ mod tests {
         assert_eq!(new_state, state);
     }
 
+    #[test]
+    fn tracks_captured_pieces_per_side() {
+        let mut board = Board::empty();
+        board.update(&coord!("a2"), Some(Piece::pack(Side::White, Rank::King))).unwrap();
+        board.update(&coord!("h1"), Some(Piece::pack(Side::Black, Rank::King))).unwrap();
+        board.update(&coord!("h8"), Some(Piece::pack(Side::White, Rank::Queen))).unwrap();
+        board.update(&coord!("a1"), Some(Piece::pack(Side::Black, Rank::Rook))).unwrap();
+        let mut state = GameState::with_board(board);
+
+        state.play(Action::Capture(
+            Piece::pack(Side::White, Rank::Queen),
+            Piece::pack(Side::Black, Rank::Rook),
+            coord!("h8"),
+            coord!("a1"),
+        )).unwrap();
+
+        assert_eq!(state.captured_by(Side::White), vec![Piece::pack(Side::Black, Rank::Rook)]);
+        assert_eq!(state.captured_by(Side::Black), vec![]);
+    }
+
+    #[test]
+    fn castles_and_takes_castling_back() {
+        let mut state = GameState::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let before = state.clone();
+
+        let castle = state.parse_move("O-O-O").unwrap();
+        state.play(castle).unwrap();
+        assert_eq!(*state.piece_at(coord!("c1")), Some(Piece::pack(Side::White, Rank::King)));
+        assert_eq!(*state.piece_at(coord!("d1")), Some(Piece::pack(Side::White, Rank::Rook)));
+        assert_eq!(state.to_fen(), "r3k2r/8/8/8/8/8/8/2KR3R b kq - 1 1");
+
+        state.undo().unwrap();
+        assert_eq!(state, before);
+    }
+
+    #[test]
+    fn moving_the_king_or_a_rook_gives_up_castling() {
+        let mut state = GameState::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        for text in &["Rb1", "Kf8", "Rb2"] {
+            let action = state.parse_move(text).unwrap();
+            state.play(action).unwrap();
+        }
+
+        assert_eq!(state.castling_rights(), vec![coord!("h1")]);
+        assert_eq!(state.to_fen(), "r4k1r/8/8/8/8/8/1R6/4K2R b K - 3 2");
+
+        // Losing the rook to a capture gives it up too
+        let mut state = GameState::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let action = state.parse_move("Rxa8").unwrap();
+        state.play(action).unwrap();
+        assert_eq!(state.castling_rights(), vec![coord!("h1"), coord!("h8")]);
+    }
+
+    #[test]
+    fn cannot_castle_out_of_or_through_check() {
+        // First the bishop covers f1, then it checks the king
+        let mut state = GameState::from_fen("4k3/8/8/8/8/8/6b1/R3K2R w KQ - 0 1").unwrap();
+        assert!(state.parse_move("O-O").is_err());
+        assert!(state.parse_move("O-O-O").is_ok());
+
+        let mut state = GameState::from_fen("4k3/8/8/8/1b6/8/8/R3K2R w KQ - 0 1").unwrap();
+        assert!(state.parse_move("O-O").is_err());
+        assert!(state.parse_move("O-O-O").is_err());
+    }
+
+    #[test]
+    fn starts_chess960_games_with_both_rooks_able_to_castle() {
+        let state = GameState::starting_from(Board::chess960(0).unwrap());
+
+        assert_eq!(state.to_fen(), "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1");
+        assert_eq!(state.castling_rights(), vec![coord!("h1"), coord!("f1"), coord!("h8"), coord!("f8")]);
+    }
+
+    #[test]
+    fn games_can_undo_and_redo_moves() {
+        let mut game = Game::new();
+        let e4 = Action::MovePiece(Piece::pack(Side::White, Rank::Pawn), coord!("e2"), coord!("e4"));
+        let e5 = Action::MovePiece(Piece::pack(Side::Black, Rank::Pawn), coord!("e7"), coord!("e5"));
+        game.advance(e4).unwrap();
+        game.advance(e5).unwrap();
+
+        assert_eq!(game.undo().unwrap(), Some(e5));
+        assert_eq!(game.undo().unwrap(), Some(e4));
+        assert_eq!(game.undo().unwrap(), None);
+        assert_eq!(*game.state(), GameState::new());
+
+        assert_eq!(game.redo().unwrap(), Some(e4));
+        assert_eq!(game.redo().unwrap(), Some(e5));
+        assert_eq!(game.redo().unwrap(), None);
+        assert_eq!(game.history(), &vec![e4, e5]);
+    }
+
+    #[test]
+    fn playing_a_move_clears_the_redo_stack() {
+        let mut game = Game::new();
+        let e4 = Action::MovePiece(Piece::pack(Side::White, Rank::Pawn), coord!("e2"), coord!("e4"));
+        let d4 = Action::MovePiece(Piece::pack(Side::White, Rank::Pawn), coord!("d2"), coord!("d4"));
+        game.advance(e4).unwrap();
+        game.undo().unwrap();
+
+        assert!(game.can_redo());
+        game.advance(d4).unwrap();
+
+        assert!(!game.can_redo());
+        assert_eq!(game.history(), &vec![d4]);
+    }
+
     #[test]
     fn undoing_without_a_history_is_a_noop() {
         let mut state = GameState::new();
@@ -277,4 +897,180 @@ mod tests {
 
         assert_eq!(state, GameState::new());
     }
+
+    #[test]
+    fn knows_when_a_side_cannot_mate() {
+        let mut board = Board::empty();
+        board.update(&coord!("e1"), Some(Piece::pack(Side::White, Rank::King))).unwrap();
+        board.update(&coord!("e8"), Some(Piece::pack(Side::Black, Rank::King))).unwrap();
+        board.update(&coord!("c8"), Some(Piece::pack(Side::Black, Rank::Bishop))).unwrap();
+        board.update(&coord!("a2"), Some(Piece::pack(Side::White, Rank::Pawn))).unwrap();
+        let state = GameState::with_board(board);
+
+        assert!(state.has_mating_material(Side::White));
+        assert!(!state.has_mating_material(Side::Black));
+        assert!(GameState::new().has_mating_material(Side::Black));
+    }
+
+    #[test]
+    fn refuses_to_play_illegal_moves() {
+        let pawn = Piece::pack(Side::White, Rank::Pawn);
+        let knight = Piece::pack(Side::White, Rank::Knight);
+        let mut state = GameState::new();
+
+        let illegal = |action, reason| Err(MoveError::IllegalMove { action, reason });
+        let e3e4 = Action::MovePiece(pawn, coord!("e3"), coord!("e4"));
+        let e7e5 = Action::MovePiece(Piece::pack(Side::Black, Rank::Pawn), coord!("e7"), coord!("e5"));
+        let e2e4_knight = Action::MovePiece(knight, coord!("e2"), coord!("e4"));
+        let e2e5 = Action::MovePiece(pawn, coord!("e2"), coord!("e5"));
+        let off_the_board = Action::MovePiece(pawn, coord!("e2"), Coordinate::new(8, 4));
+
+        assert_eq!(state.play(e3e4), illegal(e3e4, IllegalReason::EmptySquare));
+        assert_eq!(state.play(e7e5), illegal(e7e5, IllegalReason::NotYourTurn));
+        assert_eq!(state.play(e2e4_knight), illegal(e2e4_knight, IllegalReason::WrongPiece));
+        assert_eq!(state.play(e2e5), illegal(e2e5, IllegalReason::NotPossible));
+        assert_eq!(state.play(off_the_board), Err(MoveError::InvalidCoordinate(InvalidCoordinate::OffTheBoard { row: 8, column: 4 })));
+        assert_eq!(state, GameState::new());
+
+        let mut pinned = GameState::from_fen("4k3/4r3/8/8/8/8/4B3/4K3 w - -").unwrap();
+        let bd3 = Action::MovePiece(Piece::pack(Side::White, Rank::Bishop), coord!("e2"), coord!("d3"));
+        assert_eq!(pinned.play(bd3), illegal(bd3, IllegalReason::LeavesKingInCheck));
+        assert_eq!(pinned.play(bd3).unwrap_err().to_string(), "Illegal move e2d3: it leaves the king in check");
+    }
+
+    // What any position reached by legal moves looks like: one king each,
+    // no more pieces than it started with, and no way to take a king.
+    fn assert_valid(state: &mut GameState, pieces_at_start: usize) {
+        for side in &[Side::White, Side::Black] {
+            assert_eq!(state.board().find_pieces(Piece::pack(*side, Rank::King)).len(), 1, "{}", state.to_fen());
+        }
+        assert!(state.board().pieces_with_coordinates().len() <= pieces_at_start, "{}", state.to_fen());
+
+        for action in state.legal_moves() {
+            if let Action::Capture(_, target, _, _) = action {
+                assert!(target.rank() != Rank::King, "{}", state.to_fen());
+            }
+        }
+    }
+
+    #[test]
+    fn random_legal_games_never_break_the_board() {
+        let mut rng = XorShiftRng::new_unseeded();
+
+        for _ in 0..8 {
+            let mut state = GameState::new();
+            let pieces_at_start = state.board().pieces_with_coordinates().len();
+
+            for _ in 0..40 {
+                let moves = state.legal_moves();
+                let action = match rng.choose(&moves) {
+                    Some(action) => *action,
+                    None => break,
+                };
+                let side = state.next_to_move();
+
+                state.play(action).unwrap();
+                assert_eq!(state.next_to_move(), !side);
+                assert_valid(&mut state, pieces_at_start);
+            }
+
+            while !state.history().is_empty() {
+                state.undo().unwrap();
+            }
+            assert_eq!(state, GameState::new());
+        }
+    }
+
+    #[test]
+    fn random_actions_are_only_played_when_legal() {
+        // Every kind of piece, from both sides
+        let pieces: Vec<Piece> = Board::default().pieces_with_coordinates().into_iter().map(|(_, piece)| piece).collect();
+        let mut rng = XorShiftRng::new_unseeded();
+        let mut state = GameState::new();
+        let (mut played, mut refused) = (0, 0);
+
+        for _ in 0..200 {
+            let moves = state.legal_moves();
+            let piece = *rng.choose(&pieces).unwrap();
+            let from = Coordinate::new(rng.gen_range(0, 8), rng.gen_range(0, 8));
+            let to = Coordinate::new(rng.gen_range(0, 8), rng.gen_range(0, 8));
+            // A legal move, a legal move made by some other piece, or
+            // anything at all
+            let action = match (rng.gen_range(0, 3), rng.choose(&moves)) {
+                (0, Some(&action)) => action,
+                (1, Some(&Action::MovePiece(_, from, to))) => Action::MovePiece(piece, from, to),
+                _ => match *state.piece_at(to) {
+                    Some(target) => Action::Capture(piece, target, from, to),
+                    None => Action::MovePiece(piece, from, to),
+                },
+            };
+
+            let legal = moves.contains(&action);
+            let before = state.clone();
+            match state.play(action) {
+                Ok(()) => {
+                    assert!(legal, "Played {:?} in {}", action, before.to_fen());
+                    played += 1;
+                },
+                Err(_) => {
+                    assert!(!legal, "Refused {:?} in {}", action, before.to_fen());
+                    assert_eq!(state, before);
+                    refused += 1;
+                }
+            }
+
+            // Keep the game moving so the random actions meet new positions
+            let moves = state.legal_moves();
+            match rng.choose(&moves) {
+                Some(action) => state.play(*action).unwrap(),
+                None => state = GameState::new(),
+            }
+        }
+        assert!(played > 20 && refused > 20, "{} played, {} refused", played, refused);
+    }
+
+    #[test]
+    fn counts_moves_in_fen_from_where_the_position_was_set_up() {
+        let mut state = GameState::new();
+        for text in &["e4", "e5", "Nf3"] {
+            let action = state.parse_move(text).unwrap();
+            state.play(action).unwrap();
+        }
+        assert_eq!(state.to_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+
+        let mut state = GameState::from_fen("4k3/8/8/8/8/8/8/R3K3 b - - 7 30").unwrap();
+        let action = state.parse_move("Kd7").unwrap();
+        state.play(action).unwrap();
+        assert_eq!(state.to_fen(), "8/3k4/8/8/8/8/8/R3K3 w - - 8 31");
+    }
+
+    #[test]
+    fn takebacks_rewind_the_clock() {
+        let mut game = Game::new().with_clock(Clock::new("1/1+5:1".parse().unwrap()));
+        let e4 = game.state_mut().parse_move("e4").unwrap();
+        game.advance(e4).unwrap();
+        game.undo().unwrap();
+        game.advance(e4).unwrap();
+
+        let clock = game.clock().unwrap();
+        let white = clock.remaining(Side::White, Instant::now());
+        assert_eq!(clock.moves_made(Side::White), 1);
+        assert!(white > Duration::from_secs(120) && white <= Duration::from_secs(125), "{:?}", white);
+
+        game.undo().unwrap();
+        game.redo().unwrap();
+        let clock = game.clock().unwrap();
+        assert_eq!(clock.moves_made(Side::White), 1);
+        assert!(clock.remaining(Side::White, Instant::now()) <= Duration::from_secs(125));
+    }
+
+    #[test]
+    fn running_out_of_time_loses_the_game() {
+        let mut game = Game::new().with_clock(Clock::new("0.0001".parse().unwrap()));
+        thread::sleep(Duration::from_millis(20));
+
+        assert!(game.check_flag());
+        assert_eq!(game.outcome(), Some(Outcome::Timeout(Side::Black)));
+        assert!(game.has_completed());
+    }
 }
@@ -0,0 +1,91 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use termion::event::{Event, Key};
+use termion::input::TermRead;
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum Input {
+    Event(Event),
+    // Nothing happened before the timeout
+    Idle,
+    // There will never be any more input
+    Closed,
+}
+
+// Where a session gets its keys and clicks from.
+pub trait InputSource {
+    fn next_input(&mut self, timeout: Duration) -> Input;
+}
+
+// Keys and mouse events from the terminal. They are read on their own
+// thread so the session can also react to things happening in the
+// background.
+pub struct TerminalInput {
+    receiver: Receiver<Event>,
+}
+
+impl TerminalInput {
+    pub fn new() -> TerminalInput {
+        let (sender, receiver) = channel();
+
+        thread::spawn(move || {
+            for event in ::std::io::stdin().events() {
+                match event {
+                    Ok(event) => if sender.send(event).is_err() { break },
+                    Err(_) => break,
+                }
+            }
+        });
+
+        TerminalInput { receiver }
+    }
+}
+
+impl Default for TerminalInput {
+    fn default() -> TerminalInput {
+        TerminalInput::new()
+    }
+}
+
+impl InputSource for TerminalInput {
+    fn next_input(&mut self, timeout: Duration) -> Input {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(event) => Input::Event(event),
+            Err(RecvTimeoutError::Timeout) => Input::Idle,
+            Err(RecvTimeoutError::Disconnected) => Input::Closed,
+        }
+    }
+}
+
+// A fixed sequence of events, handed out one at a time without waiting.
+// Closes once they run out.
+pub struct ScriptedInput {
+    events: VecDeque<Event>,
+}
+
+impl ScriptedInput {
+    pub fn new(events: Vec<Event>) -> ScriptedInput {
+        ScriptedInput { events: events.into_iter().collect() }
+    }
+
+    // Each character is typed as a key, with "\n" for enter.
+    pub fn typing(keys: &str) -> ScriptedInput {
+        ScriptedInput::new(keys.chars().map(|key| Event::Key(Key::Char(key))).collect())
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.events.len()
+    }
+}
+
+impl InputSource for ScriptedInput {
+    fn next_input(&mut self, _timeout: Duration) -> Input {
+        match self.events.pop_front() {
+            Some(event) => Input::Event(event),
+            None => Input::Closed,
+        }
+    }
+}
@@ -1,6 +1,14 @@
 #![cfg_attr(feature = "strict", deny(warnings))]
 
 extern crate termion;
+extern crate rand;
+extern crate libc;
+extern crate toml;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 mod piece;
 mod game;
 mod board;
@@ -9,9 +17,43 @@ mod ui;
 mod session;
 mod action;
 mod ai;
+mod polyglot;
+mod notation;
+mod analysis;
+mod theme;
+mod input;
+mod clock;
+mod save;
+mod fen;
+mod error;
+mod variant;
+mod pocket;
+#[cfg(feature = "serde")]
+mod serialization;
 
 use std::fmt;
-use session::Session;
+
+// The rules: a Position can list its legal moves, make and unmake them
+// with advance and undo, say how the game stands and read or write FEN and
+// SAN. Game adds a clock and takebacks on top.
+pub use game::{Game, GameState as Position, Outcome, Status};
+pub use action::Action as Move;
+pub use board::{Board, Coordinate, MAX_SIZE};
+pub use piece::{Piece, Rank};
+pub use fen::FenError;
+pub use error::{IllegalReason, InvalidCoordinate, MoveError};
+pub use variant::{variant_named, Atomic, Capablanca, Consequences, Crazyhouse, Horde, KingOfTheHill, Standard, ThreeCheck, Variant};
+pub use pocket::Pockets;
+#[cfg(feature = "serde")]
+pub use serialization::ActionSeed as MoveSeed;
+
+pub use ai::{Ai, Level};
+pub use polyglot::Book;
+pub use theme::Theme;
+pub use session::{Hint, Session, SessionState};
+pub use clock::{Clock, TimeControl};
+pub use input::{Input, InputSource, ScriptedInput, TerminalInput};
+pub use save::{autosave_path, load, save};
 
 use std::ops::Not;
 
@@ -19,7 +61,12 @@ pub fn new_session() -> Session {
     Session::new()
 }
 
+pub fn new_ai_session(ai: Ai) -> Session {
+    Session::against_ai(ai)
+}
+
 #[derive(PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Side {
     White,
     Black,
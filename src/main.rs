@@ -1,7 +1,11 @@
 extern crate terminal_chess;
+#[macro_use]
 extern crate clap;
 
-use clap::{App, SubCommand};
+use std::path::PathBuf;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use terminal_chess::{variant_named, Ai, Board, Book, Game, Level, Position, Session, Theme, TimeControl};
 
 fn main() {
     let app = App::new("Terminal Chess")
@@ -9,18 +13,170 @@ fn main() {
                           .author("Niklas Begley")
                           .about("Play chess in your terminal.")
                           .subcommand(SubCommand::with_name("new")
-                                      .about("Host a new game that can be joined by another player."))
+                                      .about("Host a new game that can be joined by another player.")
+                                      .arg(time_arg())
+                                      .arg(save_arg())
+                                      .arg(variant_arg())
+                                      .arg(position_arg()))
                           .subcommand(SubCommand::with_name("join")
-                                      .about("Joing an existing game over the network."))
+                                      .about("Joing an existing game over the network.")
+                                      .arg(time_arg()))
                           .subcommand(SubCommand::with_name("ai")
-                                      .about("Play against a simple AI."));
+                                      .about("Play against a simple AI.")
+                                      .arg(time_arg())
+                                      .arg(save_arg())
+                                      .arg(variant_arg())
+                                      .arg(position_arg())
+                                      .arg(Arg::with_name("book")
+                                           .long("book")
+                                           .value_name("FILE")
+                                           .help("Polyglot (.bin) opening book for the AI to play from")
+                                           .takes_value(true))
+                                      .arg(Arg::with_name("level")
+                                           .long("level")
+                                           .value_name("LEVEL")
+                                           .help("How strong the AI plays")
+                                           .possible_values(&["beginner", "casual", "intermediate", "advanced", "max"])
+                                           .default_value("max")
+                                           .takes_value(true)))
+                          .subcommand(SubCommand::with_name("resume")
+                                      .about("Carry on with the last game, or a saved one.")
+                                      .arg(save_arg()));
 
     let matches = app.get_matches();
 
-    if let Some(_subcommand_matches) = matches.subcommand_matches("new") {
-        let mut session = terminal_chess::new_session();
-        session.run();
+    let theme = match Theme::load() {
+        Ok(theme) => theme,
+        Err(e) => {
+            eprintln!("{}", e);
+            ::std::process::exit(1);
+        }
+    };
+
+    if let Some(subcommand_matches) = matches.subcommand_matches("new") {
+        let session = terminal_chess::new_session().with_theme(theme);
+        let session = with_variant(session, subcommand_matches);
+        let session = with_time_control(session, subcommand_matches);
+        with_save_file(session, subcommand_matches).run();
+    } else if let Some(subcommand_matches) = matches.subcommand_matches("ai") {
+        let level = value_t!(subcommand_matches, "level", Level).unwrap_or_else(|e| e.exit());
+        let mut ai = Ai::new().with_level(level);
+
+        if let Some(path) = subcommand_matches.value_of("book") {
+            match Book::open(path) {
+                Ok(book) => ai = ai.with_book(book),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    ::std::process::exit(1);
+                }
+            }
+        }
+
+        let session = terminal_chess::new_ai_session(ai).with_theme(theme);
+        let session = with_variant(session, subcommand_matches);
+        let session = with_time_control(session, subcommand_matches);
+        with_save_file(session, subcommand_matches).run();
+    } else if let Some(subcommand_matches) = matches.subcommand_matches("resume") {
+        let path = match save_path(subcommand_matches) {
+            Some(path) => path,
+            None => {
+                eprintln!("Could not find where the game was saved, use --save to name the file");
+                ::std::process::exit(1);
+            }
+        };
+
+        match terminal_chess::load(&path) {
+            Ok(session) => session.with_theme(theme).with_save_file(path).run(),
+            Err(e) => {
+                eprintln!("{}", e);
+                ::std::process::exit(1);
+            }
+        }
+    } else {
+
+    }
+}
+
+fn time_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("time")
+        .long("time")
+        .value_name("CONTROL")
+        .help("Play with a clock, e.g. 5+3 (minutes plus seconds a move), 5d3 (Bronstein delay) or 40/90+30:30+30")
+        .takes_value(true)
+}
+
+fn save_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("save")
+        .long("save")
+        .value_name("FILE")
+        .help("Where to save the game as it is played, instead of the autosave in the data directory")
+        .takes_value(true)
+}
+
+fn variant_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("variant")
+        .long("variant")
+        .value_name("VARIANT")
+        .help("Which chess to play. Chess960 starts from a random Fischer Random position")
+        .possible_values(&["standard", "chess960", "king-of-the-hill", "three-check", "atomic", "horde", "crazyhouse", "capablanca"])
+        .default_value("standard")
+        .takes_value(true)
+}
+
+fn position_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("position")
+        .long("position")
+        .value_name("NUMBER")
+        .help("The Chess960 starting position to play, from 0 to 959 (518 is the usual one)")
+        .takes_value(true)
+}
+
+fn save_path(matches: &ArgMatches) -> Option<PathBuf> {
+    match matches.value_of("save") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => terminal_chess::autosave_path(),
+    }
+}
+
+fn with_save_file(session: Session, matches: &ArgMatches) -> Session {
+    match save_path(matches) {
+        Some(path) => session.with_save_file(path),
+        None => session,
+    }
+}
+
+// Sets up the starting position before anything else, as it replaces the game.
+fn with_variant(session: Session, matches: &ArgMatches) -> Session {
+    match matches.value_of("variant") {
+        Some("standard") | None => return session,
+        Some("chess960") => {},
+        Some(name) => {
+            let variant = variant_named(name).expect("Unknown variant");
+            return session.with_game(Game::starting_from(Position::for_variant(variant)))
+        },
+    }
+
+    let board = if matches.is_present("position") {
+        let number = value_t!(matches, "position", usize).unwrap_or_else(|e| e.exit());
+        match Board::chess960(number) {
+            Some(board) => board,
+            None => {
+                eprintln!("There are 960 starting positions, numbered 0 to 959");
+                ::std::process::exit(1);
+            }
+        }
+    } else {
+        Board::random_chess960()
+    };
+
+    session.with_game(Game::starting_from(Position::starting_from(board)))
+}
+
+fn with_time_control(session: Session, matches: &ArgMatches) -> Session {
+    if matches.is_present("time") {
+        let control = value_t!(matches, "time", TimeControl).unwrap_or_else(|e| e.exit());
+        session.with_clock(control)
     } else {
-        
+        session
     }
 }
@@ -0,0 +1,628 @@
+use Side;
+use game::GameState;
+use action::{Action, from_coordinate_for, to_coordinate_for};
+use piece::{Piece, Rank};
+use board::{Board, Coordinate, MAX_SIZE};
+use engine;
+use error::MoveError;
+
+// Formats a legal action in Standard Algebraic Notation, e.g. "Nbd7",
+// "exd5" or "Qh4#", for the position it is about to be played in.
+pub fn to_san(action: &Action, state: &mut GameState) -> String {
+    let (piece, is_capture) = match *action {
+        Action::MovePiece(piece, _, _) => (piece, false),
+        Action::Capture(piece, _, _, _) => (piece, true),
+        Action::Promotion(piece, _, captured, _, _) => (piece, captured.is_some()),
+        Action::Castle(_, _, to, _) => {
+            let castle = if to.column() == 2 { "O-O-O" } else { "O-O" };
+            return format!("{}{}", castle, state.evaluate_with_action(*action, check_suffix))
+        },
+        Action::Drop(..) => return format!("{}{}", to_long_algebraic(action), state.evaluate_with_action(*action, check_suffix)),
+    };
+    let from = *from_coordinate_for(action);
+    let to = *to_coordinate_for(action);
+
+    let mut san = String::new();
+
+    if piece.rank() == Rank::Pawn {
+        if is_capture {
+            san.push_str(&from.to_human()[0..1]);
+        }
+    } else {
+        san.push_str(rank_letter(&piece.rank()));
+        san.push_str(&disambiguation(action, state));
+    }
+
+    if is_capture {
+        san.push('x');
+    }
+    san.push_str(&to.to_human());
+
+    if let Action::Promotion(_, promoted_to, _, _, _) = *action {
+        san.push('=');
+        san.push_str(rank_letter(&promoted_to.rank()));
+    }
+
+    san.push_str(&state.evaluate_with_action(*action, check_suffix));
+    san
+}
+
+// Formats as many actions of a line as are legal, stopping at the first
+// one that is not (search lines may end by capturing a king).
+pub fn line_to_san(state: &GameState, actions: &[Action]) -> Vec<String> {
+    let mut state = state.clone();
+    let mut sans = vec![];
+
+    for action in actions {
+        let legal = match *action {
+            Action::Drop(..) => engine::possible_drops(&mut state),
+            _ => engine::possible_actions(from_coordinate_for(action), &mut state),
+        };
+        if !legal.contains(action) {
+            break;
+        }
+        sans.push(to_san(action, &mut state));
+        state.play(*action).expect("Illegal move found");
+    }
+
+    sans
+}
+
+// Formats every action played so far, replaying them from the position
+// the game started in.
+pub fn history_to_san(state: &GameState) -> Vec<String> {
+    line_to_san(&state.starting_position(), state.history())
+}
+
+// Pairs up a line of SAN moves starting from the given state under their
+// move numbers, as (number, White's move, Black's move). A line starting
+// with Black to move has no move for White in its first pair.
+pub fn numbered_moves(state: &GameState, sans: &[String]) -> Vec<(usize, Option<String>, Option<String>)> {
+    let mut move_number = state.fullmove_number();
+    let mut sans = sans.iter().cloned();
+    let mut moves = vec![];
+
+    if state.next_to_move() == Side::Black {
+        if let Some(san) = sans.next() {
+            moves.push((move_number, None, Some(san)));
+            move_number += 1;
+        }
+    }
+    while let Some(white) = sans.next() {
+        moves.push((move_number, Some(white), sans.next()));
+        move_number += 1;
+    }
+
+    moves
+}
+
+// Adds move numbers to a line of SAN moves starting from the given state,
+// e.g. "12... e5 13. Nf3".
+pub fn numbered_line(state: &GameState, sans: &[String]) -> String {
+    let parts: Vec<String> = numbered_moves(state, sans)
+        .into_iter()
+        .map(|(move_number, white, black)| {
+            let mut part = match white {
+                Some(san) => format!("{}. {}", move_number, san),
+                None => format!("{}...", move_number),
+            };
+            if let Some(san) = black {
+                part.push(' ');
+                part.push_str(&san);
+            }
+            part
+        })
+        .collect();
+
+    parts.join(" ")
+}
+
+// Resolves a move typed by the player, in SAN ("Nf3", "exd5", "O-O", "e8=Q")
+// or long algebraic notation ("e2e4", "e7e8q"), to one of the legal actions.
+// Drops are written the same in both, as "N@f3", or "@e4" for a pawn.
+pub fn parse_move(input: &str, state: &mut GameState) -> Result<Action, MoveError> {
+    let text = input.trim().trim_matches(|c| c == '+' || c == '#' || c == '!' || c == '?');
+    let legal_actions = engine::all_possible_actions(state);
+
+    let candidates: Vec<Action> = match (parse_drop(text), parse_long_algebraic(text)) {
+        (Some((rank, to)), _) => legal_actions
+            .into_iter()
+            .filter(|action| match *action {
+                Action::Drop(piece, square) => piece.rank() == rank && square == to,
+                _ => false,
+            })
+            .collect(),
+        (None, Some((from, to, promotion))) => {
+            let moves_from: Vec<Action> = legal_actions
+                .into_iter()
+                .filter(|action| *from_coordinate_for(action) == from && promoted_rank(action) == promotion)
+                .collect();
+
+            // Castling is written as the king taking its own rook, but the
+            // king's own move is understood too when nothing else matches
+            let exact: Vec<Action> = moves_from.iter().cloned().filter(|action| match *action {
+                Action::Castle(_, _, _, rook) => rook == to,
+                _ => *to_coordinate_for(action) == to,
+            }).collect();
+
+            if exact.is_empty() {
+                moves_from.into_iter().filter(|action| *to_coordinate_for(action) == to).collect()
+            } else {
+                exact
+            }
+        },
+        (None, None) => {
+            let pattern = parse_san(text, state.board(), state.next_to_move())?;
+            legal_actions
+                .into_iter()
+                .filter(|action| pattern.matches(action))
+                .collect()
+        }
+    };
+
+    match candidates.len() {
+        0 => Err(MoveError::NoSuchMove(input.trim().to_string())),
+        1 => Ok(candidates[0]),
+        _ => Err(MoveError::AmbiguousMove(input.trim().to_string())),
+    }
+}
+
+// The move in long algebraic notation ("e2e4", "e7e8q"), which parse_move
+// reads back without needing to know what else could move there. Castling
+// is the king taking its own rook ("e1h1"), as in Chess960, so it can't be
+// mistaken for a king move.
+pub fn to_long_algebraic(action: &Action) -> String {
+    match *action {
+        Action::Castle(_, from, _, rook) => return format!("{}{}", from.to_human(), rook.to_human()),
+        Action::Drop(piece, to) if piece.rank() == Rank::Pawn => return format!("P@{}", to.to_human()),
+        Action::Drop(piece, to) => return format!("{}@{}", rank_letter(&piece.rank()), to.to_human()),
+        _ => {}
+    }
+
+    let promotion = promoted_rank(action).map(|rank| rank_letter(&rank).to_lowercase()).unwrap_or_default();
+    format!("{}{}{}", from_coordinate_for(action).to_human(), to_coordinate_for(action).to_human(), promotion)
+}
+
+// What a SAN move tells us about the action it stands for.
+struct SanPattern {
+    rank: Rank,
+    to: Coordinate,
+    from_column: Option<usize>,
+    from_row: Option<usize>,
+    promotion: Option<Rank>,
+    castle: bool,
+}
+
+impl SanPattern {
+    fn matches(&self, action: &Action) -> bool {
+        let from = from_coordinate_for(action);
+
+        moving_piece(action).rank() == self.rank &&
+            *to_coordinate_for(action) == self.to &&
+            self.from_column.iter().all(|&column| from.column() == column) &&
+            self.from_row.iter().all(|&row| from.row() == row) &&
+            promoted_rank(action) == self.promotion &&
+            match *action {
+                Action::Castle(..) => self.castle,
+                Action::Drop(..) => false,
+                _ => !self.castle,
+            }
+    }
+}
+
+// The rank and square of a drop such as "N@f3", "P@e4" or "@e4".
+fn parse_drop(text: &str) -> Option<(Rank, Coordinate)> {
+    let at = text.find('@')?;
+    let rank = match &text[..at] {
+        "" | "P" => Rank::Pawn,
+        letter => rank_from_letter(letter)?,
+    };
+    let to = Coordinate::from_human(text[at + 1..].to_string()).ok()?;
+
+    Some((rank, to))
+}
+
+fn parse_long_algebraic(text: &str) -> Option<(Coordinate, Coordinate, Option<Rank>)> {
+    if !text.is_ascii() {
+        return None
+    }
+
+    let (from, rest) = leading_square(text)?;
+    let (to, rest) = leading_square(rest)?;
+    let promotion = match rest {
+        "" => None,
+        letter if letter.len() == 1 => Some(rank_from_letter(&letter.to_uppercase())?),
+        _ => return None,
+    };
+
+    Some((from, to, promotion))
+}
+
+// The square at the start of the text, a file letter and a rank number of
+// one or two digits, and whatever follows it.
+fn leading_square(text: &str) -> Option<(Coordinate, &str)> {
+    let digits = text.chars().skip(1).take_while(|letter| letter.is_ascii_digit()).count();
+    let square = Coordinate::from_human(text.get(0..1 + digits)?.to_string()).ok()?;
+    Some((square, &text[1 + digits..]))
+}
+
+// The text before the square at the end of it, and the square.
+fn trailing_square(text: &str) -> Option<(&str, Coordinate)> {
+    let digits = text.chars().rev().take_while(|letter| letter.is_ascii_digit()).count();
+    let start = text.len().checked_sub(digits + 1)?;
+    let square = Coordinate::from_human(text[start..].to_string()).ok()?;
+    Some((&text[..start], square))
+}
+
+fn parse_san(text: &str, board: &Board, side: Side) -> Result<SanPattern, MoveError> {
+    let invalid = || MoveError::NotAMove(text.to_string());
+
+    if text == "O-O" || text == "0-0" || text == "O-O-O" || text == "0-0-0" {
+        let row = board.home_row(side);
+        let column = if text.len() == 3 { board.width() - 2 } else { 2 };
+        return Ok(SanPattern {
+            rank: Rank::King,
+            to: Coordinate::new(row, column),
+            from_column: None,
+            from_row: Some(row),
+            promotion: None,
+            castle: true,
+        })
+    }
+
+    if !text.is_ascii() {
+        return Err(invalid())
+    }
+
+    let (text, promotion) = match text.find('=') {
+        Some(index) => (&text[..index], Some(rank_from_letter(&text[index + 1..]).ok_or_else(&invalid)?)),
+        None => (text, None),
+    };
+
+    let (prefix, to) = trailing_square(text).ok_or_else(&invalid)?;
+
+    let (rank, prefix) = match prefix.chars().next().and_then(|letter| rank_from_letter(&letter.to_string())) {
+        Some(rank) => (rank, &prefix[1..]),
+        None => (Rank::Pawn, prefix),
+    };
+
+    let mut from_column = None;
+    let mut from_row = None;
+    for letter in prefix.chars().filter(|&letter| letter != 'x') {
+        match letter {
+            _ if is_file_letter(letter) && from_column.is_none() => from_column = Some(letter as usize - 'a' as usize),
+            _ if is_rank_digit(letter) && from_row.is_none() => from_row = Some(letter as usize - '1' as usize),
+            _ => return Err(invalid()),
+        }
+    }
+
+    Ok(SanPattern { rank, to, from_column, from_row, promotion, castle: false })
+}
+
+fn is_file_letter(letter: char) -> bool {
+    letter >= 'a' && (letter as usize) < 'a' as usize + MAX_SIZE
+}
+
+fn is_rank_digit(letter: char) -> bool {
+    letter.is_ascii_digit() && letter != '0'
+}
+
+fn rank_from_letter(letter: &str) -> Option<Rank> {
+    match letter {
+        "N" => Some(Rank::Knight),
+        "B" => Some(Rank::Bishop),
+        "R" => Some(Rank::Rook),
+        "Q" => Some(Rank::Queen),
+        "K" => Some(Rank::King),
+        "A" => Some(Rank::Archbishop),
+        "C" => Some(Rank::Chancellor),
+        "M" => Some(Rank::Amazon),
+        _ => None,
+    }
+}
+
+fn promoted_rank(action: &Action) -> Option<Rank> {
+    match *action {
+        Action::Promotion(_, promoted_to, _, _, _) => Some(promoted_to.rank()),
+        _ => None,
+    }
+}
+
+pub fn rank_letter(rank: &Rank) -> &'static str {
+    match *rank {
+        Rank::Pawn => "",
+        Rank::Knight => "N",
+        Rank::Bishop => "B",
+        Rank::Rook => "R",
+        Rank::Queen => "Q",
+        Rank::King => "K",
+        Rank::Archbishop => "A",
+        Rank::Chancellor => "C",
+        Rank::Amazon => "M",
+    }
+}
+
+// When another identical piece could also reach the target square, name the
+// file, then the rank, then both, whichever is enough to tell them apart.
+fn disambiguation(action: &Action, state: &mut GameState) -> String {
+    let piece = moving_piece(action);
+    let from = *from_coordinate_for(action);
+    let to = *to_coordinate_for(action);
+
+    let rivals: Vec<_> = engine::all_possible_actions(state)
+        .into_iter()
+        .filter(|other| if let Action::Drop(..) = *other { false } else { moving_piece(other) == piece })
+        .filter(|other| *to_coordinate_for(other) == to && *from_coordinate_for(other) != from)
+        .map(|other| *from_coordinate_for(&other))
+        .collect();
+
+    let human = from.to_human();
+    if rivals.is_empty() {
+        String::new()
+    } else if rivals.iter().all(|rival| rival.column() != from.column()) {
+        human[0..1].to_string()
+    } else if rivals.iter().all(|rival| rival.row() != from.row()) {
+        human[1..2].to_string()
+    } else {
+        human
+    }
+}
+
+pub fn moving_piece(action: &Action) -> Piece {
+    match *action {
+        Action::MovePiece(piece, _, _) => piece,
+        Action::Capture(piece, _, _, _) => piece,
+        Action::Promotion(piece, _, _, _, _) => piece,
+        Action::Castle(piece, _, _, _) => piece,
+        Action::Drop(piece, _) => piece,
+    }
+}
+
+fn check_suffix(state: &mut GameState) -> String {
+    let side = state.next_to_move();
+    let has_king = !state.board().find_pieces(Piece::pack(side, Rank::King)).is_empty();
+
+    if !has_king || !engine::is_in_check(state, side) {
+        String::new()
+    } else if engine::is_in_checkmate(state, side) {
+        "#".to_string()
+    } else {
+        "+".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use board::{Board, Coordinate};
+
+    macro_rules! coord {
+        ($x:expr) => { Coordinate::from_human($x.to_string()).unwrap() }
+    }
+
+    #[test]
+    fn formats_pawn_and_piece_moves() {
+        let mut state = GameState::new();
+
+        let e4 = Action::MovePiece(Piece::pack(Side::White, Rank::Pawn), coord!("e2"), coord!("e4"));
+        let nf3 = Action::MovePiece(Piece::pack(Side::White, Rank::Knight), coord!("g1"), coord!("f3"));
+
+        assert_eq!(to_san(&e4, &mut state), "e4");
+        assert_eq!(to_san(&nf3, &mut state), "Nf3");
+    }
+
+    #[test]
+    fn formats_captures_and_checks() {
+        let mut board = Board::empty();
+        board.update(&coord!("e1"), Some(Piece::pack(Side::White, Rank::King))).unwrap();
+        board.update(&coord!("e8"), Some(Piece::pack(Side::Black, Rank::King))).unwrap();
+        board.update(&coord!("d4"), Some(Piece::pack(Side::White, Rank::Pawn))).unwrap();
+        board.update(&coord!("e5"), Some(Piece::pack(Side::Black, Rank::Pawn))).unwrap();
+        board.update(&coord!("a4"), Some(Piece::pack(Side::White, Rank::Rook))).unwrap();
+        let mut state = GameState::with_board(board);
+
+        let dxe5 = Action::Capture(
+            Piece::pack(Side::White, Rank::Pawn),
+            Piece::pack(Side::Black, Rank::Pawn),
+            coord!("d4"),
+            coord!("e5"),
+        );
+        let ra8 = Action::MovePiece(Piece::pack(Side::White, Rank::Rook), coord!("a4"), coord!("a8"));
+
+        assert_eq!(to_san(&dxe5, &mut state), "dxe5");
+        assert_eq!(to_san(&ra8, &mut state), "Ra8+");
+    }
+
+    #[test]
+    fn formats_checkmate() {
+        let mut board = Board::empty();
+        board.update(&coord!("b3"), Some(Piece::pack(Side::White, Rank::King))).unwrap();
+        board.update(&coord!("a1"), Some(Piece::pack(Side::Black, Rank::King))).unwrap();
+        board.update(&coord!("h8"), Some(Piece::pack(Side::White, Rank::Rook))).unwrap();
+        let mut state = GameState::with_board(board);
+
+        let rh1 = Action::MovePiece(Piece::pack(Side::White, Rank::Rook), coord!("h8"), coord!("h1"));
+
+        assert_eq!(to_san(&rh1, &mut state), "Rh1#");
+    }
+
+    #[test]
+    fn disambiguates_by_file_then_rank() {
+        let mut board = Board::empty();
+        board.update(&coord!("h1"), Some(Piece::pack(Side::White, Rank::King))).unwrap();
+        board.update(&coord!("h8"), Some(Piece::pack(Side::Black, Rank::King))).unwrap();
+        board.update(&coord!("b1"), Some(Piece::pack(Side::White, Rank::Knight))).unwrap();
+        board.update(&coord!("f1"), Some(Piece::pack(Side::White, Rank::Knight))).unwrap();
+        board.update(&coord!("a1"), Some(Piece::pack(Side::White, Rank::Rook))).unwrap();
+        board.update(&coord!("a5"), Some(Piece::pack(Side::White, Rank::Rook))).unwrap();
+        let mut state = GameState::with_board(board);
+
+        let nbd2 = Action::MovePiece(Piece::pack(Side::White, Rank::Knight), coord!("b1"), coord!("d2"));
+        let r1a3 = Action::MovePiece(Piece::pack(Side::White, Rank::Rook), coord!("a1"), coord!("a3"));
+
+        assert_eq!(to_san(&nbd2, &mut state), "Nbd2");
+        assert_eq!(to_san(&r1a3, &mut state), "R1a3");
+    }
+
+    #[test]
+    fn formats_the_whole_history() {
+        let mut state = GameState::new();
+        state.play(Action::MovePiece(Piece::pack(Side::White, Rank::Pawn), coord!("e2"), coord!("e4"))).unwrap();
+        state.play(Action::MovePiece(Piece::pack(Side::Black, Rank::Pawn), coord!("d7"), coord!("d5"))).unwrap();
+        state.play(Action::Capture(
+            Piece::pack(Side::White, Rank::Pawn),
+            Piece::pack(Side::Black, Rank::Pawn),
+            coord!("e4"),
+            coord!("d5"),
+        )).unwrap();
+
+        assert_eq!(history_to_san(&state), vec!["e4", "d5", "exd5"]);
+    }
+
+    #[test]
+    fn parses_san_and_long_algebraic_moves() {
+        let mut state = GameState::new();
+        let nf3 = Action::MovePiece(Piece::pack(Side::White, Rank::Knight), coord!("g1"), coord!("f3"));
+        let e4 = Action::MovePiece(Piece::pack(Side::White, Rank::Pawn), coord!("e2"), coord!("e4"));
+
+        assert_eq!(parse_move("Nf3", &mut state), Ok(nf3));
+        assert_eq!(parse_move("e4", &mut state), Ok(e4));
+        assert_eq!(parse_move("e2e4", &mut state), Ok(e4));
+        assert_eq!(parse_move(" g1f3 ", &mut state), Ok(nf3));
+    }
+
+    #[test]
+    fn parses_captures_with_disambiguation() {
+        let mut board = Board::empty();
+        board.update(&coord!("h1"), Some(Piece::pack(Side::White, Rank::King))).unwrap();
+        board.update(&coord!("h8"), Some(Piece::pack(Side::Black, Rank::King))).unwrap();
+        board.update(&coord!("b1"), Some(Piece::pack(Side::White, Rank::Knight))).unwrap();
+        board.update(&coord!("f1"), Some(Piece::pack(Side::White, Rank::Knight))).unwrap();
+        board.update(&coord!("d2"), Some(Piece::pack(Side::Black, Rank::Pawn))).unwrap();
+        let mut state = GameState::with_board(board);
+
+        let nbxd2 = Action::Capture(
+            Piece::pack(Side::White, Rank::Knight),
+            Piece::pack(Side::Black, Rank::Pawn),
+            coord!("b1"),
+            coord!("d2"),
+        );
+
+        assert_eq!(parse_move("Nbxd2", &mut state), Ok(nbxd2));
+        assert_eq!(parse_move("Nbd2", &mut state), Ok(nbxd2));
+        assert_eq!(parse_move("Nxd2", &mut state), Err(MoveError::AmbiguousMove("Nxd2".to_string())));
+    }
+
+    #[test]
+    fn reads_and_writes_castling() {
+        let king = Piece::pack(Side::White, Rank::King);
+        let short = Action::Castle(king, coord!("e1"), coord!("g1"), coord!("h1"));
+        let mut state = GameState::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+
+        assert_eq!(to_san(&short, &mut state), "O-O");
+        assert_eq!(to_long_algebraic(&short), "e1h1");
+        assert_eq!(parse_move("O-O", &mut state), Ok(short));
+        assert_eq!(parse_move("0-0", &mut state), Ok(short));
+        assert_eq!(parse_move("e1h1", &mut state), Ok(short));
+        assert_eq!(parse_move("e1g1", &mut state), Ok(short));
+        assert_eq!(parse_move("Kf1", &mut state), Ok(Action::MovePiece(king, coord!("e1"), coord!("f1"))));
+
+        // In Chess960 the king can castle to a square it could also step to
+        let mut state = GameState::from_fen("1r2k3/8/8/8/8/8/8/1R1K4 w Bb - 0 1").unwrap();
+        let long = Action::Castle(king, coord!("d1"), coord!("c1"), coord!("b1"));
+        let step = Action::MovePiece(king, coord!("d1"), coord!("c1"));
+        assert_eq!(to_san(&long, &mut state), "O-O-O");
+        assert_eq!(parse_move("O-O-O", &mut state), Ok(long));
+        assert_eq!(parse_move("d1b1", &mut state), Ok(long));
+        assert_eq!(parse_move("Kc1", &mut state), Ok(step));
+        assert_eq!(parse_move("d1c1", &mut state), Ok(step));
+    }
+
+    #[test]
+    fn reads_and_writes_drops() {
+        let mut state = GameState::from_variant_fen("4k3/8/8/8/8/8/8/4K3[NP] w - - 0 1", &::variant::Crazyhouse).unwrap();
+        let knight = Action::Drop(Piece::pack(Side::White, Rank::Knight), coord!("f6"));
+        let pawn = Action::Drop(Piece::pack(Side::White, Rank::Pawn), coord!("e4"));
+
+        assert_eq!(to_san(&knight, &mut state), "N@f6+");
+        assert_eq!(to_long_algebraic(&knight), "N@f6");
+        assert_eq!(to_long_algebraic(&pawn), "P@e4");
+        assert_eq!(parse_move("N@f6", &mut state), Ok(knight));
+        assert_eq!(parse_move("P@e4", &mut state), Ok(pawn));
+        assert_eq!(parse_move("@e4", &mut state), Ok(pawn));
+        assert_eq!(parse_move("B@e4", &mut state), Err(MoveError::NoSuchMove("B@e4".to_string())));
+        assert_eq!(parse_move("P@e8", &mut state), Err(MoveError::NoSuchMove("P@e8".to_string())));
+    }
+
+    #[test]
+    fn plays_and_undoes_promotions() {
+        let fen = "3r3k/4P3/8/8/8/8/8/4K3 w - - 0 1";
+        let mut state = GameState::from_fen(fen).unwrap();
+        let pawn = Piece::pack(Side::White, Rank::Pawn);
+        let rook = Piece::pack(Side::Black, Rank::Rook);
+
+        let queen = parse_move("e8=Q", &mut state).unwrap();
+        assert_eq!(queen, Action::Promotion(pawn, Piece::pack(Side::White, Rank::Queen), None, coord!("e7"), coord!("e8")));
+        assert_eq!(to_san(&queen, &mut state), "e8=Q+");
+        assert_eq!(to_long_algebraic(&queen), "e7e8q");
+        assert_eq!(parse_move("e7e8n", &mut state), Ok(Action::Promotion(pawn, Piece::pack(Side::White, Rank::Knight), None, coord!("e7"), coord!("e8"))));
+        assert_eq!(parse_move("e8", &mut state), Err(MoveError::NoSuchMove("e8".to_string())));
+
+        let takes = parse_move("exd8=R", &mut state).unwrap();
+        assert_eq!(takes, Action::Promotion(pawn, Piece::pack(Side::White, Rank::Rook), Some(rook), coord!("e7"), coord!("d8")));
+        assert_eq!(to_san(&takes, &mut state), "exd8=R+");
+
+        state.play(queen).unwrap();
+        assert_eq!(state.to_fen(), "3rQ2k/8/8/8/8/8/8/4K3 b - - 0 1");
+        state.undo().unwrap();
+        assert_eq!(state.to_fen(), fen);
+
+        state.play(takes).unwrap();
+        assert_eq!(state.to_fen(), "3R3k/8/8/8/8/8/8/4K3 b - - 0 1");
+        state.undo().unwrap();
+        assert_eq!(state.to_fen(), fen);
+    }
+
+    #[test]
+    fn rejects_illegal_and_malformed_moves() {
+        let mut state = GameState::new();
+
+        assert_eq!(parse_move("e5", &mut state), Err(MoveError::NoSuchMove("e5".to_string())));
+        assert_eq!(parse_move("Ke2", &mut state), Err(MoveError::NoSuchMove("Ke2".to_string())));
+        assert_eq!(parse_move("O-O", &mut state), Err(MoveError::NoSuchMove("O-O".to_string())));
+        assert_eq!(parse_move("e8=Q", &mut state), Err(MoveError::NoSuchMove("e8=Q".to_string())));
+        assert_eq!(parse_move("hello", &mut state), Err(MoveError::NotAMove("hello".to_string())));
+        assert_eq!(parse_move("", &mut state), Err(MoveError::NotAMove("".to_string())));
+    }
+
+    #[test]
+    fn numbers_lines_from_the_current_move() {
+        let mut state = GameState::new();
+        let line = vec![
+            Action::MovePiece(Piece::pack(Side::White, Rank::Pawn), coord!("e2"), coord!("e4")),
+            Action::MovePiece(Piece::pack(Side::Black, Rank::Pawn), coord!("e7"), coord!("e5")),
+            Action::MovePiece(Piece::pack(Side::White, Rank::Knight), coord!("g1"), coord!("f3")),
+        ];
+
+        let sans = line_to_san(&state, &line);
+        assert_eq!(numbered_line(&state, &sans), "1. e4 e5 2. Nf3");
+
+        state.play(line[0]).unwrap();
+        let sans = line_to_san(&state, &line[1..]);
+        assert_eq!(numbered_line(&state, &sans), "1... e5 2. Nf3");
+
+        // Positions set up part way through a game count on from their FEN,
+        // including ones with Black to move
+        let mut state = GameState::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 5 40").unwrap();
+        let line = vec![
+            Action::MovePiece(Piece::pack(Side::Black, Rank::King), coord!("e8"), coord!("d7")),
+            Action::MovePiece(Piece::pack(Side::White, Rank::Pawn), coord!("e2"), coord!("e4")),
+        ];
+        let sans = line_to_san(&state, &line);
+        assert_eq!(numbered_line(&state, &sans), "40... Kd7 41. e4");
+
+        state.play(line[0]).unwrap();
+        let sans = line_to_san(&state, &line[1..]);
+        assert_eq!(numbered_line(&state, &sans), "41. e4");
+    }
+}
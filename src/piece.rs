@@ -14,7 +14,10 @@ impl Piece {
             Rank::Bishop => 2,
             Rank::Rook => 3,
             Rank::Queen => 4,
-            Rank::King => 5
+            Rank::King => 5,
+            Rank::Archbishop => 6,
+            Rank::Chancellor => 7,
+            Rank::Amazon => 8,
         };
 
         if side == Side::White {
@@ -32,15 +35,20 @@ impl Piece {
         }
     }
 
+    // Only pack makes pieces, but any bits pack never sets read as an
+    // amazon rather than panicking.
     pub fn rank(&self) -> Rank {
-        match self.repr << 1 {
+        match self.repr & 0b0111_1111 {
             0 => Rank::Pawn,
-            2 => Rank::Knight,
-            4 => Rank::Bishop,
-            6 => Rank::Rook,
-            8 => Rank::Queen,
-            10 => Rank::King,
-            i => panic!(format!("Unknown rank {:08b}", i))
+            1 => Rank::Knight,
+            2 => Rank::Bishop,
+            3 => Rank::Rook,
+            4 => Rank::Queen,
+            5 => Rank::King,
+            6 => Rank::Archbishop,
+            7 => Rank::Chancellor,
+            8 => Rank::Amazon,
+            i => unreachable!("Unknown rank {:08b}", i),
         }
     }
 }
@@ -52,13 +60,20 @@ impl fmt::Debug for Piece {
 }
 
 #[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Rank {
     Pawn,
     Knight,
     Bishop,
     Rook,
     Queen,
-    King
+    King,
+    // Fairy pieces, as in Capablanca chess: the archbishop moves as a bishop
+    // or a knight, the chancellor as a rook or a knight and the amazon as a
+    // queen or a knight.
+    Archbishop,
+    Chancellor,
+    Amazon,
 }
 
 impl fmt::Debug for Rank {
@@ -70,6 +85,9 @@ impl fmt::Debug for Rank {
             Rank::Rook => write!(f, "♖"),
             Rank::Queen => write!(f, "♕"),
             Rank::King => write!(f, "♔"),
+            Rank::Archbishop => write!(f, "A"),
+            Rank::Chancellor => write!(f, "C"),
+            Rank::Amazon => write!(f, "M"),
         }
     }
 }
@@ -103,6 +121,9 @@ mod tests {
         assert_eq!(rook.rank(),   Rank::Rook);
         assert_eq!(queen.rank(),  Rank::Queen);
         assert_eq!(king.rank(),   Rank::King);
+
+        let amazon = Piece { repr: 0b1000_1000 };
+        assert_eq!(amazon.rank(), Rank::Amazon);
     }
 
     #[test]
@@ -0,0 +1,125 @@
+use Side;
+use piece::{Piece, Rank};
+
+// The pieces each side holds to drop back on the board, as in Crazyhouse.
+// Kings are never captured, so never held.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct Pockets {
+    // How many of each rank, most valuable first, for White then Black
+    counts: [[usize; SLOTS]; 2],
+}
+
+impl Pockets {
+    pub fn new() -> Pockets {
+        Pockets::default()
+    }
+
+    pub fn add(&mut self, piece: Piece) {
+        if let Some(slot) = slot(&piece.rank()) {
+            self.counts[side_index(piece.side())][slot] += 1;
+        }
+    }
+
+    // Takes one of the piece out, if there is one to take.
+    pub fn take(&mut self, piece: Piece) -> bool {
+        match slot(&piece.rank()) {
+            Some(slot) if self.counts[side_index(piece.side())][slot] > 0 => {
+                self.counts[side_index(piece.side())][slot] -= 1;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    pub fn count(&self, piece: Piece) -> usize {
+        match slot(&piece.rank()) {
+            Some(slot) => self.counts[side_index(piece.side())][slot],
+            None => 0,
+        }
+    }
+
+    // The different pieces the side could drop, most valuable first.
+    pub fn pieces(&self, side: Side) -> Vec<Piece> {
+        (0..SLOTS)
+            .filter(|&slot| self.counts[side_index(side)][slot] > 0)
+            .map(|slot| Piece::pack(side, rank_at(slot)))
+            .collect()
+    }
+
+    // Every piece the side holds, repeats included, most valuable first.
+    pub fn all_pieces(&self, side: Side) -> Vec<Piece> {
+        self.pieces(side)
+            .into_iter()
+            .flat_map(|piece| vec![piece; self.count(piece)])
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.iter().all(|side| side.iter().all(|&count| count == 0))
+    }
+}
+
+const SLOTS: usize = 8;
+
+fn side_index(side: Side) -> usize {
+    match side {
+        Side::White => 0,
+        Side::Black => 1,
+    }
+}
+
+fn slot(rank: &Rank) -> Option<usize> {
+    match *rank {
+        Rank::Amazon => Some(0),
+        Rank::Queen => Some(1),
+        Rank::Chancellor => Some(2),
+        Rank::Archbishop => Some(3),
+        Rank::Rook => Some(4),
+        Rank::Bishop => Some(5),
+        Rank::Knight => Some(6),
+        Rank::Pawn => Some(7),
+        Rank::King => None,
+    }
+}
+
+fn rank_at(slot: usize) -> Rank {
+    match slot {
+        0 => Rank::Amazon,
+        1 => Rank::Queen,
+        2 => Rank::Chancellor,
+        3 => Rank::Archbishop,
+        4 => Rank::Rook,
+        5 => Rank::Bishop,
+        6 => Rank::Knight,
+        _ => Rank::Pawn,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_pieces_for_each_side() {
+        let mut pockets = Pockets::new();
+        let knight = Piece::pack(Side::White, Rank::Knight);
+        let queen = Piece::pack(Side::White, Rank::Queen);
+        let pawn = Piece::pack(Side::Black, Rank::Pawn);
+
+        pockets.add(knight);
+        pockets.add(pawn);
+        pockets.add(knight);
+        pockets.add(queen);
+        pockets.add(Piece::pack(Side::Black, Rank::King));
+
+        assert_eq!(pockets.count(knight), 2);
+        assert_eq!(pockets.pieces(Side::White), vec![queen, knight]);
+        assert_eq!(pockets.all_pieces(Side::White), vec![queen, knight, knight]);
+        assert_eq!(pockets.pieces(Side::Black), vec![pawn]);
+
+        assert!(pockets.take(pawn));
+        assert!(!pockets.take(pawn));
+        assert!(pockets.take(queen) && pockets.take(knight) && pockets.take(knight));
+        assert!(pockets.is_empty());
+    }
+}
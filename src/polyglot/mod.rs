@@ -0,0 +1,397 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use rand::{self, Rng};
+
+use Side;
+use game::GameState;
+use action::{Action, from_coordinate_for, to_coordinate_for};
+use board::Coordinate;
+use piece::{Piece, Rank};
+use engine;
+
+mod random;
+
+use self::random::RANDOM64;
+
+const ENTRY_SIZE: usize = 16;
+const CASTLING_OFFSET: usize = 768;
+const EN_PASSANT_OFFSET: usize = 772;
+const TURN_OFFSET: usize = 780;
+
+// A Polyglot opening book. Entries are kept sorted by key, as they are in
+// the .bin file, so lookups can binary search.
+#[derive(PartialEq, Clone)]
+pub struct Book {
+    entries: Vec<Entry>,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Entry {
+    pub key: u64,
+    pub raw_move: u16,
+    pub weight: u16,
+}
+
+impl Book {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Book, String> {
+        let mut bytes = vec![];
+        File::open(path.as_ref())
+            .and_then(|mut file| file.read_to_end(&mut bytes))
+            .map_err(|e| format!("Could not read book {}: {}", path.as_ref().display(), e))?;
+
+        Book::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Book, String> {
+        let trailing_bytes = bytes.len() % ENTRY_SIZE;
+        if trailing_bytes != 0 {
+            return Err(format!("Book size must be a multiple of {} bytes", ENTRY_SIZE))
+        }
+
+        let mut entries: Vec<Entry> = bytes
+            .chunks(ENTRY_SIZE)
+            .map(|chunk| Entry {
+                key: read_u64(&chunk[0..8]),
+                raw_move: read_u16(&chunk[8..10]),
+                weight: read_u16(&chunk[10..12]),
+            })
+            .collect();
+
+        // Books are sorted already, but a stable sort keeps us correct for
+        // hand-made files too.
+        entries.sort_by_key(|entry| entry.key);
+
+        Ok(Book { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entries_for(&self, key: u64) -> &[Entry] {
+        let start = lower_bound(&self.entries, key);
+        let count = self.entries[start..].iter().take_while(|entry| entry.key == key).count();
+
+        &self.entries[start..start + count]
+    }
+
+    // The book moves for the current position that are legal here, along
+    // with their weights.
+    pub fn actions_for(&self, state: &mut GameState) -> Vec<(Action, u16)> {
+        if !has_polyglot_key(state) {
+            return vec![]
+        }
+
+        let entries = self.entries_for(hash(state));
+        if entries.is_empty() {
+            return vec![]
+        }
+
+        let legal_actions = engine::all_possible_actions(state);
+
+        entries
+            .iter()
+            .filter_map(|entry| {
+                decode_move(entry.raw_move)
+                    .and_then(|(from, to, promotion)| legal_actions.iter().find(|action| {
+                        *from_coordinate_for(action) == from && book_destination(action) == to && promoted_rank(action) == promotion
+                    }))
+                    .map(|action| (*action, entry.weight))
+            })
+            .collect()
+    }
+
+    // Picks a book move at random, weighted by the entry weights.
+    pub fn choose(&self, state: &mut GameState) -> Option<Action> {
+        let actions = self.actions_for(state);
+        let total: u32 = actions.iter().map(|&(_, weight)| u32::from(weight)).sum();
+        if total == 0 {
+            return None
+        }
+
+        pick_weighted(&actions, rand::thread_rng().gen_range(0, total))
+    }
+}
+
+impl fmt::Debug for Book {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Book {{ entries: {} }}", self.entries.len())
+    }
+}
+
+// Polyglot only knows the usual pieces on the usual board.
+fn has_polyglot_key(state: &GameState) -> bool {
+    let board = state.board();
+    board.width() == 8 && board.height() == 8 &&
+        board.pieces_with_coordinates().into_iter().all(|(_coordinate, piece)| piece_kind(piece).is_some())
+}
+
+// Computes the standard Polyglot key for the position. Fairy pieces, which
+// Polyglot has no numbers for, are left out.
+pub fn hash(state: &GameState) -> u64 {
+    let mut key = 0;
+
+    for (coordinate, piece) in state.board().pieces_with_coordinates() {
+        if let Some(kind) = piece_kind(piece) {
+            key ^= RANDOM64[64 * kind + 8 * coordinate.row() + coordinate.column()];
+        }
+    }
+
+    for (index, &(king, rook)) in castling_squares().iter().enumerate() {
+        if can_still_castle(state, king, rook) {
+            key ^= RANDOM64[CASTLING_OFFSET + index];
+        }
+    }
+
+    if let Some(column) = en_passant_column(state) {
+        key ^= RANDOM64[EN_PASSANT_OFFSET + column];
+    }
+
+    if state.next_to_move() == Side::White {
+        key ^= RANDOM64[TURN_OFFSET];
+    }
+
+    key
+}
+
+fn piece_kind(piece: Piece) -> Option<usize> {
+    let rank = match piece.rank() {
+        Rank::Pawn => 0,
+        Rank::Knight => 1,
+        Rank::Bishop => 2,
+        Rank::Rook => 3,
+        Rank::Queen => 4,
+        Rank::King => 5,
+        Rank::Archbishop | Rank::Chancellor | Rank::Amazon => return None,
+    };
+
+    match piece.side() {
+        Side::White => Some(2 * rank + 1),
+        Side::Black => Some(2 * rank),
+    }
+}
+
+// King and rook squares, in Polyglot order: white short, white long,
+// black short, black long.
+fn castling_squares() -> [((Piece, Coordinate), Coordinate); 4] {
+    let white_king = (Piece::pack(Side::White, Rank::King), Coordinate::new(0, 4));
+    let black_king = (Piece::pack(Side::Black, Rank::King), Coordinate::new(7, 4));
+
+    [
+        (white_king, Coordinate::new(0, 7)),
+        (white_king, Coordinate::new(0, 0)),
+        (black_king, Coordinate::new(7, 7)),
+        (black_king, Coordinate::new(7, 0)),
+    ]
+}
+
+// Polyglot only knows about castling from the usual squares: the king on
+// the e file and the rook in the corner, with the right still held.
+fn can_still_castle(state: &GameState, king: (Piece, Coordinate), rook: Coordinate) -> bool {
+    let (king_piece, king_coordinate) = king;
+
+    *state.piece_at(king_coordinate) == Some(king_piece) && state.castling_rights().contains(&rook)
+}
+
+// Polyglot only hashes the en passant file when a pawn of the side to move
+// could actually make the capture.
+fn en_passant_column(state: &GameState) -> Option<usize> {
+    let to = match state.history().last() {
+        Some(&Action::MovePiece(piece, from, to)) => {
+            let distance = (from.row() as i8 - to.row() as i8).abs();
+            if piece.rank() != Rank::Pawn || distance != 2 {
+                return None
+            }
+            to
+        },
+        _ => return None,
+    };
+
+    let my_pawn = Some(Piece::pack(state.next_to_move(), Rank::Pawn));
+    let neighbours = [to.column().checked_sub(1), Some(to.column() + 1)];
+
+    let can_capture = neighbours
+        .iter()
+        .filter_map(|&column| column)
+        .filter_map(|column| Coordinate::new_safe(to.row(), column).ok())
+        .any(|coordinate| *state.piece_at(coordinate) == my_pawn);
+
+    if can_capture { Some(to.column()) } else { None }
+}
+
+// Polyglot writes castling as the king taking its own rook.
+fn book_destination(action: &Action) -> Coordinate {
+    match *action {
+        Action::Castle(_, _, _, rook) => rook,
+        _ => *to_coordinate_for(action),
+    }
+}
+
+fn promoted_rank(action: &Action) -> Option<Rank> {
+    match *action {
+        Action::Promotion(_, promoted_to, _, _, _) => Some(promoted_to.rank()),
+        _ => None,
+    }
+}
+
+// Polyglot moves pack the to square in the low 6 bits, the from square in
+// the next 6, and the promotion piece above that: 1 for a knight up to 4
+// for a queen. Anything higher isn't a Polyglot move.
+fn decode_move(raw_move: u16) -> Option<(Coordinate, Coordinate, Option<Rank>)> {
+    let square = |bits: u16| Coordinate::new(((bits >> 3) & 7) as usize, (bits & 7) as usize);
+    let promotion = match (raw_move >> 12) & 7 {
+        0 => None,
+        1 => Some(Rank::Knight),
+        2 => Some(Rank::Bishop),
+        3 => Some(Rank::Rook),
+        4 => Some(Rank::Queen),
+        _ => return None,
+    };
+
+    Some((square(raw_move >> 6), square(raw_move), promotion))
+}
+
+fn pick_weighted(actions: &[(Action, u16)], mut roll: u32) -> Option<Action> {
+    for &(action, weight) in actions {
+        if roll < u32::from(weight) {
+            return Some(action)
+        }
+        roll -= u32::from(weight);
+    }
+    None
+}
+
+fn lower_bound(entries: &[Entry], key: u64) -> usize {
+    let (mut low, mut high) = (0, entries.len());
+    while low < high {
+        let middle = (low + high) / 2;
+        if entries[middle].key < key { low = middle + 1 } else { high = middle }
+    }
+    low
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |acc, &byte| (acc << 8) | u64::from(byte))
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |acc, &byte| (acc << 8) | u16::from(byte))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use piece::Piece;
+
+    macro_rules! coord {
+        ($x:expr) => { Coordinate::from_human($x.to_string()).unwrap() }
+    }
+
+    fn play(state: &mut GameState, from: &str, to: &str) {
+        let action = engine::possible_actions(&coord!(from), state)
+            .into_iter()
+            .find(|action| *to_coordinate_for(action) == coord!(to))
+            .expect("Move not found");
+        state.play(action).unwrap();
+    }
+
+    fn tiny_book() -> Book {
+        Book::from_bytes(include_bytes!("../../tests/fixtures/tiny.bin")).unwrap()
+    }
+
+    #[test]
+    fn hashes_the_starting_position() {
+        assert_eq!(hash(&GameState::new()), 0x463b96181691fc9c);
+    }
+
+    #[test]
+    fn hashes_positions_after_moves() {
+        let mut state = GameState::new();
+
+        play(&mut state, "e2", "e4");
+        assert_eq!(hash(&state), 0x823c9b50fd114196);
+        play(&mut state, "d7", "d5");
+        assert_eq!(hash(&state), 0x0756b94461c50fb0);
+        play(&mut state, "e4", "e5");
+        assert_eq!(hash(&state), 0x662fafb965db29d4);
+    }
+
+    #[test]
+    fn hashes_en_passant_and_lost_castling_rights() {
+        let mut state = GameState::new();
+
+        play(&mut state, "e2", "e4");
+        play(&mut state, "d7", "d5");
+        play(&mut state, "e4", "e5");
+        play(&mut state, "f7", "f5");
+        assert_eq!(hash(&state), 0x22a48b5a8e47ff78);
+        play(&mut state, "e1", "e2");
+        assert_eq!(hash(&state), 0x652a607ca3f242c1);
+        play(&mut state, "e8", "f7");
+        assert_eq!(hash(&state), 0x00fdd303c946bdd9);
+    }
+
+    #[test]
+    fn rejects_truncated_books() {
+        assert!(Book::from_bytes(&[0; 15]).is_err());
+    }
+
+    #[test]
+    fn finds_legal_book_moves_for_a_position() {
+        let book = tiny_book();
+        let mut state = GameState::new();
+
+        assert_eq!(book.len(), 4);
+        assert_eq!(book.entries_for(hash(&state)).len(), 3);
+        // The castling entry is not playable here, so it is skipped
+        assert_eq!(book.actions_for(&mut state), vec![
+            (Action::MovePiece(Piece::pack(Side::White, Rank::Pawn), coord!("e2"), coord!("e4")), 3),
+            (Action::MovePiece(Piece::pack(Side::White, Rank::Pawn), coord!("d2"), coord!("d4")), 1),
+        ]);
+
+        play(&mut state, "e2", "e4");
+        assert_eq!(book.choose(&mut state), Some(
+            Action::MovePiece(Piece::pack(Side::Black, Rank::Pawn), coord!("e7"), coord!("e5"))
+        ));
+
+        play(&mut state, "e7", "e5");
+        assert_eq!(book.choose(&mut state), None);
+    }
+
+    #[test]
+    fn finds_book_promotions() {
+        let mut state = GameState::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let key = hash(&state);
+        // a7a8, promoting to a knight and to a queen
+        let book = Book { entries: vec![
+            Entry { key, raw_move: 0x1c38, weight: 1 },
+            Entry { key, raw_move: 0x4c38, weight: 2 },
+            Entry { key, raw_move: 0x5c38, weight: 5 },
+        ] };
+        let pawn = Piece::pack(Side::White, Rank::Pawn);
+
+        assert_eq!(book.actions_for(&mut state), vec![
+            (Action::Promotion(pawn, Piece::pack(Side::White, Rank::Knight), None, coord!("a7"), coord!("a8")), 1),
+            (Action::Promotion(pawn, Piece::pack(Side::White, Rank::Queen), None, coord!("a7"), coord!("a8")), 2),
+        ]);
+    }
+
+    #[test]
+    fn picks_moves_in_proportion_to_their_weight() {
+        let e4 = Action::MovePiece(Piece::pack(Side::White, Rank::Pawn), coord!("e2"), coord!("e4"));
+        let d4 = Action::MovePiece(Piece::pack(Side::White, Rank::Pawn), coord!("d2"), coord!("d4"));
+        let actions = vec![(e4, 3), (d4, 1)];
+
+        assert_eq!(pick_weighted(&actions, 0), Some(e4));
+        assert_eq!(pick_weighted(&actions, 2), Some(e4));
+        assert_eq!(pick_weighted(&actions, 3), Some(d4));
+        assert_eq!(pick_weighted(&actions, 4), None);
+    }
+}
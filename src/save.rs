@@ -0,0 +1,281 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use toml::Value;
+use toml::value::Table;
+
+use Side;
+use ai::Ai;
+use clock::Clock;
+use game::{Game, GameState};
+use notation;
+use session::Session;
+use variant::{variant_named, Standard, Variant};
+
+// A saved game is a small TOML file with the moves in long algebraic
+// notation, so it can be read and even edited by hand:
+//
+//     mode = "ai"
+//     level = "max"
+//     player_as = "white"
+//     moves = ["e2e4", "e7e5"]
+//
+// Variants other than standard chess have a variant = "atomic" line, and games
+// that didn't start from the variant's usual position, like Chess960 ones,
+// have a start = "<FEN>" line.
+//
+//     [clock]
+//     control = "5+3"
+//     white_millis = 291200
+//     black_millis = 297400
+//     white_moves = 1
+//     black_moves = 1
+pub fn to_toml(session: &Session) -> String {
+    let game = session.game();
+    let mut saved = Table::new();
+
+    match session.opponent() {
+        Some(ai) => {
+            saved.insert("mode".to_string(), Value::String("ai".to_string()));
+            saved.insert("level".to_string(), Value::String(ai.level().name().to_string()));
+        },
+        None => {
+            saved.insert("mode".to_string(), Value::String("local".to_string()));
+        },
+    }
+    saved.insert("player_as".to_string(), Value::String(side_name(session.player_as()).to_string()));
+
+    let variant = game.state().variant();
+    if variant.name() != Standard.name() {
+        saved.insert("variant".to_string(), Value::String(variant.name().to_string()));
+    }
+
+    let start = game.state().starting_position();
+    if start != GameState::for_variant(variant) {
+        saved.insert("start".to_string(), Value::String(start.to_fen()));
+    }
+
+    let moves = game.history().iter().map(|action| Value::String(notation::to_long_algebraic(action))).collect();
+    saved.insert("moves".to_string(), Value::Array(moves));
+
+    if let Some(clock) = game.clock() {
+        let now = Instant::now();
+        let mut saved_clock = Table::new();
+        saved_clock.insert("control".to_string(), Value::String(clock.control().to_string()));
+        saved_clock.insert("white_millis".to_string(), Value::Integer(millis(clock.remaining(Side::White, now))));
+        saved_clock.insert("black_millis".to_string(), Value::Integer(millis(clock.remaining(Side::Black, now))));
+        saved_clock.insert("white_moves".to_string(), Value::Integer(i64::from(clock.moves_made(Side::White))));
+        saved_clock.insert("black_moves".to_string(), Value::Integer(i64::from(clock.moves_made(Side::Black))));
+        saved.insert("clock".to_string(), Value::Table(saved_clock));
+    }
+
+    Value::Table(saved).to_string()
+}
+
+// Sets the game back up by replaying its moves from the start, so a file
+// with an illegal move in it is rejected rather than trusted.
+pub fn from_toml(text: &str) -> Result<Session, String> {
+    let saved = text.parse::<Value>().map_err(|e| e.to_string())?;
+
+    let session = match string_setting(&saved, "mode")? {
+        "local" => Session::new(),
+        "ai" => {
+            let mut ai = Ai::new();
+            if saved.get("level").is_some() {
+                ai = ai.with_level(string_setting(&saved, "level")?.parse()?);
+            }
+            Session::against_ai(ai)
+        },
+        mode => return Err(format!("Unknown mode {}, expected local or ai", mode)),
+    };
+
+    let player_as = match string_setting(&saved, "player_as")? {
+        "white" => Side::White,
+        "black" => Side::Black,
+        side => return Err(format!("Unknown side {}, expected white or black", side)),
+    };
+
+    let moves = saved.get("moves")
+        .and_then(|moves| moves.as_array())
+        .ok_or_else(|| "moves should be a list".to_string())?;
+
+    let variant = match saved.get("variant") {
+        Some(_) => {
+            let name = string_setting(&saved, "variant")?;
+            variant_named(name).ok_or_else(|| format!("Unknown variant {}", name))?
+        },
+        None => &Standard,
+    };
+
+    let mut game = match saved.get("start") {
+        Some(_) => {
+            let start = GameState::from_variant_fen(string_setting(&saved, "start")?, variant).map_err(|e| e.to_string())?;
+            Game::starting_from(start)
+        },
+        None => Game::starting_from(GameState::for_variant(variant)),
+    };
+    for (index, saved_move) in moves.iter().enumerate() {
+        let text = saved_move.as_str().ok_or_else(|| "moves should be strings".to_string())?;
+        let action = notation::parse_move(text, game.state_mut())
+            .map_err(|e| format!("Move {}: {}", index + 1, e))?;
+        game.advance(action).map_err(|e| e.to_string())?;
+    }
+
+    if let Some(saved_clock) = saved.get("clock") {
+        let control = string_setting(saved_clock, "control")?.parse()?;
+        let remaining = [
+            Duration::from_millis(integer_setting(saved_clock, "white_millis")?),
+            Duration::from_millis(integer_setting(saved_clock, "black_millis")?),
+        ];
+        let moves_made = [
+            integer_setting(saved_clock, "white_moves")? as u32,
+            integer_setting(saved_clock, "black_moves")? as u32,
+        ];
+        game = game.with_clock(Clock::resume(control, remaining, moves_made));
+    }
+
+    Ok(session.playing_as(player_as).with_game(game))
+}
+
+pub fn save(session: &Session, path: &Path) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+    }
+
+    File::create(path)
+        .and_then(|mut file| file.write_all(to_toml(session).as_bytes()))
+        .map_err(|e| format!("Could not save to {}: {}", path.display(), e))
+}
+
+pub fn load(path: &Path) -> Result<Session, String> {
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+
+    from_toml(&text).map_err(|e| format!("Could not resume {}: {}", path.display(), e))
+}
+
+// $XDG_DATA_HOME/terminal-chess/autosave.toml, or ~/.local/share/... without it.
+pub fn autosave_path() -> Option<PathBuf> {
+    let data_dir = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".local").join("share"),
+    };
+
+    Some(data_dir.join("terminal-chess").join("autosave.toml"))
+}
+
+fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::White => "white",
+        Side::Black => "black",
+    }
+}
+
+fn millis(time: Duration) -> i64 {
+    (time.as_secs() * 1000 + u64::from(time.subsec_millis())) as i64
+}
+
+fn string_setting<'a>(saved: &'a Value, key: &str) -> Result<&'a str, String> {
+    saved.get(key)
+        .and_then(|value| value.as_str())
+        .ok_or_else(|| format!("{} should be a string", key))
+}
+
+fn integer_setting(saved: &Value, key: &str) -> Result<u64, String> {
+    match saved.get(key).and_then(|value| value.as_integer()) {
+        Some(value) if value >= 0 => Ok(value as u64),
+        _ => Err(format!("{} should be a positive number", key)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ai::Level;
+    use board::Board;
+    use clock::TimeControl;
+    use variant::Atomic;
+
+    fn play(mut game: Game, moves: &[&str]) -> Game {
+        for text in moves {
+            let action = notation::parse_move(text, game.state_mut()).unwrap();
+            game.advance(action).unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn saves_and_resumes_a_game_against_the_ai() {
+        let game = play(Game::new(), &["e4", "e5", "Nf3"]);
+        let session = Session::against_ai(Ai::new().with_level(Level::Casual)).with_game(game);
+
+        let text = to_toml(&session);
+        assert!(text.contains("moves = [\"e2e4\", \"e7e5\", \"g1f3\"]"));
+
+        let resumed = from_toml(&text).unwrap();
+        assert_eq!(resumed.game().history(), session.game().history());
+        assert_eq!(resumed.game().current_turn(), Side::Black);
+        assert_eq!(resumed.opponent().map(|ai| ai.level()), Some(Level::Casual));
+        assert_eq!(resumed.move_list(), &vec!["e4".to_string(), "e5".to_string(), "Nf3".to_string()]);
+    }
+
+    #[test]
+    fn keeps_the_time_left_on_the_clock() {
+        let control: TimeControl = "5+3".parse().unwrap();
+        let game = play(Game::new().with_clock(Clock::new(control.clone())), &["d4", "d5"]);
+        let session = Session::new().with_game(game);
+
+        let resumed = from_toml(&to_toml(&session)).unwrap();
+        let clock = resumed.game().clock().unwrap();
+        let now = Instant::now();
+        assert_eq!(clock.control(), &control);
+        assert_eq!(clock.moves_made(Side::White), 1);
+        assert_eq!(clock.running(), Some(Side::White));
+        assert!(clock.remaining(Side::Black, now) > Duration::from_secs(302));
+        assert!(clock.remaining(Side::Black, now) <= Duration::from_secs(303));
+    }
+
+    #[test]
+    fn resumes_chess960_games_from_their_starting_position() {
+        let start = GameState::starting_from(Board::chess960(0).unwrap());
+        let game = play(Game::starting_from(start), &["f3", "f6", "Rf2", "Rf7", "O-O"]);
+        let session = Session::new().with_game(game);
+
+        let text = to_toml(&session);
+        assert!(text.contains("start = \"bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1\""));
+        assert!(text.contains("moves = [\"f2f3\", \"f7f6\", \"f1f2\", \"f8f7\", \"g1h1\"]"));
+
+        let resumed = from_toml(&text).unwrap();
+        assert_eq!(resumed.game().state(), session.game().state());
+        assert!(!to_toml(&Session::new()).contains("start"));
+    }
+
+    #[test]
+    fn resumes_variant_games_by_their_own_rules() {
+        let game = play(Game::starting_from(GameState::for_variant(&Atomic)), &["Nf3", "d5", "Ne5", "h6", "Nxf7"]);
+        let session = Session::new().with_game(game);
+
+        let text = to_toml(&session);
+        assert!(text.contains("variant = \"atomic\""));
+        assert!(!text.contains("start"));
+
+        let resumed = from_toml(&text).unwrap();
+        assert_eq!(resumed.game().state(), session.game().state());
+        assert_eq!(resumed.game().state().variant(), &Atomic as &dyn Variant);
+        assert!(from_toml("mode = \"local\"\nplayer_as = \"white\"\nvariant = \"bughouse\"\nmoves = []").is_err());
+    }
+
+    #[test]
+    fn rejects_broken_saves() {
+        assert!(from_toml("mode = \"ai\"").is_err());
+        assert!(from_toml("mode = \"online\"\nplayer_as = \"white\"\nmoves = []").is_err());
+
+        let illegal = from_toml("mode = \"local\"\nplayer_as = \"white\"\nmoves = [\"e2e4\", \"e2e4\"]");
+        assert_eq!(illegal.err(), Some("Move 2: Illegal move: e2e4".to_string()));
+    }
+}
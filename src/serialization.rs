@@ -0,0 +1,335 @@
+// Serde support for the core types, behind the "serde" feature. Side, Rank
+// and Board are derived, though a Board is checked when read back; the rest
+// are written out the way a chess player would write them: pieces as FEN
+// letters, coordinates as "e4" and actions as UCI moves like "e2e4" or
+// "e7e8q".
+//
+// An Action has no Deserialize of its own, since a UCI move only makes sense
+// in a position. It can only be read back together with its game state:
+// through ActionSeed (exported as MoveSeed) with the position it was played
+// in, or as one of the moves of a serialized GameState.
+use std::fmt;
+
+use serde::de::{self, Deserialize, DeserializeSeed, Deserializer, Visitor};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use Side;
+use action::Action;
+use board::{Board, Coordinate, MAX_SIZE};
+use game::GameState;
+use notation;
+use piece::{Piece, Rank};
+use variant::{variant_named, Standard};
+
+const PIECE_LETTERS: &str = "PNBRQKACMpnbrqkacm";
+const RANKS: [Rank; 9] = [Rank::Pawn, Rank::Knight, Rank::Bishop, Rank::Rook, Rank::Queen, Rank::King, Rank::Archbishop, Rank::Chancellor, Rank::Amazon];
+
+impl Serialize for Piece {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let rank = RANKS.iter().position(|rank| *rank == self.rank()).unwrap();
+        let index = match self.side() {
+            Side::White => rank,
+            Side::Black => rank + RANKS.len(),
+        };
+        serializer.serialize_str(&PIECE_LETTERS[index..index + 1])
+    }
+}
+
+impl<'de> Deserialize<'de> for Piece {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Piece, D::Error> {
+        deserializer.deserialize_str(StringVisitor("a FEN piece letter like P or n", |text: &str| {
+            match PIECE_LETTERS.find(text) {
+                Some(index) if text.len() == 1 && index < RANKS.len() => Ok(Piece::pack(Side::White, rank_at(index))),
+                Some(index) if text.len() == 1 => Ok(Piece::pack(Side::Black, rank_at(index - RANKS.len()))),
+                _ => Err(format!("Unknown piece {}", text)),
+            }
+        }))
+    }
+}
+
+fn rank_at(index: usize) -> Rank {
+    match index {
+        0 => Rank::Pawn,
+        1 => Rank::Knight,
+        2 => Rank::Bishop,
+        3 => Rank::Rook,
+        4 => Rank::Queen,
+        5 => Rank::King,
+        6 => Rank::Archbishop,
+        7 => Rank::Chancellor,
+        _ => Rank::Amazon,
+    }
+}
+
+// The fields of a Board as they're written, before they're checked to make
+// a board no bigger than MAX_SIZE on either side.
+#[derive(Deserialize)]
+struct BoardFields {
+    width: usize,
+    squares: Vec<Option<Piece>>,
+}
+
+impl<'de> Deserialize<'de> for Board {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Board, D::Error> {
+        let fields = BoardFields::deserialize(deserializer)?;
+        if fields.width == 0 || fields.width > MAX_SIZE {
+            return Err(de::Error::custom(format!("Board width must be 1 to {}, not {}", MAX_SIZE, fields.width)))
+        }
+        if fields.squares.len() % fields.width != 0 {
+            return Err(de::Error::custom(format!("{} squares don't fill ranks {} wide", fields.squares.len(), fields.width)))
+        }
+        let height = fields.squares.len() / fields.width;
+        if height == 0 || height > MAX_SIZE {
+            return Err(de::Error::custom(format!("Board height must be 1 to {}, not {}", MAX_SIZE, height)))
+        }
+
+        let mut board = Board::with_size(fields.width, height);
+        for (index, piece) in fields.squares.into_iter().enumerate() {
+            board.update(&Coordinate::new(index / fields.width, index % fields.width), piece).map_err(de::Error::custom)?;
+        }
+        Ok(board)
+    }
+}
+
+impl Serialize for Coordinate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_human())
+    }
+}
+
+impl<'de> Deserialize<'de> for Coordinate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Coordinate, D::Error> {
+        deserializer.deserialize_str(StringVisitor("a square like e4", |text: &str| Coordinate::from_human(text.to_string())))
+    }
+}
+
+// A UCI move doesn't say which pieces were involved, so actions can only be
+// read back against a position, with ActionSeed.
+impl Serialize for Action {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&notation::to_long_algebraic(self))
+    }
+}
+
+// Reads a move played in the position, which says what pieces it moves and
+// takes. Moves that aren't legal there are rejected.
+pub struct ActionSeed<'a>(pub &'a mut GameState);
+
+impl<'a, 'de> DeserializeSeed<'de> for ActionSeed<'a> {
+    type Value = Action;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Action, D::Error> {
+        let state = self.0;
+        deserializer.deserialize_str(StringVisitor("a UCI move like e2e4", |text: &str| notation::parse_move(text, state)))
+    }
+}
+
+// The variant, the FEN of the position the game started from and the moves
+// played since, plus the resulting board and side to move for anything that
+// only wants those.
+impl Serialize for GameState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let start = self.starting_position();
+
+        let mut state = serializer.serialize_struct("GameState", 5)?;
+        state.serialize_field("variant", self.variant().name())?;
+        state.serialize_field("start", &start.to_fen())?;
+        state.serialize_field("moves", self.history())?;
+        state.serialize_field("board", self.board())?;
+        state.serialize_field("next_to_move", &self.next_to_move())?;
+        state.end()
+    }
+}
+
+#[derive(Deserialize)]
+struct SavedState {
+    variant: Option<String>,
+    start: String,
+    moves: Vec<String>,
+    board: Option<Board>,
+    next_to_move: Option<Side>,
+}
+
+// Replays the moves from the start, so only legal games are accepted.
+impl<'de> Deserialize<'de> for GameState {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<GameState, D::Error> {
+        let saved = SavedState::deserialize(deserializer)?;
+
+        let variant = match saved.variant {
+            Some(ref name) => variant_named(name).ok_or_else(|| de::Error::custom(format!("Unknown variant {}", name)))?,
+            None => &Standard,
+        };
+
+        let mut state = GameState::from_variant_fen(&saved.start, variant)
+            .map_err(|e| de::Error::custom(format!("Start: {}", e)))?;
+        for (index, text) in saved.moves.iter().enumerate() {
+            let action = notation::parse_move(text, &mut state)
+                .map_err(|e| de::Error::custom(format!("Move {}: {}", index + 1, e)))?;
+            state.play(action).map_err(de::Error::custom)?;
+        }
+
+        if saved.board.iter().any(|board| board != state.board()) {
+            return Err(de::Error::custom("The board does not match the moves"))
+        }
+        if saved.next_to_move.iter().any(|side| *side != state.next_to_move()) {
+            return Err(de::Error::custom("The side to move does not match the moves"))
+        }
+
+        Ok(state)
+    }
+}
+
+// Reads a string through a parsing function, with what it expected for
+// error messages.
+struct StringVisitor<F>(&'static str, F);
+
+impl<'de, T, M, F> Visitor<'de> for StringVisitor<F> where F: FnOnce(&str) -> Result<T, M>, M: fmt::Display {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+
+    fn visit_str<E: de::Error>(self, text: &str) -> Result<T, E> {
+        (self.1)(text).map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+    use variant::Crazyhouse;
+
+    macro_rules! coord {
+        ($x:expr) => { Coordinate::from_human($x.to_string()).unwrap() }
+    }
+
+    #[test]
+    fn writes_pieces_coordinates_and_actions_like_a_chess_player() {
+        let pawn = Piece::pack(Side::White, Rank::Pawn);
+        let knight = Piece::pack(Side::Black, Rank::Knight);
+
+        assert_eq!(serde_json::to_string(&pawn).unwrap(), "\"P\"");
+        assert_eq!(serde_json::to_string(&knight).unwrap(), "\"n\"");
+        assert_eq!(serde_json::from_str::<Piece>("\"n\"").unwrap(), knight);
+        assert!(serde_json::from_str::<Piece>("\"x\"").is_err());
+
+        assert_eq!(serde_json::to_string(&coord!("e4")).unwrap(), "\"e4\"");
+        assert_eq!(serde_json::from_str::<Coordinate>("\"e4\"").unwrap(), coord!("e4"));
+        assert_eq!(serde_json::from_str::<Coordinate>("\"j10\"").unwrap(), coord!("j10"));
+        assert!(serde_json::from_str::<Coordinate>("\"q9\"").is_err());
+        assert!(serde_json::from_str::<Coordinate>("\"a17\"").is_err());
+
+        let e4 = Action::MovePiece(pawn, coord!("e2"), coord!("e4"));
+        assert_eq!(serde_json::to_string(&e4).unwrap(), "\"e2e4\"");
+        assert_eq!(serde_json::to_string(&Side::Black).unwrap(), "\"Black\"");
+    }
+
+    #[test]
+    fn rejects_boards_that_do_not_fit() {
+        let board = Board::with_size(10, 8);
+        let json = serde_json::to_string(&board).unwrap();
+        assert_eq!(serde_json::from_str::<Board>(&json).unwrap(), board);
+
+        assert!(serde_json::from_str::<Board>("{\"width\":0,\"squares\":[]}").is_err());
+        assert!(serde_json::from_str::<Board>("{\"width\":17,\"squares\":[null]}").is_err());
+        assert!(serde_json::from_str::<Board>("{\"width\":2,\"squares\":[null,null,null]}").is_err());
+        assert!(serde_json::from_str::<Board>("{\"width\":1,\"squares\":[]}").is_err());
+        let too_tall = format!("{{\"width\":1,\"squares\":[{}null]}}", "null,".repeat(MAX_SIZE));
+        assert!(serde_json::from_str::<Board>(&too_tall).is_err());
+    }
+
+    #[test]
+    fn reads_actions_back_against_a_position() {
+        let mut state = GameState::new();
+        let e4 = Action::MovePiece(Piece::pack(Side::White, Rank::Pawn), coord!("e2"), coord!("e4"));
+        let json = serde_json::to_string(&e4).unwrap();
+
+        let read_back = ActionSeed(&mut state).deserialize(&mut serde_json::Deserializer::from_str(&json)).unwrap();
+        assert_eq!(read_back, e4);
+
+        let illegal = ActionSeed(&mut state).deserialize(&mut serde_json::Deserializer::from_str("\"e2e5\""));
+        assert!(illegal.is_err());
+        assert!(state.history().is_empty());
+    }
+
+    #[test]
+    fn round_trips_a_game_through_json() {
+        let mut state = GameState::new();
+        for text in &["e4", "d5", "exd5", "Qxd5"] {
+            let action = notation::parse_move(text, &mut state).unwrap();
+            state.play(action).unwrap();
+        }
+
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(json.contains("\"moves\":[\"e2e4\",\"d7d5\",\"e4d5\",\"d8d5\"]"));
+        assert!(json.contains("\"next_to_move\":\"White\""));
+        assert!(json.contains("\"start\":\"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1\""));
+        assert!(json.contains("\"variant\":\"standard\""));
+
+        let read_back: GameState = serde_json::from_str(&json).unwrap();
+        assert_eq!(read_back, state);
+    }
+
+    #[test]
+    fn round_trips_a_move_along_with_its_game() {
+        let mut state = GameState::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let promotion = notation::parse_move("a8=Q", &mut state).unwrap();
+        let json = serde_json::to_string(&promotion).unwrap();
+        assert_eq!(json, "\"a7a8q\"");
+
+        let read_back = ActionSeed(&mut state).deserialize(&mut serde_json::Deserializer::from_str(&json)).unwrap();
+        assert_eq!(read_back, promotion);
+        state.play(read_back).unwrap();
+
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(json.contains("\"moves\":[\"a7a8q\"]"));
+        let game: GameState = serde_json::from_str(&json).unwrap();
+        assert_eq!(game, state);
+        assert_eq!(game.history(), &[promotion]);
+    }
+
+    #[test]
+    fn round_trips_a_game_started_with_black_to_move() {
+        let mut state = GameState::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 5 40").unwrap();
+        let action = notation::parse_move("Kd7", &mut state).unwrap();
+        state.play(action).unwrap();
+
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(json.contains("\"start\":\"4k3/8/8/8/8/8/4P3/4K3 b - - 5 40\""));
+
+        let read_back: GameState = serde_json::from_str(&json).unwrap();
+        assert_eq!(read_back, state);
+        assert_eq!(read_back.to_fen(), "8/3k4/8/8/8/8/4P3/4K3 w - - 6 41");
+    }
+
+    #[test]
+    fn round_trips_a_crazyhouse_game_with_pockets() {
+        let fen = "rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQ~KBNR[Qp] w KQkq - 0 1";
+        let mut state = GameState::from_variant_fen(fen, &Crazyhouse).unwrap();
+        for text in &["Q@e3", "P@e6"] {
+            let action = notation::parse_move(text, &mut state).unwrap();
+            state.play(action).unwrap();
+        }
+
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(json.contains(&format!("\"start\":\"{}\"", fen)));
+
+        let read_back: GameState = serde_json::from_str(&json).unwrap();
+        assert_eq!(read_back, state);
+        assert!(read_back.is_promoted(coord!("d1")));
+        assert!(read_back.pockets().is_empty());
+    }
+
+    #[test]
+    fn rejects_games_that_do_not_add_up() {
+        let start = "\"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1\"";
+
+        let illegal = format!("{{\"start\":{},\"moves\":[\"e2e5\"]}}", start);
+        assert!(serde_json::from_str::<GameState>(&illegal).is_err());
+
+        let wrong_side = format!("{{\"start\":{},\"moves\":[\"e2e4\"],\"next_to_move\":\"White\"}}", start);
+        assert!(serde_json::from_str::<GameState>(&wrong_side).is_err());
+    }
+}
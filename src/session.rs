@@ -1,50 +1,189 @@
-use game::{Game};
+use game::{Game, Outcome, Status};
+use clock::{self, Clock, TimeControl};
 use ui::Cursor;
 use Side;
-use action::{Action, to_coordinate_for};
+use action::{Action, from_coordinate_for, picked_coordinate_for, to_coordinate_for};
 use board::Coordinate;
+use engine;
+use piece::Piece;
 use ::ai;
+use ai::Ai;
+use analysis::{Analysis, MoveSearch};
+use notation;
+use theme::Theme;
+use input::{Input, InputSource, TerminalInput};
+use save;
 
-use termion::event::Key;
-use termion::input::TermRead;
+use std::io::Write;
+use std::mem;
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use libc;
+
+use termion::event::{Event, Key, MouseButton, MouseEvent};
+use termion::input::MouseTerminal;
 use termion::raw::IntoRawMode;
 
+// How often the session wakes up to check on background work when no keys
+// are pressed.
+const TICK_MILLIS: u64 = 100;
+
+// Set by the SIGWINCH handler so the next tick redraws for the new size.
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
 #[derive(PartialEq, Clone, Debug)]
 pub enum SessionState {
     NothingSelected,
     CoordinateSelected(Coordinate, Vec<Action>),
+    // A piece picked from the pocket, with the squares it can be dropped on
+    DropSelected(Piece, Vec<Action>),
+    // Typing a move in algebraic notation, with what has been typed so far
+    EnteringMove(String),
     WillQuit
 }
 
+// A suggested move for the side to act, shown but not played.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Hint {
+    pub action: Action,
+    pub score: i32,
+}
+
 #[derive(PartialEq, Debug)]
 pub struct Session {
     current_game: Game,
     cursor: Cursor,
     player_as: Side,
     state: SessionState,
+    opponent: Option<Ai>,
+    // The AI's next move, searched for in the background
+    thinking: Option<MoveSearch>,
+    hint: Option<Hint>,
+    analysis: Option<Analysis>,
+    // Why the last typed move was rejected, shown until the next key
+    message: Option<String>,
+    // The game's moves in SAN, kept up to date as the position changes
+    move_list: Vec<String>,
+    // Whether the side to move is in check, mated or stalemated
+    status: Status,
+    outcome: Option<Outcome>,
+    // The time left for White and Black as last drawn
+    clock_times: Option<(String, String)>,
+    theme: Theme,
+    // Where the game is saved after every move and on quitting
+    save_to: Option<PathBuf>,
 }
 
 impl Session {
     pub fn new() -> Session {
+        let game = Game::new();
         Session {
-            current_game: Game::new(),
-            cursor: Cursor::new(Side::White),
+            cursor: Cursor::new(Side::White, game.board()),
+            current_game: game,
             player_as: Side::White,
             state: SessionState::NothingSelected,
+            opponent: None,
+            thinking: None,
+            hint: None,
+            analysis: None,
+            message: None,
+            move_list: vec![],
+            status: Status::InProgress,
+            outcome: None,
+            clock_times: None,
+            theme: Theme::default(),
+            save_to: None,
+        }
+    }
+
+    pub fn against_ai(ai: Ai) -> Session {
+        Session {
+            opponent: Some(ai),
+            ..Session::new()
         }
     }
 
+    pub fn with_clock(mut self, control: TimeControl) -> Session {
+        self.current_game.set_clock(Clock::new(control));
+        self.clock_times = self.current_clock_times();
+        self
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> Session {
+        self.theme = theme;
+        self
+    }
+
+    // Carries on with a game already under way, such as a saved one.
+    pub fn with_game(mut self, game: Game) -> Session {
+        self.current_game = game;
+        self.cursor = Cursor::new(self.player_as, self.current_game.board());
+        self.position_changed();
+        self
+    }
+
+    pub fn playing_as(mut self, side: Side) -> Session {
+        self.player_as = side;
+        self.cursor = Cursor::new(side, self.current_game.board());
+        self
+    }
+
+    pub fn with_save_file(mut self, path: PathBuf) -> Session {
+        self.save_to = Some(path);
+        self
+    }
+
     pub fn run(mut self) {
-        let mut stdout = ::std::io::stdout().into_raw_mode().unwrap();
+        {
+            let mut stdout = MouseTerminal::from(::std::io::stdout().into_raw_mode().unwrap());
+            watch_for_resize();
 
-        ::ui::clear(&mut stdout).expect("Error drawing UI");
-        ::ui::draw(&self, &mut stdout).expect("Error drawing UI");
+            ::ui::clear(&mut stdout).expect("Error drawing UI");
+            self.play(&mut TerminalInput::new(), &mut stdout);
+        }
+
+        // Most likely the game could not be saved on the way out
+        if let Some(message) = self.message {
+            eprintln!("{}", message);
+        }
+    }
+
+    // Runs the session until the player quits, the game ends or the input
+    // runs out, drawing to out along the way.
+    pub fn play<I: InputSource, W: Write>(&mut self, input: &mut I, out: &mut W) {
+        ::ui::draw(self, out).expect("Error drawing UI");
 
-        for c in ::std::io::stdin().keys() {
-            self.update(c.unwrap());
-            ::ui::draw(&self, &mut stdout).expect("Error drawing UI");
+        loop {
+            let mut changed = match input.next_input(Duration::from_millis(TICK_MILLIS)) {
+                Input::Event(Event::Key(key)) => {
+                    self.update(key);
+                    true
+                },
+                Input::Event(Event::Mouse(mouse)) => {
+                    self.update_mouse(mouse);
+                    true
+                },
+                Input::Event(Event::Unsupported(_)) => false,
+                Input::Idle => false,
+                Input::Closed => {
+                    self.wait_for_ai_move();
+                    break
+                },
+            };
+            changed |= self.tick();
+            changed |= RESIZED.swap(false, Ordering::Relaxed);
+
+            if !changed {
+                continue;
+            }
+
+            ::ui::draw(self, out).expect("Error drawing UI");
 
             if self.state == SessionState::WillQuit {
+                self.autosave();
                 break;
             }
 
@@ -66,12 +205,127 @@ impl Session {
         self.player_as
     }
 
+    pub fn opponent(&self) -> Option<&Ai> {
+        self.opponent.as_ref()
+    }
+
     pub fn state(&self) -> &SessionState {
         &self.state
     }
 
-    fn update(&mut self, input: Key) {
-        let mut next_state: Option<SessionState> = None;
+    pub fn hint(&self) -> Option<&Hint> {
+        self.hint.as_ref()
+    }
+
+    pub fn analysis(&self) -> Option<&Analysis> {
+        self.analysis.as_ref()
+    }
+
+    pub fn message(&self) -> Option<&String> {
+        self.message.as_ref()
+    }
+
+    pub fn move_list(&self) -> &Vec<String> {
+        &self.move_list
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.outcome
+    }
+
+    pub fn clock_times(&self) -> Option<&(String, String)> {
+        self.clock_times.as_ref()
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    // Keeps the analysis on the current position and collects its progress,
+    // and plays the AI's move once it has one. Returns whether there is
+    // anything new to draw.
+    fn tick(&mut self) -> bool {
+        let mut changed = self.tick_clock();
+        changed |= self.tick_ai_move();
+
+        let analysing_old_position = match self.analysis {
+            Some(ref analysis) => analysis.state() != self.current_game.state(),
+            None => false,
+        };
+
+        if analysing_old_position {
+            self.analysis = Some(Analysis::start(self.current_game.state()));
+            return true;
+        }
+
+        changed |= match self.analysis {
+            Some(ref mut analysis) => analysis.poll(),
+            None => false,
+        };
+        changed
+    }
+
+    // Checks for a flag falling, and whether the clock needs redrawing.
+    fn tick_clock(&mut self) -> bool {
+        let mut changed = false;
+
+        if self.current_game.check_flag() {
+            self.outcome = self.current_game.outcome();
+            self.autosave();
+            changed = true;
+        }
+
+        let clock_times = self.current_clock_times();
+        if clock_times != self.clock_times {
+            self.clock_times = clock_times;
+            changed = true;
+        }
+
+        changed
+    }
+
+    // Starts the AI thinking when it is its turn, and plays the move it
+    // finds. A search for a position that has since changed is dropped.
+    fn tick_ai_move(&mut self) -> bool {
+        let searching_old_position = match self.thinking {
+            Some(ref search) => search.state() != self.current_game.state(),
+            None => false,
+        };
+        if searching_old_position {
+            self.thinking = None;
+        }
+
+        if self.waiting_on_opponent() && !self.current_game.has_completed() {
+            self.start_ai_move();
+        }
+
+        match self.thinking.as_ref().and_then(|search| search.poll()) {
+            Some(action) => {
+                self.thinking = None;
+                self.play_ai_move(action);
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn current_clock_times(&self) -> Option<(String, String)> {
+        self.current_game.clock().map(|game_clock| {
+            let now = Instant::now();
+            (clock::format_clock(game_clock.remaining(Side::White, now)), clock::format_clock(game_clock.remaining(Side::Black, now)))
+        })
+    }
+
+    pub fn update(&mut self, input: Key) {
+        self.message = None;
+
+        if let SessionState::EnteringMove(_) = self.state {
+            return self.update_move_entry(input);
+        }
 
         match input {
             Key::Char('q') => self.state = SessionState::WillQuit,
@@ -79,53 +333,283 @@ impl Session {
             Key::Down => self.cursor.down(),
             Key::Right => self.cursor.right(),
             Key::Left => self.cursor.left(),
-            Key::Char(' ') => {
-                match self.state {
-                    SessionState::NothingSelected => {
-                        if let &Some(piece) = self.current_game.state().piece_at(self.cursor.to_coord()) {
-                            if piece.side() == self.current_game.state().next_to_move() {
-                                let possible_actions = self.current_game.state_mut().actions_at(self.cursor.to_coord());
-                                next_state = Some(SessionState::CoordinateSelected(self.cursor.to_coord(), possible_actions));
-                            }
-                        }
-                    },
-                    SessionState::CoordinateSelected(ref coord, ref actions) => {
-                        // We have found a move, lets invoke it
-                        if let Some(action) = actions.into_iter().find(|a| to_coordinate_for(a) == &self.cursor.to_coord() ) {
-                            self.current_game.advance(action.clone()).expect("Illegal move found");
-                            next_state = Some(SessionState::NothingSelected);
-                        // We're on the same coordinate we selected before, clear selection
-                        } else if *coord == self.cursor.to_coord() {
-                            next_state = Some(SessionState::NothingSelected);
-                        // We've found another piece, lets select it
-                        } else if let &Some(piece) = self.game().state().piece_at(self.cursor.to_coord()) {
-                            if piece.side() == self.game().state().next_to_move() {
-                                let possible_actions = self.current_game.state_mut().actions_at(self.cursor.to_coord());
-                                next_state = Some(SessionState::CoordinateSelected(self.cursor.to_coord(), possible_actions));
-                            }
-                        // We've found an empty piece, clear selection
-                        } else {
-                            next_state = Some(SessionState::NothingSelected);
-                        }
-                    }
-                    _ => unimplemented!()
+            Key::Char(' ') => self.select_at_cursor(),
+            // Against the AI it moves by itself, so this is only for playing
+            // both sides
+            Key::Char('a') if self.opponent.is_none() => self.start_ai_move(),
+            Key::Char('u') => self.take_back(),
+            Key::Char('r') => self.replay(),
+            Key::Char('x') => {
+                self.analysis = match self.analysis.take() {
+                    Some(_) => None,
+                    None => Some(Analysis::start(self.current_game.state())),
+                };
+            },
+            Key::Char(':') => self.state = SessionState::EnteringMove(String::new()),
+            Key::Char('p') => self.select_next_drop(),
+            Key::Char('h') => {
+                self.hint = ai::suggest_move(self.current_game.state_mut())
+                    .map(|(action, score)| Hint { action, score });
+            },
+            _ => {}
+        }
+    }
+
+    // Selects the piece under the cursor, or plays the selected piece to it.
+    fn select_at_cursor(&mut self) {
+        let cursor_at = self.cursor.to_coord();
+
+        match self.state.clone() {
+            SessionState::NothingSelected => self.select_piece_at(cursor_at),
+            SessionState::DropSelected(_, actions) => {
+                self.state = SessionState::NothingSelected;
+                match actions.into_iter().find(|a| to_coordinate_for(a) == &cursor_at) {
+                    Some(action) => self.play_move(action),
+                    None => self.select_piece_at(cursor_at),
                 }
+            }
+            SessionState::CoordinateSelected(coord, actions) => {
+                // We have found a move, lets invoke it
+                if let Some(action) = actions.into_iter().find(|a| picked_coordinate_for(a) == &cursor_at) {
+                    self.play_move(action);
+                // We're on the same coordinate we selected before, clear selection
+                } else if coord == cursor_at {
+                    self.state = SessionState::NothingSelected;
+                // Otherwise select whatever is there, if anything
+                } else {
+                    self.state = SessionState::NothingSelected;
+                    self.select_piece_at(cursor_at);
+                }
+            }
+            _ => unimplemented!()
+        }
+    }
+
+    // Picks the next piece in the pocket of the side to move, going back to
+    // nothing after the last one.
+    fn select_next_drop(&mut self) {
+        let side = self.current_game.state().next_to_move();
+        let pieces = self.current_game.state().pockets().pieces(side);
+
+        let next = match self.state {
+            SessionState::DropSelected(selected, _) => pieces.iter().position(|&piece| piece == selected).map(|index| index + 1),
+            _ => Some(0),
+        };
+
+        self.state = match next.and_then(|index| pieces.get(index)) {
+            Some(&piece) => {
+                let drops = engine::possible_drops(self.current_game.state_mut())
+                    .into_iter()
+                    .filter(|action| *action == Action::Drop(piece, *to_coordinate_for(action)))
+                    .collect();
+                SessionState::DropSelected(piece, drops)
             },
-            Key::Char('a') => {
-                if let Some(action) = ai::make_move(self.current_game.state_mut()) {
-                    match action {
-                        Action::Capture(_,_,_,ref to) => self.cursor.move_to_coord(to),
-                        Action::MovePiece(_,_,ref to) => self.cursor.move_to_coord(to),
-                        _ => {}
-                    }
-                    self.current_game.advance(action).expect("Illegal move found");
+            None => SessionState::NothingSelected,
+        };
+    }
+
+    fn select_piece_at(&mut self, coord: Coordinate) {
+        if let &Some(piece) = self.current_game.state().piece_at(coord) {
+            if piece.side() == self.current_game.state().next_to_move() {
+                let possible_actions = self.current_game.state_mut().actions_at(coord);
+                self.state = SessionState::CoordinateSelected(coord, possible_actions);
+            }
+        }
+    }
+
+    // Clicking works like moving the cursor and pressing space, so moves can
+    // be made by clicking twice. Letting go over another square finishes a
+    // drag, which only ever plays the move.
+    fn update_mouse(&mut self, input: MouseEvent) {
+        self.message = None;
+
+        if let SessionState::EnteringMove(_) = self.state {
+            return;
+        }
+
+        match input {
+            MouseEvent::Press(MouseButton::Left, x, y) => {
+                if let Some(coord) = ::ui::coordinate_at(self, x, y) {
+                    self.cursor.move_to_coord(&coord);
+                    self.select_at_cursor();
+                }
+            },
+            MouseEvent::Hold(x, y) => {
+                if let Some(coord) = ::ui::coordinate_at(self, x, y) {
+                    self.cursor.move_to_coord(&coord);
+                }
+            },
+            MouseEvent::Release(x, y) => {
+                let dropped_on = ::ui::coordinate_at(self, x, y);
+                let action = match self.state {
+                    SessionState::CoordinateSelected(_, ref actions) => dropped_on.and_then(|coord| {
+                        actions.iter().find(|a| *picked_coordinate_for(a) == coord).cloned()
+                    }),
+                    _ => None,
+                };
+
+                if let Some(action) = action {
+                    self.cursor.move_to_coord(to_coordinate_for(&action));
+                    self.play_move(action);
                 }
             },
             _ => {}
         }
+    }
+
+    fn update_move_entry(&mut self, input: Key) {
+        let mut typed = match self.state {
+            SessionState::EnteringMove(ref typed) => typed.clone(),
+            _ => return,
+        };
 
-        if let Some(state) = next_state {
-            self.state = state;
+        match input {
+            Key::Esc => self.state = SessionState::NothingSelected,
+            Key::Char('\n') => {
+                self.state = SessionState::NothingSelected;
+                match self.current_game.state_mut().parse_move(&typed) {
+                    Ok(action) => {
+                        self.cursor.move_to_coord(to_coordinate_for(&action));
+                        self.play_move(action);
+                    },
+                    Err(e) => self.message = Some(e.to_string()),
+                }
+            },
+            Key::Backspace => {
+                typed.pop();
+                self.state = SessionState::EnteringMove(typed);
+            },
+            Key::Char(letter) => {
+                typed.push(letter);
+                self.state = SessionState::EnteringMove(typed);
+            },
+            _ => {}
         }
     }
+
+    // Plays the player's move. The AI replies on the next tick, if there is
+    // one.
+    fn play_move(&mut self, action: Action) {
+        if self.thinking.is_some() {
+            self.message = Some("Waiting for the AI to move".to_string());
+            return;
+        }
+        if let Err(e) = self.current_game.advance(action) {
+            self.message = Some(e.to_string());
+            return;
+        }
+        self.position_changed();
+    }
+
+    fn start_ai_move(&mut self) {
+        if self.thinking.is_none() {
+            self.thinking = Some(MoveSearch::start(self.opponent.clone(), self.current_game.state()));
+        }
+    }
+
+    // Lets the AI finish its move, when there are no more keys to wait for.
+    fn wait_for_ai_move(&mut self) {
+        self.tick_ai_move();
+        if let Some(search) = self.thinking.take() {
+            let action = search.wait();
+            self.play_ai_move(action);
+        }
+    }
+
+    fn play_ai_move(&mut self, action: Option<Action>) {
+        if let Some(action) = action {
+            match action {
+                Action::Capture(_,_,_,ref to) => self.cursor.move_to_coord(to),
+                Action::MovePiece(_,_,ref to) => self.cursor.move_to_coord(to),
+                Action::Promotion(_,_,_,_,ref to) => self.cursor.move_to_coord(to),
+                Action::Castle(_,_,ref to,_) => self.cursor.move_to_coord(to),
+                Action::Drop(_,ref to) => self.cursor.move_to_coord(to),
+            }
+            if let Err(e) = self.current_game.advance(action) {
+                self.message = Some(e.to_string());
+                return;
+            }
+            self.position_changed();
+        }
+    }
+
+    // Undoes the last move. Against the AI, its reply is taken back as well
+    // so the player is to move again.
+    fn take_back(&mut self) {
+        loop {
+            match self.current_game.undo() {
+                Ok(Some(action)) => self.cursor.move_to_coord(from_coordinate_for(&action)),
+                Ok(None) => break,
+                Err(e) => {
+                    self.message = Some(e.to_string());
+                    break;
+                },
+            }
+            if !self.waiting_on_opponent() {
+                break;
+            }
+        }
+        self.position_changed();
+    }
+
+    // Redoes what take_back undid.
+    fn replay(&mut self) {
+        loop {
+            match self.current_game.redo() {
+                Ok(Some(action)) => self.cursor.move_to_coord(to_coordinate_for(&action)),
+                Ok(None) => break,
+                Err(e) => {
+                    self.message = Some(e.to_string());
+                    break;
+                },
+            }
+            if !self.waiting_on_opponent() {
+                break;
+            }
+        }
+        self.position_changed();
+    }
+
+    fn waiting_on_opponent(&self) -> bool {
+        self.opponent.is_some() && self.current_game.current_turn() != self.player_as
+    }
+
+    // Anything selected or suggested belongs to the previous position.
+    fn position_changed(&mut self) {
+        self.state = SessionState::NothingSelected;
+        self.hint = None;
+        self.move_list = notation::history_to_san(self.current_game.state());
+        self.status = self.current_game.state_mut().status();
+        self.outcome = self.current_game.outcome();
+        self.clock_times = self.current_clock_times();
+        self.autosave();
+    }
+
+    fn autosave(&mut self) {
+        if let Some(ref path) = self.save_to {
+            if let Err(e) = save::save(self, path) {
+                self.message = Some(e);
+            }
+        }
+    }
+}
+
+impl Default for Session {
+    fn default() -> Session {
+        Session::new()
+    }
+}
+
+extern "C" fn on_resize(_signal: libc::c_int) {
+    RESIZED.store(true, Ordering::Relaxed);
+}
+
+fn watch_for_resize() {
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = on_resize as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        // Don't let the signal interrupt reading keys
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigaction(libc::SIGWINCH, &action, ptr::null_mut());
+    }
 }
@@ -0,0 +1,416 @@
+use std::env;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use termion::color;
+use toml::Value;
+
+use piece::{Piece, Rank};
+use Side;
+
+// The standard 16 terminal colours, as xterm shows them by default.
+static ANSI_16: [(u8, u8, u8); 16] = [
+    (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0),
+    (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+    (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0),
+    (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+];
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Colour(pub u8, pub u8, pub u8);
+
+// Parses "#rrggbb".
+impl FromStr for Colour {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Colour, String> {
+        let invalid = || format!("Invalid colour {}, expected #rrggbb", text);
+
+        if text.len() != 7 || !text.starts_with('#') || !text.is_ascii() {
+            return Err(invalid())
+        }
+
+        let component = |index: usize| u8::from_str_radix(&text[index..index + 2], 16).map_err(|_| invalid());
+        Ok(Colour(component(1)?, component(3)?, component(5)?))
+    }
+}
+
+// How many colours the terminal can show. Anything short of truecolor gets
+// the nearest colour it does have.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ColourSupport {
+    TrueColour,
+    Ansi256,
+    Ansi16,
+}
+
+impl ColourSupport {
+    pub fn detect() -> ColourSupport {
+        ColourSupport::from_env(env::var("COLORTERM").ok(), env::var("TERM").ok())
+    }
+
+    // Truecolor is advertised through COLORTERM, 256 colours through TERM
+    fn from_env(colorterm: Option<String>, term: Option<String>) -> ColourSupport {
+        match colorterm {
+            Some(ref value) if value == "truecolor" || value == "24bit" => return ColourSupport::TrueColour,
+            _ => {}
+        }
+
+        match term {
+            Some(ref value) if value.contains("256color") => ColourSupport::Ansi256,
+            _ => ColourSupport::Ansi16,
+        }
+    }
+}
+
+impl FromStr for ColourSupport {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<ColourSupport, String> {
+        match name {
+            "truecolor" => Ok(ColourSupport::TrueColour),
+            "256" => Ok(ColourSupport::Ansi256),
+            "16" => Ok(ColourSupport::Ansi16),
+            _ => Err(format!("Unknown colour support {}, expected truecolor, 256 or 16", name)),
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum PieceSet {
+    Unicode,
+    // Letters as in FEN: upper case for white, lower case for black
+    Letters,
+    // Letters, with empty squares dotted for terminals where the board
+    // colours are hard to tell apart
+    Ascii,
+}
+
+impl PieceSet {
+    pub fn format(&self, piece: &Option<Piece>) -> &'static str {
+        match (*self, *piece) {
+            (PieceSet::Unicode, Some(piece)) => unicode_piece(piece),
+            (PieceSet::Unicode, None) => " ",
+            (_, Some(piece)) => letter_piece(piece),
+            (PieceSet::Letters, None) => " ",
+            (PieceSet::Ascii, None) => ".",
+        }
+    }
+}
+
+impl FromStr for PieceSet {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<PieceSet, String> {
+        match name {
+            "unicode" => Ok(PieceSet::Unicode),
+            "letters" => Ok(PieceSet::Letters),
+            "ascii" => Ok(PieceSet::Ascii),
+            _ => Err(format!("Unknown piece set {}, expected unicode, letters or ascii", name)),
+        }
+    }
+}
+
+fn unicode_piece(piece: Piece) -> &'static str {
+    match (piece.side(), piece.rank()) {
+        (Side::White, Rank::Pawn) => "♙",
+        (Side::White, Rank::Knight) => "♘",
+        (Side::White, Rank::Bishop) => "♗",
+        (Side::White, Rank::Rook) => "♖",
+        (Side::White, Rank::Queen) => "♕",
+        (Side::White, Rank::King) => "♔",
+        (Side::Black, Rank::Pawn) => "♟",
+        (Side::Black, Rank::Knight) => "♞",
+        (Side::Black, Rank::Bishop) => "♝",
+        (Side::Black, Rank::Rook) => "♜",
+        (Side::Black, Rank::Queen) => "♛",
+        (Side::Black, Rank::King) => "♚",
+        // There are no symbols for the fairy pieces
+        _ => letter_piece(piece),
+    }
+}
+
+fn letter_piece(piece: Piece) -> &'static str {
+    match (piece.side(), piece.rank()) {
+        (Side::White, Rank::Pawn) => "P",
+        (Side::White, Rank::Knight) => "N",
+        (Side::White, Rank::Bishop) => "B",
+        (Side::White, Rank::Rook) => "R",
+        (Side::White, Rank::Queen) => "Q",
+        (Side::White, Rank::King) => "K",
+        (Side::Black, Rank::Pawn) => "p",
+        (Side::Black, Rank::Knight) => "n",
+        (Side::Black, Rank::Bishop) => "b",
+        (Side::Black, Rank::Rook) => "r",
+        (Side::Black, Rank::Queen) => "q",
+        (Side::Black, Rank::King) => "k",
+        (Side::White, Rank::Archbishop) => "A",
+        (Side::White, Rank::Chancellor) => "C",
+        (Side::White, Rank::Amazon) => "M",
+        (Side::Black, Rank::Archbishop) => "a",
+        (Side::Black, Rank::Chancellor) => "c",
+        (Side::Black, Rank::Amazon) => "m",
+    }
+}
+
+// Colours and pieces for drawing the board.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Theme {
+    pub light: Colour,
+    pub dark: Colour,
+    pub cursor: Colour,
+    pub legal_move: Colour,
+    pub selected: Colour,
+    pub hint: Colour,
+    pub last_move: Colour,
+    pub check: Colour,
+    pub pieces: PieceSet,
+    pub support: ColourSupport,
+}
+
+impl Theme {
+    pub fn classic() -> Theme {
+        Theme {
+            light: Colour(226, 226, 226),
+            dark: Colour(190, 190, 190),
+            cursor: Colour(0, 100, 100),
+            legal_move: Colour(10, 0, 100),
+            selected: Colour(100, 0, 0),
+            hint: Colour(0, 120, 0),
+            last_move: Colour(170, 162, 58),
+            check: Colour(220, 60, 60),
+            pieces: PieceSet::Unicode,
+            support: ColourSupport::TrueColour,
+        }
+    }
+
+    pub fn named(name: &str) -> Result<Theme, String> {
+        match name {
+            "classic" => Ok(Theme::classic()),
+            "wood" => Ok(Theme {
+                light: Colour(240, 217, 181),
+                dark: Colour(181, 136, 99),
+                cursor: Colour(70, 130, 180),
+                legal_move: Colour(130, 151, 105),
+                selected: Colour(186, 202, 68),
+                hint: Colour(100, 160, 90),
+                last_move: Colour(205, 210, 106),
+                check: Colour(230, 80, 70),
+                ..Theme::classic()
+            }),
+            "ocean" => Ok(Theme {
+                light: Colour(222, 227, 230),
+                dark: Colour(140, 162, 173),
+                cursor: Colour(255, 160, 60),
+                legal_move: Colour(90, 110, 200),
+                selected: Colour(200, 80, 80),
+                hint: Colour(80, 170, 120),
+                last_move: Colour(170, 200, 230),
+                check: Colour(230, 90, 60),
+                ..Theme::classic()
+            }),
+            "contrast" => Ok(Theme {
+                light: Colour(255, 255, 255),
+                dark: Colour(120, 120, 120),
+                cursor: Colour(0, 160, 255),
+                legal_move: Colour(255, 0, 255),
+                selected: Colour(255, 0, 0),
+                hint: Colour(0, 200, 0),
+                last_move: Colour(255, 215, 0),
+                check: Colour(255, 80, 0),
+                ..Theme::classic()
+            }),
+            _ => Err(format!("Unknown theme {}, expected classic, wood, ocean or contrast", name)),
+        }
+    }
+
+    // Reads the theme from the user's config file, if they have one.
+    pub fn load() -> Result<Theme, String> {
+        let path = match config_path() {
+            Some(path) => path,
+            None => return Ok(Theme { support: ColourSupport::detect(), ..Theme::classic() }),
+        };
+
+        let mut text = String::new();
+        match File::open(&path).and_then(|mut file| file.read_to_string(&mut text)) {
+            Ok(_) => Theme::from_toml(&text, ColourSupport::detect())
+                .map_err(|e| format!("Could not load {}: {}", path.display(), e)),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(Theme { support: ColourSupport::detect(), ..Theme::classic() }),
+            Err(e) => Err(format!("Could not read {}: {}", path.display(), e)),
+        }
+    }
+
+    // A config picks a built-in theme and can override any of its colours:
+    //
+    //     theme = "wood"
+    //     pieces = "letters"
+    //     colour_support = "256"
+    //
+    //     [colours]
+    //     cursor = "#4682b4"
+    pub fn from_toml(text: &str, support: ColourSupport) -> Result<Theme, String> {
+        let config = text.parse::<Value>().map_err(|e| e.to_string())?;
+
+        let mut theme = match config.get("theme") {
+            Some(name) => Theme::named(string_setting(name, "theme")?)?,
+            None => Theme::classic(),
+        };
+
+        theme.support = match config.get("colour_support") {
+            Some(name) => string_setting(name, "colour_support")?.parse()?,
+            None => support,
+        };
+
+        if let Some(name) = config.get("pieces") {
+            theme.pieces = string_setting(name, "pieces")?.parse()?;
+        }
+
+        if let Some(colours) = config.get("colours") {
+            let colours = colours.as_table().ok_or_else(|| "colours should be a table".to_string())?;
+            for (key, value) in colours {
+                let colour = string_setting(value, key)?.parse()?;
+                match key.as_str() {
+                    "light" => theme.light = colour,
+                    "dark" => theme.dark = colour,
+                    "cursor" => theme.cursor = colour,
+                    "legal_move" => theme.legal_move = colour,
+                    "selected" => theme.selected = colour,
+                    "hint" => theme.hint = colour,
+                    "last_move" => theme.last_move = colour,
+                    "check" => theme.check = colour,
+                    _ => return Err(format!("Unknown colour {}", key)),
+                }
+            }
+        }
+
+        Ok(theme)
+    }
+
+    // The escape code setting the background, in as many colours as the
+    // terminal has.
+    pub fn background(&self, colour: Colour) -> String {
+        let Colour(r, g, b) = colour;
+
+        match self.support {
+            ColourSupport::TrueColour => format!("{}", color::Bg(color::Rgb(r, g, b))),
+            ColourSupport::Ansi256 => format!("{}", color::Bg(color::AnsiValue(nearest_256(colour)))),
+            ColourSupport::Ansi16 => match nearest_16(colour) {
+                index if index < 8 => format!("\x1b[{}m", 40 + index),
+                index => format!("\x1b[{}m", 100 + index - 8),
+            },
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::classic()
+    }
+}
+
+// $XDG_CONFIG_HOME/terminal-chess/config.toml, or ~/.config/... without it.
+pub fn config_path() -> Option<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(config_dir.join("terminal-chess").join("config.toml"))
+}
+
+fn string_setting<'a>(value: &'a Value, key: &str) -> Result<&'a str, String> {
+    value.as_str().ok_or_else(|| format!("{} should be a string", key))
+}
+
+// Either the 6x6x6 colour cube or the grey ramp, whichever is closer.
+fn nearest_256(colour: Colour) -> u8 {
+    let Colour(r, g, b) = colour;
+
+    let cube_level = |c: u8| (c as u16 * 5 + 127) / 255;
+    let cube_value = |level: u16| if level == 0 { 0 } else { 55 + level * 40 };
+    let (cr, cg, cb) = (cube_level(r), cube_level(g), cube_level(b));
+    let cube = Colour(cube_value(cr) as u8, cube_value(cg) as u8, cube_value(cb) as u8);
+
+    let grey_level = ((r as u16 + g as u16 + b as u16) / 3).saturating_sub(3) / 10;
+    let grey_level = if grey_level > 23 { 23 } else { grey_level };
+    let grey_value = (8 + grey_level * 10) as u8;
+    let grey = Colour(grey_value, grey_value, grey_value);
+
+    if distance(colour, grey) < distance(colour, cube) {
+        (232 + grey_level) as u8
+    } else {
+        (16 + 36 * cr + 6 * cg + cb) as u8
+    }
+}
+
+fn nearest_16(colour: Colour) -> u8 {
+    let nearest = ANSI_16.iter()
+        .enumerate()
+        .min_by_key(|&(_, &(r, g, b))| distance(colour, Colour(r, g, b)))
+        .map(|(index, _)| index)
+        .unwrap_or(0);
+    nearest as u8
+}
+
+fn distance(a: Colour, b: Colour) -> u32 {
+    let difference = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    difference(a.0, b.0) + difference(a.1, b.1) + difference(a.2, b.2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_colours() {
+        assert_eq!("#e2e2e2".parse(), Ok(Colour(226, 226, 226)));
+        assert_eq!("#4682B4".parse(), Ok(Colour(70, 130, 180)));
+        assert!("e2e2e2".parse::<Colour>().is_err());
+        assert!("#e2e2zz".parse::<Colour>().is_err());
+    }
+
+    #[test]
+    fn loads_a_theme_from_config() {
+        let config = r##"
+            theme = "wood"
+            pieces = "letters"
+
+            [colours]
+            cursor = "#ff0000"
+        "##;
+
+        let theme = Theme::from_toml(config, ColourSupport::Ansi256).unwrap();
+
+        assert_eq!(theme.light, Theme::named("wood").unwrap().light);
+        assert_eq!(theme.cursor, Colour(255, 0, 0));
+        assert_eq!(theme.pieces, PieceSet::Letters);
+        assert_eq!(theme.support, ColourSupport::Ansi256);
+    }
+
+    #[test]
+    fn rejects_unknown_settings() {
+        assert!(Theme::from_toml("theme = \"neon\"", ColourSupport::TrueColour).is_err());
+        assert!(Theme::from_toml("pieces = 3", ColourSupport::TrueColour).is_err());
+        assert!(Theme::from_toml("[colours]\nborder = \"#000000\"", ColourSupport::TrueColour).is_err());
+    }
+
+    #[test]
+    fn falls_back_to_fewer_colours() {
+        assert_eq!(nearest_256(Colour(255, 0, 0)), 196);
+        assert_eq!(nearest_256(Colour(226, 226, 226)), 254);
+        assert_eq!(nearest_16(Colour(250, 10, 10)), 9);
+
+        let theme = Theme { support: ColourSupport::Ansi16, ..Theme::classic() };
+        assert_eq!(theme.background(Colour(0, 0, 0)), "\x1b[40m");
+        assert_eq!(theme.background(Colour(255, 255, 255)), "\x1b[107m");
+    }
+
+    #[test]
+    fn detects_colour_support_from_the_environment() {
+        assert_eq!(ColourSupport::from_env(Some("truecolor".to_string()), None), ColourSupport::TrueColour);
+        assert_eq!(ColourSupport::from_env(None, Some("xterm-256color".to_string())), ColourSupport::Ansi256);
+        assert_eq!(ColourSupport::from_env(None, Some("xterm".to_string())), ColourSupport::Ansi16);
+    }
+}
@@ -2,99 +2,442 @@ extern crate termion;
 
 use {Side};
 use session::{Session, SessionState};
+use game::{Outcome, Status};
+use std::cmp;
 use std::io::{Write};
-use board::Coordinate;
+use board::{Board, Coordinate};
 use piece::{Piece, Rank};
-use action::Action;
+use theme::Colour;
+use action::{Action, from_coordinate_for, to_coordinate_for};
+use ai::Score;
+use notation;
 
 use termion::{color, style};
 use ::std::io::Result;
 
-type Out = termion::raw::RawTerminal<::std::io::Stdout>;
+// Squares to try, biggest first, as (width, height, room for rank labels).
+// Small terminals get squares down to a single character.
+static SQUARE_SIZES : [(u16, u16, u16); 4] = [(7, 3, 5), (5, 2, 4), (3, 1, 3), (1, 1, 2)];
+static HEADER_ROWS : u16 = 3;
+static CLOCK_ROWS : u16 = 1;
+static HEADER_WIDTH : u16 = 24;
 
-static SIDE_BUFFER : &str = "     ";
+// The side panel sits just right of the board, level with its top row.
+static PANEL_GAP : u16 = 4;
+static PANEL_WIDTH : usize = 32;
+static MOVE_LIST_ROWS : usize = 8;
 
-pub fn draw(session: &Session, out: &mut Out) -> Result<()> {
+// Where everything is drawn for a given terminal size, centred on screen.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Layout {
+    left: u16,
+    top: u16,
+    header_rows: u16,
+    // Files and ranks on the board
+    files: u16,
+    ranks: u16,
+    columns: u16,
+    rows: u16,
+    label_width: u16,
+    square_width: u16,
+    square_height: u16,
+    show_panel: bool,
+}
+
+impl Layout {
+    pub fn current(session: &Session) -> Layout {
+        let (columns, rows) = termion::terminal_size().unwrap_or((80, 24));
+        Layout::for_session(session, columns, rows)
+    }
+
+    // Games with a clock get an extra header row for it.
+    pub fn for_session(session: &Session, columns: u16, rows: u16) -> Layout {
+        let board = session.game().board();
+        let header_rows = match session.clock_times() {
+            Some(_) => HEADER_ROWS + CLOCK_ROWS,
+            None => HEADER_ROWS,
+        };
+        Layout::for_size(columns, rows, header_rows, board.width() as u16, board.height() as u16)
+    }
+
+    fn for_size(columns: u16, rows: u16, header_rows: u16, files: u16, ranks: u16) -> Layout {
+        let &(square_width, square_height, label_width) = SQUARE_SIZES.iter()
+            // The board needs a row below it for the file labels
+            .find(|&&(width, height, label_width)| label_width + files * width <= columns && header_rows + ranks * height < rows)
+            .unwrap_or(&SQUARE_SIZES[SQUARE_SIZES.len() - 1]);
+
+        let mut layout = Layout {
+            left: 1,
+            top: 1,
+            header_rows,
+            files,
+            ranks,
+            columns,
+            rows,
+            label_width,
+            square_width,
+            square_height,
+            show_panel: false,
+        };
+
+        layout.show_panel = layout.width_with_panel() <= columns;
+        let width = if layout.show_panel { layout.width_with_panel() } else { layout.board_width() };
+        layout.left = columns.saturating_sub(width) / 2 + 1;
+        layout.top = rows.saturating_sub(layout.height()) / 2 + 1;
+        layout
+    }
+
+    fn board_width(&self) -> u16 {
+        self.label_width + self.files * self.square_width
+    }
+
+    fn width_with_panel(&self) -> u16 {
+        self.board_width() + PANEL_GAP + PANEL_WIDTH as u16
+    }
+
+    // The header, the board and the file labels under it
+    fn height(&self) -> u16 {
+        self.header_rows + self.ranks * self.square_height + 1
+    }
+
+    // Lined up with the board, unless that pushes the text off screen
+    fn header_column(&self) -> u16 {
+        cmp::max(1, cmp::min(self.board_column(), self.columns.saturating_sub(HEADER_WIDTH) + 1))
+    }
+
+    fn board_column(&self) -> u16 {
+        self.left + self.label_width
+    }
+
+    fn board_row(&self) -> u16 {
+        self.top + self.header_rows
+    }
+
+    fn panel_column(&self) -> u16 {
+        self.left + self.board_width() + PANEL_GAP
+    }
+}
+
+pub fn draw<W: Write>(session: &Session, out: &mut W) -> Result<()> {
+    draw_with_layout(session, &Layout::current(session), out)
+}
+
+pub fn draw_with_layout<W: Write>(session: &Session, layout: &Layout, out: &mut W) -> Result<()> {
     write!(out, "{}", termion::cursor::Goto(1, 1))?;
     clear(out)?;
 
-    write!(out, "\n\n{}", SIDE_BUFFER)?;
-    if session.game().current_turn() == Side::White {
-        write!(out, "To Act: {}White{}\n\r", style::Bold, style::Reset)?;
-    } else {
-        write!(out, "To Act: {}Black{}\n\r", style::Bold, style::Reset)?;
+    draw_header(session, layout, out)?;
+    draw_table(session, layout, out)?;
+    if layout.show_panel {
+        draw_side_panel(session, layout, out)?;
     }
-    write!(out, "{}", SIDE_BUFFER)?;
-    write!(out, "Cursor at: {}, {} | Piece: {:?}\n\r", session.cursor().row(), session.cursor().column(), session.game().state().piece_at(Coordinate::new(session.cursor().row(), session.cursor().column())))?;
-    draw_table(session, out)?;
     write!(out, "{}", termion::cursor::Goto(1, 1))?;
 
-    Ok(())
+    // Everything is positioned rather than ending in a newline, so nothing
+    // else would push it out to the terminal
+    out.flush()
 }
 
-fn draw_table(session: &Session, out: &mut Out) -> Result<()> {
-    for (index, row) in row_iterator(session).enumerate() {
-        draw_row(row, index, session, out)?;
+fn draw_header<W: Write>(session: &Session, layout: &Layout, out: &mut W) -> Result<()> {
+    let column = layout.header_column();
+
+    write!(out, "{}", termion::cursor::Goto(column, layout.top))?;
+    let to_act = side_name(session.game().current_turn());
+    match (session.outcome(), session.status()) {
+        (Some(Outcome::Checkmate(winner)), _) => write!(out, "{}Checkmate{}, {} wins", style::Bold, style::Reset, side_name(winner))?,
+        (Some(Outcome::Stalemate), _) => write!(out, "{}Stalemate{}, it's a draw", style::Bold, style::Reset)?,
+        (Some(Outcome::Timeout(winner)), _) => write!(out, "{}{} wins on time{}", style::Bold, side_name(winner), style::Reset)?,
+        (Some(Outcome::TimeoutVsInsufficientMaterial), _) => write!(out, "{}Out of time{}, but it's a draw", style::Bold, style::Reset)?,
+        (Some(Outcome::VariantWin(winner)), _) => write!(out, "{}{}{}, {} wins", style::Bold, session.game().state().variant().how_won(), style::Reset, side_name(winner))?,
+        (None, Status::Check) => write!(out, "To Act: {}{}{} {}Check!{}", style::Bold, to_act, style::Reset, color::Fg(color::Red), color::Fg(color::Reset))?,
+        (None, _) => write!(out, "To Act: {}{}{}", style::Bold, to_act, style::Reset)?,
+    }
+
+    write!(out, "{}", termion::cursor::Goto(column, layout.top + 1))?;
+    write!(out, "Cursor at: {} {}", session.cursor().to_coord().to_human(), format_piece(session, session.game().state().piece_at(session.cursor().to_coord())))?;
+
+    if let Some(&(ref white, ref black)) = session.clock_times() {
+        write!(out, "{}", termion::cursor::Goto(column, layout.top + 2))?;
+        draw_clock(session, Side::White, white, out)?;
+        write!(out, "  ")?;
+        draw_clock(session, Side::Black, black, out)?;
+    }
+
+    // The status line sits just above the board
+    write!(out, "{}", termion::cursor::Goto(column, layout.board_row() - 1))?;
+    if let &SessionState::EnteringMove(ref typed) = session.state() {
+        write!(out, "Move: {}{}{}_", style::Bold, typed, style::Reset)
+    } else if let &SessionState::DropSelected(piece, _) = session.state() {
+        write!(out, "Drop: {}{}{}", style::Bold, format_piece(session, &Some(piece)), style::Reset)
+    } else if let Some(message) = session.message() {
+        write!(out, "{}{}{}", color::Fg(color::Red), message, color::Fg(color::Reset))
+    } else if let Some(hint) = session.hint() {
+        write!(out, "Hint: {}{}{} (score {:+.1})", style::Bold, format_hint(&hint.action), style::Reset, hint.score as f64 / 10.0)
+    } else {
+        Ok(())
+    }
+}
+
+// The side whose time is running is in bold.
+fn draw_clock<W: Write>(session: &Session, side: Side, time: &str, out: &mut W) -> Result<()> {
+    let running = session.game().clock().and_then(|clock| clock.running()) == Some(side);
+    if running {
+        write!(out, "{}{} {}{}", style::Bold, side_name(side), time, style::Reset)
+    } else {
+        write!(out, "{} {}", side_name(side), time)
+    }
+}
+
+fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::White => "White",
+        Side::Black => "Black",
+    }
+}
+
+fn draw_side_panel<W: Write>(session: &Session, layout: &Layout, out: &mut W) -> Result<()> {
+    let mut lines = if session.game().state().variant().has_pockets() { pocket_lines(session) } else { captured_lines(session) };
+    lines.push(String::new());
+    lines.append(&mut move_list_lines(session));
+
+    if session.analysis().is_some() {
+        lines.push(String::new());
+        lines.append(&mut analysis_lines(session));
+    }
+
+    for (index, line) in lines.iter().enumerate() {
+        let row = layout.board_row() + index as u16;
+        // Drawing past the bottom would scroll the whole screen
+        if row > layout.rows {
+            break;
+        }
+        write!(out, "{}{}", termion::cursor::Goto(layout.panel_column(), row), line)?;
     }
+
     Ok(())
 }
 
-pub fn clear(out: &mut Out) -> Result<()> {
-    write!(out, "{}", termion::clear::All)
+fn captured_lines(session: &Session) -> Vec<String> {
+    let state = session.game().state();
+    let white_material = material(&state.captured_by(Side::White));
+    let black_material = material(&state.captured_by(Side::Black));
+
+    [(Side::White, "White", white_material - black_material), (Side::Black, "Black", black_material - white_material)]
+        .iter()
+        .map(|&(side, name, difference)| {
+            let pieces: String = state.captured_by(side).iter().map(|piece| format_piece(session, &Some(*piece))).collect();
+            if difference > 0 {
+                format!("{}: {} +{}", name, pieces, difference)
+            } else {
+                format!("{}: {}", name, pieces)
+            }
+        })
+        .collect()
+}
+
+// What each side can drop, with counts for more than one of a piece. The
+// piece picked to drop is in bold.
+fn pocket_lines(session: &Session) -> Vec<String> {
+    let pockets = session.game().state().pockets();
+    let selected = match *session.state() {
+        SessionState::DropSelected(piece, _) => Some(piece),
+        _ => None,
+    };
+
+    let mut lines: Vec<String> = [(Side::White, "White"), (Side::Black, "Black")]
+        .iter()
+        .map(|&(side, name)| {
+            let pieces: Vec<String> = pockets.pieces(side).into_iter().map(|piece| {
+                let count = pockets.count(piece);
+                let shown = if count > 1 {
+                    format!("{}{}", format_piece(session, &Some(piece)), count)
+                } else {
+                    format_piece(session, &Some(piece)).to_string()
+                };
+
+                if Some(piece) == selected { format!("{}{}{}", style::Bold, shown, style::Reset) } else { shown }
+            }).collect();
+            format!("{}: {}", name, pieces.join(" "))
+        })
+        .collect();
+
+    lines.push("p picks a piece to drop".to_string());
+    lines
+}
+
+fn material(pieces: &[Piece]) -> i32 {
+    pieces.iter().map(|piece| match piece.rank() {
+        Rank::Pawn => 1,
+        Rank::Knight => 3,
+        Rank::Bishop => 3,
+        Rank::Rook => 5,
+        Rank::Queen => 9,
+        Rank::King => 0,
+        Rank::Archbishop => 7,
+        Rank::Chancellor => 8,
+        Rank::Amazon => 12,
+    }).sum()
 }
 
-fn draw_row(row: &[Option<Piece>; 8], row_index: usize, session: &Session, out: &mut Out) -> Result<()> {
-    // First row
-    write!(out, "{}", SIDE_BUFFER)?;
-    for (col_index, _square) in column_iterator(session, row).enumerate() {
-        draw_square_padding(out, row_index, col_index, session)?;
+// Two columns of moves, scrolled so the latest move is always visible.
+fn move_list_lines(session: &Session) -> Vec<String> {
+    let start = session.game().state().starting_position();
+    let rows: Vec<String> = notation::numbered_moves(&start, session.move_list())
+        .into_iter()
+        .map(|(move_number, white, black)| {
+            let white_move = white.unwrap_or_else(|| "...".to_string());
+            format!("{:>3}. {:<8} {}", move_number, white_move, black.unwrap_or_default())
+        })
+        .collect();
+
+    let first_visible = rows.len().saturating_sub(MOVE_LIST_ROWS);
+    let mut lines = vec![format!("{}Moves{}", style::Bold, style::Reset)];
+    lines.extend(rows.into_iter().skip(first_visible));
+    lines
+}
+
+fn analysis_lines(session: &Session) -> Vec<String> {
+    let analysis = match session.analysis() {
+        Some(analysis) => analysis,
+        None => return vec![],
+    };
+
+    let mut lines = vec![format!("{}Analysis{}", style::Bold, style::Reset)];
+
+    match analysis.latest() {
+        Some(progress) => {
+            lines.push(format!("Depth: {}", progress.depth));
+            lines.push(format!("Score: {}", format_score(&progress.score)));
+            lines.push(format!("Nodes/s: {}", progress.nodes_per_second()));
+            lines.push("Line:".to_string());
+
+            let sans = notation::line_to_san(analysis.state(), &progress.pv);
+            lines.append(&mut wrap(&notation::numbered_line(analysis.state(), &sans), PANEL_WIDTH));
+        },
+        None => lines.push("Thinking...".to_string()),
     }
-    write!(out, "\n\r")?;
 
-    // Second row with the character
-    write!(out, "{}", SIDE_BUFFER)?;
-    for (col_index, square) in column_iterator(session, row).enumerate() {
-        draw_square_with_piece(square, out, row_index, col_index, session)?;
+    lines
+}
+
+fn format_score(score: &Score) -> String {
+    match *score {
+        Score::Centipawns(cp) => format!("{:+.2}", cp as f64 / 100.0),
+        Score::Mate(moves) if moves > 0 => format!("Mate in {}", moves),
+        Score::Mate(moves) => format!("Mated in {}", -moves),
     }
-    write!(out, "\n\r")?;
+}
 
-    // Third row, same as above (ish)
-    write!(out, "{}", SIDE_BUFFER)?;
-    for (col_index, _square) in column_iterator(session, row).enumerate() {
-        draw_square_padding(out, row_index, col_index, session)?;
+// Breaks text into lines of at most width characters, between words.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut current = String::new();
+
+    for word in text.split_whitespace() {
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > width {
+            lines.push(current);
+            current = String::new();
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
     }
-    write!(out, "\n\r")
+
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
 }
 
-fn draw_square_padding(out: &mut Out, row_index: usize, col_index: usize, session: &Session) -> Result<()> {
-    if is_under_cursor(session, row_index, col_index) {
-        write!(out, "{}       {}", color::Bg(color::Rgb(0,100,100)), color::Bg(color::Reset))
-    } else if is_possible_action(session, row_index, col_index) {
-        write!(out, "{}       {}", color::Bg(color::Rgb(010,000,100)), color::Bg(color::Reset))
-    } else if is_chosen_square(session, row_index, col_index) {
-        write!(out, "{}       {}", color::Bg(color::Rgb(100,000,000)), color::Bg(color::Reset))
-    } else {
-        if (col_index + row_index) % 2 == 0 {
-            write!(out, "{}       {}", color::Bg(color::Rgb(226,226,226)), color::Bg(color::Reset))
+fn draw_table<W: Write>(session: &Session, layout: &Layout, out: &mut W) -> Result<()> {
+    for (index, row) in row_iterator(session).enumerate() {
+        draw_row(row, index, session, layout, out)?;
+    }
+    draw_file_labels(session, layout, out)
+}
+
+fn draw_file_labels<W: Write>(session: &Session, layout: &Layout, out: &mut W) -> Result<()> {
+    write!(out, "{}", termion::cursor::Goto(layout.board_column(), layout.board_row() + layout.ranks * layout.square_height))?;
+    for col_index in 0..session.game().board().width() {
+        write!(out, "{:^width$}", file_label(session, col_index), width = layout.square_width as usize)?;
+    }
+    Ok(())
+}
+
+fn file_label(session: &Session, col_index: usize) -> char {
+    let column = match session.player_as() {
+        Side::White => col_index,
+        Side::Black => session.game().board().width() - 1 - col_index,
+    };
+    (b'a' + column as u8) as char
+}
+
+fn rank_label(session: &Session, row_index: usize) -> usize {
+    match session.player_as() {
+        Side::White => session.game().board().height() - row_index,
+        Side::Black => row_index + 1,
+    }
+}
+
+pub fn clear<W: Write>(out: &mut W) -> Result<()> {
+    write!(out, "{}", termion::clear::All)
+}
+
+fn draw_row<W: Write>(row: &[Option<Piece>], row_index: usize, session: &Session, layout: &Layout, out: &mut W) -> Result<()> {
+    let top = layout.board_row() + row_index as u16 * layout.square_height;
+
+    for line in 0..layout.square_height {
+        write!(out, "{}", termion::cursor::Goto(layout.left, top + line))?;
+
+        // The middle line holds the pieces, labelled with the rank
+        if line == layout.square_height / 2 {
+            write!(out, "{:^width$}", rank_label(session, row_index), width = layout.label_width as usize)?;
+            for (col_index, square) in column_iterator(session, row).enumerate() {
+                draw_square_with_piece(square, out, row_index, col_index, session, layout)?;
+            }
         } else {
-            write!(out, "{}       {}", color::Bg(color::Rgb(190,190,190)), color::Bg(color::Reset))
+            write!(out, "{}", termion::cursor::Goto(layout.board_column(), top + line))?;
+            for (col_index, _square) in column_iterator(session, row).enumerate() {
+                draw_square_padding(out, row_index, col_index, session, layout)?;
+            }
         }
     }
+
+    Ok(())
+}
+
+fn draw_square_padding<W: Write>(out: &mut W, row_index: usize, col_index: usize, session: &Session, layout: &Layout) -> Result<()> {
+    let padding = " ".repeat(layout.square_width as usize);
+    let background = session.theme().background(square_colour(session, row_index, col_index));
+    write!(out, "{}{}{}", background, padding, color::Bg(color::Reset))
 }
 
-fn draw_square_with_piece(square: &Option<Piece>, out: &mut Out, row_index: usize, col_index: usize, session: &Session) -> Result<()> {
+fn draw_square_with_piece<W: Write>(square: &Option<Piece>, out: &mut W, row_index: usize, col_index: usize, session: &Session, layout: &Layout) -> Result<()> {
+    let padding = " ".repeat(layout.square_width as usize / 2);
+    let background = session.theme().background(square_colour(session, row_index, col_index));
+    write!(out, "{}{}{}{}{}", background, padding, format_piece(session, square), padding, color::Bg(color::Reset))
+}
+
+fn square_colour(session: &Session, row_index: usize, col_index: usize) -> Colour {
+    let theme = session.theme();
+
     if is_under_cursor(session, row_index, col_index) {
-        write!(out, "{}   {}   {}", color::Bg(color::Rgb(0,100,100)), format_piece(square), color::Bg(color::Reset))
+        theme.cursor
     } else if is_possible_action(session, row_index, col_index) {
-        write!(out, "{}   {}   {}", color::Bg(color::Rgb(010,000,100)), format_piece(square), color::Bg(color::Reset))
+        theme.legal_move
     } else if is_chosen_square(session, row_index, col_index) {
-        write!(out, "{}   {}   {}", color::Bg(color::Rgb(100,000,000)), format_piece(square), color::Bg(color::Reset))
+        theme.selected
+    } else if is_king_in_check(session, row_index, col_index) {
+        theme.check
+    } else if is_hinted_square(session, row_index, col_index) {
+        theme.hint
+    } else if is_last_move_square(session, row_index, col_index) {
+        theme.last_move
+    } else if (col_index + row_index) % 2 == 0 {
+        theme.light
     } else {
-        if (col_index + row_index) % 2 == 0 {
-            write!(out, "{}   {}   {}", color::Bg(color::Rgb(226,226,226)), format_piece(square), color::Bg(color::Reset))
-        } else {
-            write!(out, "{}   {}   {}", color::Bg(color::Rgb(190,190,190)), format_piece(square), color::Bg(color::Reset))
-        }
+        theme.dark
     }
 }
 
@@ -103,14 +446,19 @@ fn is_under_cursor(session: &Session, row_index: usize, col_index: usize) -> boo
 }
 
 fn is_possible_action(session: &Session, row_index: usize, col_index: usize) -> bool {
-    match session.state() {
-        &SessionState::CoordinateSelected(_, ref actions) => actions.into_iter().any(|action|
+    match *session.state() {
+        SessionState::CoordinateSelected(_, ref actions) => actions.into_iter().any(|action|
             match *action {
                 Action::MovePiece(_,_,ref to) => matches_coordinate(session, to, row_index, col_index),
                 Action::Capture(_,_,_,ref to) => matches_coordinate(session, to, row_index, col_index),
+                Action::Promotion(_,_,_,_,ref to) => matches_coordinate(session, to, row_index, col_index),
+                Action::Castle(_,_,_,ref rook) => matches_coordinate(session, rook, row_index, col_index),
                 _ => false
             }
         ),
+        SessionState::DropSelected(_, ref actions) => actions.iter().any(|action|
+            matches_coordinate(session, to_coordinate_for(action), row_index, col_index)
+        ),
         _ => false
     }
 }
@@ -122,42 +470,83 @@ fn is_chosen_square(session: &Session, row_index: usize, col_index: usize) -> bo
     }
 }
 
+fn is_king_in_check(session: &Session, row_index: usize, col_index: usize) -> bool {
+    match session.status() {
+        Status::Check | Status::Checkmate => {
+            let king = Piece::pack(session.game().current_turn(), Rank::King);
+            session.game().board().find_pieces(king).iter().any(|coord| matches_coordinate(session, coord, row_index, col_index))
+        },
+        _ => false
+    }
+}
+
+fn is_hinted_square(session: &Session, row_index: usize, col_index: usize) -> bool {
+    match session.hint() {
+        Some(hint) => matches_coordinate(session, from_coordinate_for(&hint.action), row_index, col_index) ||
+                      matches_coordinate(session, to_coordinate_for(&hint.action), row_index, col_index),
+        None => false
+    }
+}
+
+fn is_last_move_square(session: &Session, row_index: usize, col_index: usize) -> bool {
+    match session.game().history().last() {
+        Some(action) => matches_coordinate(session, from_coordinate_for(action), row_index, col_index) ||
+                        matches_coordinate(session, to_coordinate_for(action), row_index, col_index),
+        None => false
+    }
+}
+
+fn format_hint(action: &Action) -> String {
+    match *action {
+        Action::Drop(..) => notation::to_long_algebraic(action),
+        _ => format!("{}-{}", from_coordinate_for(action).to_human(), to_coordinate_for(action).to_human()),
+    }
+}
+
 fn matches_coordinate(session: &Session, coord: &Coordinate, row_index: usize, col_index: usize) -> bool {
+    square_drawn_at(session, row_index, col_index) == *coord
+}
+
+// The square in the given row and column of the board as drawn, counting
+// from the top left.
+fn square_drawn_at(session: &Session, row_index: usize, col_index: usize) -> Coordinate {
+    let board = session.game().board();
     match session.player_as() {
-        Side::Black => coord.row() == row_index && coord.column() == 7 - col_index,
-        Side::White => coord.row() == 7 - row_index && coord.column() == col_index,
-    }
-}
-
-fn format_piece(piece: &Option<Piece>) -> &str {
-    if let &Some(p) = piece {
-        match (p.side(), p.rank()) {
-            (Side::White, Rank::Pawn) => "♙",
-            (Side::White, Rank::Knight) => "♘",
-            (Side::White, Rank::Bishop) => "♗",
-            (Side::White, Rank::Rook) => "♖",
-            (Side::White, Rank::Queen) => "♕",
-            (Side::White, Rank::King) => "♔",
-            (Side::Black, Rank::Pawn) => "♟",
-            (Side::Black, Rank::Knight) => "♞",
-            (Side::Black, Rank::Bishop) => "♝",
-            (Side::Black, Rank::Rook) => "♜",
-            (Side::Black, Rank::Queen) => "♛",
-            (Side::Black, Rank::King) => "♚",
-        }
-    } else {
-        " "
+        Side::Black => Coordinate::new(row_index, board.width() - 1 - col_index),
+        Side::White => Coordinate::new(board.height() - 1 - row_index, col_index),
     }
 }
 
-fn row_iterator<'a>(session: &'a Session) -> Box<Iterator<Item = &'a[Option<Piece>; 8]> + 'a> {
+// Finds the square drawn at a terminal position, e.g. one that was clicked.
+// Positions are 1-based, as termion reports them.
+pub fn coordinate_at(session: &Session, x: u16, y: u16) -> Option<Coordinate> {
+    let layout = Layout::current(session);
+    if x < layout.board_column() || y < layout.board_row() {
+        return None
+    }
+
+    let col_index = ((x - layout.board_column()) / layout.square_width) as usize;
+    let row_index = ((y - layout.board_row()) / layout.square_height) as usize;
+    let board = session.game().board();
+    if col_index >= board.width() || row_index >= board.height() {
+        return None
+    }
+
+    Some(square_drawn_at(session, row_index, col_index))
+}
+
+fn format_piece(session: &Session, piece: &Option<Piece>) -> &'static str {
+    session.theme().pieces.format(piece)
+}
+
+fn row_iterator<'a>(session: &'a Session) -> Box<Iterator<Item = &'a [Option<Piece>]> + 'a> {
     match session.player_as() {
-        Side::White => Box::new(session.game().board().rows().into_iter().rev()),
-        Side::Black => Box::new(session.game().board().rows().into_iter())
+        Side::White => Box::new(session.game().board().rows().rev()),
+        Side::Black => Box::new(session.game().board().rows())
     }
 }
 
-fn column_iterator<'a>(session: &Session, row: &'a [Option<Piece>; 8]) -> Box<Iterator<Item = &'a Option<Piece>> + 'a> {
+fn column_iterator<'a>(session: &Session, row: &'a [Option<Piece>]) -> Box<Iterator<Item = &'a Option<Piece>> + 'a> {
     match session.player_as() {
         Side::White => Box::new(row.into_iter()),
         Side::Black => Box::new(row.into_iter().rev())
@@ -169,14 +558,19 @@ pub struct Cursor {
     row: usize,
     column: usize,
     side: Side,
+    // The last row and column it can go to
+    last_row: usize,
+    last_column: usize,
 }
 
 impl Cursor {
-    pub fn new(side: Side) -> Cursor {
+    pub fn new(side: Side, board: &Board) -> Cursor {
         Cursor {
             row: 0,
             column: 0,
             side: side,
+            last_row: board.height() - 1,
+            last_column: board.width() - 1,
         }
     }
 
@@ -190,7 +584,7 @@ impl Cursor {
 
     pub fn up(&mut self) {
         match self.side {
-            Side::White => { if self.row < 7 { self.row += 1 } },
+            Side::White => { if self.row < self.last_row { self.row += 1 } },
             Side::Black => { if self.row > 0 { self.row -= 1 } }
         }
     }
@@ -198,13 +592,13 @@ impl Cursor {
     pub fn down(&mut self) {
         match self.side {
             Side::White => { if self.row > 0 { self.row -= 1 } },
-            Side::Black => { if self.row < 7 { self.row += 1 } }
+            Side::Black => { if self.row < self.last_row { self.row += 1 } }
         }
     }
 
     pub fn right(&mut self) {
         match self.side {
-            Side::White => { if self.column < 7 { self.column += 1 } },
+            Side::White => { if self.column < self.last_column { self.column += 1 } },
             Side::Black => { if self.column > 0 { self.column -= 1 } }
         }
     }
@@ -212,7 +606,7 @@ impl Cursor {
     pub fn left(&mut self) {
         match self.side {
             Side::White => { if self.column > 0 { self.column -= 1 } },
-            Side::Black => { if self.column < 7 { self.column += 1 } }
+            Side::Black => { if self.column < self.last_column { self.column += 1 } }
         }
     }
 
@@ -225,3 +619,215 @@ impl Cursor {
         Coordinate::new(self.row, self.column)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use theme::{PieceSet, Theme};
+    use termion::event::Key;
+
+    // What a terminal would show after drawing: the characters, and which
+    // theme colour is behind each of them.
+    struct Screen {
+        text: Vec<Vec<char>>,
+        backgrounds: Vec<Vec<char>>,
+    }
+
+    impl Screen {
+        fn render(session: &Session, columns: u16, rows: u16) -> Screen {
+            let mut out = vec![];
+            draw_with_layout(session, &Layout::for_session(session, columns, rows), &mut out).unwrap();
+
+            let mut screen = Screen {
+                text: vec![vec![' '; columns as usize]; rows as usize],
+                backgrounds: vec![vec![' '; columns as usize]; rows as usize],
+            };
+            let (mut x, mut y, mut background) = (0, 0, ' ');
+
+            let output = String::from_utf8(out).unwrap();
+            let mut chars = output.chars();
+            while let Some(c) = chars.next() {
+                if c != '\x1b' {
+                    if y < rows as usize && x < columns as usize {
+                        screen.text[y][x] = c;
+                        screen.backgrounds[y][x] = background;
+                    }
+                    x += 1;
+                    continue;
+                }
+
+                // Control sequences look like ESC [ <numbers separated by ;> <command>
+                chars.next();
+                let mut params = String::new();
+                let command = loop {
+                    match chars.next() {
+                        Some(c) if c.is_ascii_digit() || c == ';' => params.push(c),
+                        Some(c) => break c,
+                        None => break ' ',
+                    }
+                };
+                let numbers: Vec<u8> = params.split(';').map(|n| n.parse().unwrap_or(0)).collect();
+
+                match command {
+                    'H' => {
+                        y = numbers[0] as usize - 1;
+                        x = numbers[1] as usize - 1;
+                    },
+                    'm' if numbers.len() == 5 && numbers[0] == 48 => {
+                        background = colour_letter(session.theme(), Colour(numbers[2], numbers[3], numbers[4]));
+                    },
+                    'm' if numbers == [49] => background = ' ',
+                    _ => {}
+                }
+            }
+
+            screen
+        }
+
+        fn text(&self) -> String {
+            lines(&self.text)
+        }
+
+        fn backgrounds(&self) -> String {
+            lines(&self.backgrounds)
+        }
+    }
+
+    fn lines(grid: &[Vec<char>]) -> String {
+        grid.iter()
+            .map(|line| {
+                let end = line.iter().rposition(|&c| c != ' ').map_or(0, |last| last + 1);
+                line[..end].iter().collect::<String>() + "\n"
+            })
+            .collect()
+    }
+
+    fn colour_letter(theme: &Theme, colour: Colour) -> char {
+        [
+            (theme.cursor, 'C'),
+            (theme.legal_move, 'M'),
+            (theme.selected, 'S'),
+            (theme.hint, 'H'),
+            (theme.last_move, 'P'),
+            (theme.check, 'K'),
+            (theme.light, 'L'),
+            (theme.dark, 'D'),
+        ].iter().find(|&&(candidate, _)| candidate == colour).map(|&(_, letter)| letter).unwrap_or('?')
+    }
+
+    fn assert_snapshot(actual: String, expected: &str) {
+        assert!(actual == expected, "Drawing differs from the snapshot, got:\n{}", actual);
+    }
+
+    fn press(session: &mut Session, keys: &str) {
+        for key in keys.chars() {
+            session.update(Key::Char(key));
+        }
+    }
+
+    fn square_size(layout: Layout) -> (u16, u16) {
+        (layout.square_width, layout.square_height)
+    }
+
+    #[test]
+    fn fits_the_squares_to_the_terminal() {
+        let roomy = Layout::for_size(100, 31, HEADER_ROWS, 8, 8);
+        assert_eq!(square_size(roomy), (7, 3));
+        assert!(roomy.show_panel);
+        assert_eq!((roomy.left, roomy.top), (2, 2));
+
+        let standard = Layout::for_size(80, 24, HEADER_ROWS, 8, 8);
+        assert_eq!(square_size(standard), (5, 2));
+        assert!(standard.show_panel);
+        assert_eq!((standard.left, standard.top), (1, 3));
+
+        // A clock takes a row, and with it the biggest squares
+        assert_eq!(square_size(Layout::for_size(100, 28, HEADER_ROWS, 8, 8)), (7, 3));
+        assert_eq!(square_size(Layout::for_size(100, 28, HEADER_ROWS + CLOCK_ROWS, 8, 8)), (5, 2));
+
+        // Wider boards need more room for the same squares
+        assert_eq!(square_size(Layout::for_size(61, 31, HEADER_ROWS, 8, 8)), (7, 3));
+        assert_eq!(square_size(Layout::for_size(61, 31, HEADER_ROWS, 10, 8)), (5, 2));
+    }
+
+    #[test]
+    fn falls_back_to_single_character_squares_on_small_terminals() {
+        let compact = Layout::for_size(20, 12, HEADER_ROWS, 8, 8);
+        assert_eq!(square_size(compact), (1, 1));
+        assert!(!compact.show_panel);
+        assert_eq!((compact.left, compact.top), (6, 1));
+
+        // Too small for anything: the smallest squares, from the top left
+        let tiny = Layout::for_size(5, 5, HEADER_ROWS, 8, 8);
+        assert_eq!(square_size(tiny), (1, 1));
+        assert_eq!((tiny.left, tiny.top), (1, 1));
+    }
+
+    #[test]
+    fn draws_the_starting_position() {
+        let session = Session::new();
+
+        assert_snapshot(Screen::render(&session, 100, 31).text(), include_str!("../tests/fixtures/ui/start.txt"));
+    }
+
+    #[test]
+    fn draws_moves_and_captures_in_the_side_panel() {
+        let mut session = Session::new();
+        press(&mut session, ":e4\n:d5\n:exd5\n");
+
+        assert_snapshot(Screen::render(&session, 100, 31).text(), include_str!("../tests/fixtures/ui/capture.txt"));
+    }
+
+    #[test]
+    fn numbers_the_move_list_from_the_starting_position() {
+        let game = ::game::Game::starting_from(::game::GameState::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 5 40").unwrap());
+        let mut session = Session::new().with_game(game);
+        press(&mut session, ":Kd7\n:e4\n");
+
+        let text = Screen::render(&session, 100, 31).text();
+        assert!(text.contains(" 40. ...      Kd7\n"), "Move list differs, got:\n{}", text);
+        assert!(text.contains(" 41. e4\n"), "Move list differs, got:\n{}", text);
+    }
+
+    #[test]
+    fn highlights_the_selected_piece_and_its_moves() {
+        let mut session = Session::new();
+        for key in &[Key::Right, Key::Right, Key::Right, Key::Right, Key::Up, Key::Char(' ')] {
+            session.update(*key);
+        }
+
+        let screen = Screen::render(&session, 20, 12);
+        assert_snapshot(screen.backgrounds(), include_str!("../tests/fixtures/ui/selected_backgrounds.txt"));
+    }
+
+    #[test]
+    fn marks_the_king_in_check_and_the_last_move() {
+        let mut session = Session::new();
+        press(&mut session, ":e4\n:f6\n:Qh5\n");
+
+        let screen = Screen::render(&session, 20, 12);
+        assert!(screen.text().contains("To Act: Black Check!"));
+        assert_snapshot(screen.backgrounds(), include_str!("../tests/fixtures/ui/check_backgrounds.txt"));
+    }
+
+    #[test]
+    fn draws_a_compact_board_with_letters_and_the_move_prompt() {
+        let theme = Theme { pieces: PieceSet::Letters, ..Theme::classic() };
+        let mut session = Session::new().with_theme(theme);
+        press(&mut session, ":Nf");
+
+        assert_snapshot(Screen::render(&session, 20, 12).text(), include_str!("../tests/fixtures/ui/compact_letters.txt"));
+    }
+
+    #[test]
+    fn draws_a_board_ten_files_wide() {
+        let theme = Theme { pieces: PieceSet::Letters, ..Theme::classic() };
+        let game = ::game::Game::starting_from(::game::GameState::for_variant(&::variant::Capablanca));
+        let mut session = Session::new().with_theme(theme).with_game(game);
+        for _ in 0..12 {
+            session.update(Key::Right);
+        }
+
+        assert_snapshot(Screen::render(&session, 20, 12).text(), include_str!("../tests/fixtures/ui/capablanca_letters.txt"));
+    }
+}
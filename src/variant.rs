@@ -0,0 +1,603 @@
+use std::fmt;
+
+use Side;
+use action::{Action, captured_piece_for, to_coordinate_for};
+use board::{Board, Coordinate};
+use engine;
+use fen;
+use game::GameState;
+use piece::{Piece, Rank};
+
+// The rules a game is played by. Standard chess is one variant among the
+// others; each hook defaults to the usual rules, so a variant only says how
+// it differs. Positions share variants between threads, hence Sync.
+pub trait Variant: Sync {
+    // What it's called on the command line and in saved games.
+    fn name(&self) -> &'static str;
+
+    // Shown when a side wins by the variant's own rules, like "Checkmate".
+    fn how_won(&self) -> &'static str {
+        "Won"
+    }
+
+    fn starting_board(&self) -> Board {
+        Board::default()
+    }
+
+    // How many files and ranks the board has.
+    fn board_size(&self) -> (usize, usize) {
+        (8, 8)
+    }
+
+    // How many kings the side plays with.
+    fn kings(&self, _side: Side) -> usize {
+        1
+    }
+
+    // Every move the piece on the square could make, before asking whether
+    // it leaves its own king in check.
+    fn actions(&self, from: &Coordinate, state: &GameState) -> Vec<Action> {
+        engine::standard_actions(from, state)
+    }
+
+    // What a pawn reaching the far rank can become, the most valuable first.
+    fn promotions(&self) -> Vec<Rank> {
+        vec![Rank::Queen, Rank::Rook, Rank::Bishop, Rank::Knight]
+    }
+
+    // Whether captured pieces are kept to drop again.
+    fn has_pockets(&self) -> bool {
+        false
+    }
+
+    // The pieces the side to move could drop from its pocket, before asking
+    // whether they leave its king in check.
+    fn drops(&self, _state: &GameState) -> Vec<Action> {
+        vec![]
+    }
+
+    // What else happens once the action has moved the pieces. The position
+    // has the action played, with the other side to move.
+    fn consequences(&self, _action: &Action, _state: &GameState) -> Consequences {
+        Consequences::default()
+    }
+
+    fn is_in_check(&self, state: &GameState, side: Side) -> bool {
+        engine::king_is_attacked(state, side)
+    }
+
+    // Whether the side can have made the move that led to the position.
+    fn is_legal_after(&self, state: &GameState, side: Side) -> bool {
+        !self.is_in_check(state, side)
+    }
+
+    // The side that has won by the variant's own rules, if one has.
+    // Checkmate and stalemate are looked for as usual either way.
+    fn winner(&self, _state: &GameState) -> Option<Side> {
+        None
+    }
+}
+
+// Variants are told apart by name, which keeps positions comparable.
+impl PartialEq for dyn Variant {
+    fn eq(&self, other: &dyn Variant) -> bool {
+        self.name() == other.name()
+    }
+}
+
+impl fmt::Debug for dyn Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// What a variant's rules did on top of a move, kept alongside the history
+// so undo can take it back.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct Consequences {
+    // Pieces taken off the board, like the ones caught in an explosion
+    pub removed: Vec<(Coordinate, Piece)>,
+    // Whether the move gave check, for variants that count them
+    pub gave_check: bool,
+    // A captured piece that went into the capturer's pocket
+    pub pocketed: Option<Piece>,
+}
+
+pub fn variant_named(name: &str) -> Option<&'static dyn Variant> {
+    match name {
+        "standard" => Some(&Standard),
+        "king-of-the-hill" => Some(&KingOfTheHill),
+        "three-check" => Some(&ThreeCheck),
+        "atomic" => Some(&Atomic),
+        "horde" => Some(&Horde),
+        "crazyhouse" => Some(&Crazyhouse),
+        "capablanca" => Some(&Capablanca),
+        _ => None,
+    }
+}
+
+pub struct Standard;
+
+impl Variant for Standard {
+    fn name(&self) -> &'static str {
+        "standard"
+    }
+}
+
+// Getting the king to one of the four centre squares wins.
+pub struct KingOfTheHill;
+
+impl Variant for KingOfTheHill {
+    fn name(&self) -> &'static str {
+        "king-of-the-hill"
+    }
+
+    fn how_won(&self) -> &'static str {
+        "King of the hill"
+    }
+
+    fn winner(&self, state: &GameState) -> Option<Side> {
+        [Side::White, Side::Black].iter().cloned().find(|&side| {
+            state.board().find_pieces(Piece::pack(side, Rank::King))
+                .iter()
+                .any(|king| (king.row() == 3 || king.row() == 4) && (king.column() == 3 || king.column() == 4))
+        })
+    }
+}
+
+// Giving check for the third time wins.
+pub struct ThreeCheck;
+
+impl Variant for ThreeCheck {
+    fn name(&self) -> &'static str {
+        "three-check"
+    }
+
+    fn how_won(&self) -> &'static str {
+        "Three checks"
+    }
+
+    fn consequences(&self, _action: &Action, state: &GameState) -> Consequences {
+        Consequences { gave_check: self.is_in_check(state, state.next_to_move()), ..Consequences::default() }
+    }
+
+    fn winner(&self, state: &GameState) -> Option<Side> {
+        [Side::White, Side::Black].iter().cloned().find(|&side| state.checks_given(side) >= 3)
+    }
+}
+
+// Captures explode, taking the capturing piece and every piece but a pawn
+// next to the square with them. Blowing up the other king wins, so kings
+// can't capture, and kings next to each other can't be in check.
+pub struct Atomic;
+
+impl Variant for Atomic {
+    fn name(&self) -> &'static str {
+        "atomic"
+    }
+
+    fn how_won(&self) -> &'static str {
+        "Explosion"
+    }
+
+    fn actions(&self, from: &Coordinate, state: &GameState) -> Vec<Action> {
+        engine::standard_actions(from, state)
+            .into_iter()
+            .filter(|action| match *action {
+                Action::Capture(capturer, _, _, _) => capturer.rank() != Rank::King,
+                _ => true,
+            })
+            .collect()
+    }
+
+    fn consequences(&self, action: &Action, state: &GameState) -> Consequences {
+        let removed = match captured_piece_for(action) {
+            Some(_) => {
+                let to = *to_coordinate_for(action);
+                state.board().pieces_with_coordinates()
+                    .into_iter()
+                    .filter(|&(coordinate, piece)| coordinate == to || (touching(&coordinate, &to) && piece.rank() != Rank::Pawn))
+                    .collect()
+            },
+            None => vec![],
+        };
+
+        Consequences { removed, ..Consequences::default() }
+    }
+
+    fn is_in_check(&self, state: &GameState, side: Side) -> bool {
+        match (king_of(state, side), king_of(state, !side)) {
+            (Some(king), Some(other_king)) => !touching(&king, &other_king) && engine::king_is_attacked(state, side),
+            _ => false,
+        }
+    }
+
+    // Blowing up the other king wins even if it leaves the side in check,
+    // but never when it takes the side's own king too.
+    fn is_legal_after(&self, state: &GameState, side: Side) -> bool {
+        king_of(state, side).is_some() && (king_of(state, !side).is_none() || !self.is_in_check(state, side))
+    }
+
+    fn winner(&self, state: &GameState) -> Option<Side> {
+        match (king_of(state, Side::White), king_of(state, Side::Black)) {
+            (Some(_), None) => Some(Side::White),
+            (None, Some(_)) => Some(Side::Black),
+            _ => None,
+        }
+    }
+}
+
+// White has no king and 36 pawns, which can also move two squares from the
+// first rank. Black wins by taking every one of White's pieces, White by
+// checkmate.
+pub struct Horde;
+
+const HORDE: &str = "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1";
+
+impl Variant for Horde {
+    fn name(&self) -> &'static str {
+        "horde"
+    }
+
+    fn how_won(&self) -> &'static str {
+        "Horde wiped out"
+    }
+
+    fn starting_board(&self) -> Board {
+        fen::parse_for(HORDE, self).expect("Bad Horde position").0
+    }
+
+    fn kings(&self, side: Side) -> usize {
+        match side {
+            Side::White => 0,
+            Side::Black => 1,
+        }
+    }
+
+    fn actions(&self, from: &Coordinate, state: &GameState) -> Vec<Action> {
+        let mut actions = engine::standard_actions(from, state);
+
+        let pawn = Piece::pack(Side::White, Rank::Pawn);
+        if *state.piece_at(*from) == Some(pawn) && from.row() == 0 {
+            let (over, to) = (Coordinate::new(1, from.column()), Coordinate::new(2, from.column()));
+            if state.board().is_empty(over) && state.board().is_empty(to) {
+                actions.push(Action::MovePiece(pawn, *from, to));
+            }
+        }
+
+        actions
+    }
+
+    fn winner(&self, state: &GameState) -> Option<Side> {
+        let white_left = state.board().pieces_with_coordinates()
+            .into_iter()
+            .any(|(_coordinate, piece)| piece.side() == Side::White);
+
+        if white_left { None } else { Some(Side::Black) }
+    }
+}
+
+// Captured pieces go into the capturer's pocket, on its own side, and can be
+// dropped on any empty square instead of moving. Pawns can't be dropped on
+// the first or last rank, and promoted pieces go back to being pawns.
+pub struct Crazyhouse;
+
+impl Variant for Crazyhouse {
+    fn name(&self) -> &'static str {
+        "crazyhouse"
+    }
+
+    fn has_pockets(&self) -> bool {
+        true
+    }
+
+    fn drops(&self, state: &GameState) -> Vec<Action> {
+        let last_row = state.board().height() - 1;
+        let empty: Vec<Coordinate> = state.board().coordinates()
+            .into_iter()
+            .filter(|square| state.board().is_empty(*square))
+            .collect();
+
+        state.pockets().pieces(state.next_to_move())
+            .into_iter()
+            .flat_map(|piece| {
+                empty.iter()
+                    .filter(|square| piece.rank() != Rank::Pawn || (square.row() != 0 && square.row() != last_row))
+                    .map(|square| Action::Drop(piece, *square))
+                    .collect::<Vec<Action>>()
+            })
+            .collect()
+    }
+
+    fn consequences(&self, action: &Action, state: &GameState) -> Consequences {
+        let pocketed = captured_piece_for(action).map(|target| {
+            let rank = if state.captured_promoted_piece() { Rank::Pawn } else { target.rank() };
+            Piece::pack(!target.side(), rank)
+        });
+
+        Consequences { pocketed, ..Consequences::default() }
+    }
+}
+
+// Capablanca chess, on a board ten files wide with an archbishop and a
+// chancellor for each side. The king castles three squares, to the i file
+// or the c file, with the rook landing next to it on the inside.
+pub struct Capablanca;
+
+const CAPABLANCA: &str = "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1";
+
+impl Variant for Capablanca {
+    fn name(&self) -> &'static str {
+        "capablanca"
+    }
+
+    fn starting_board(&self) -> Board {
+        fen::parse_for(CAPABLANCA, self).expect("Bad Capablanca position").0
+    }
+
+    fn board_size(&self) -> (usize, usize) {
+        (10, 8)
+    }
+
+    fn promotions(&self) -> Vec<Rank> {
+        vec![Rank::Queen, Rank::Chancellor, Rank::Archbishop, Rank::Rook, Rank::Bishop, Rank::Knight]
+    }
+}
+
+fn king_of(state: &GameState, side: Side) -> Option<Coordinate> {
+    state.board().find_pieces(Piece::pack(side, Rank::King)).into_iter().next()
+}
+
+// Whether the squares are the same or next to each other, diagonals included.
+fn touching(a: &Coordinate, b: &Coordinate) -> bool {
+    (a.row() as i8 - b.row() as i8).abs() <= 1 && (a.column() as i8 - b.column() as i8).abs() <= 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use action::to_coordinate_for;
+    use error::{IllegalReason, MoveError};
+    use game::Outcome;
+
+    macro_rules! coord {
+        ($x:expr) => { Coordinate::from_human($x.to_string()).unwrap() }
+    }
+
+    fn position(fen: &str, variant: &'static dyn Variant) -> GameState {
+        GameState::from_variant_fen(fen, variant).unwrap()
+    }
+
+    fn play(state: &mut GameState, moves: &[&str]) {
+        for text in moves {
+            let action = state.parse_move(text).unwrap();
+            state.play(action).unwrap();
+        }
+    }
+
+    #[test]
+    fn wins_by_taking_the_king_to_the_centre() {
+        let fen = "8/8/8/8/8/4K3/8/k7 w - - 0 1";
+        let mut standard = GameState::from_fen(fen).unwrap();
+        play(&mut standard, &["Ke4"]);
+        assert_eq!(standard.outcome(), None);
+
+        let mut state = position(fen, &KingOfTheHill);
+        play(&mut state, &["Ke4"]);
+        assert_eq!(state.outcome(), Some(Outcome::VariantWin(Side::White)));
+        assert!(state.legal_moves().is_empty());
+
+        let move_on = Action::MovePiece(Piece::pack(Side::Black, Rank::King), coord!("a1"), coord!("a2"));
+        assert_eq!(state.play(move_on), Err(MoveError::IllegalMove { action: move_on, reason: IllegalReason::GameOver }));
+    }
+
+    #[test]
+    fn wins_with_the_third_check() {
+        let mut state = position("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", &ThreeCheck);
+        play(&mut state, &["Ra8+", "Ke7", "Ra7+", "Ke6"]);
+        assert_eq!(state.checks_given(Side::White), 2);
+        assert_eq!(state.checks_given(Side::Black), 0);
+        assert_eq!(state.outcome(), None);
+
+        play(&mut state, &["Ra6+"]);
+        assert_eq!(state.outcome(), Some(Outcome::VariantWin(Side::White)));
+
+        state.undo().unwrap();
+        assert_eq!(state.checks_given(Side::White), 2);
+        assert_eq!(state.outcome(), None);
+    }
+
+    #[test]
+    fn explodes_everything_but_pawns_around_a_capture() {
+        let mut state = GameState::for_variant(&Atomic);
+        play(&mut state, &["Nf3", "d5", "Ne5", "h6"]);
+        let before = state.clone();
+
+        play(&mut state, &["Nxf7"]);
+        for square in &["f7", "e8", "f8", "g8"] {
+            assert_eq!(*state.piece_at(coord!(square)), None);
+        }
+        assert_eq!(*state.piece_at(coord!("e7")), Some(Piece::pack(Side::Black, Rank::Pawn)));
+        assert_eq!(*state.piece_at(coord!("g7")), Some(Piece::pack(Side::Black, Rank::Pawn)));
+        assert_eq!(*state.piece_at(coord!("d8")), Some(Piece::pack(Side::Black, Rank::Queen)));
+        assert_eq!(state.outcome(), Some(Outcome::VariantWin(Side::White)));
+
+        state.undo().unwrap();
+        assert_eq!(state, before);
+    }
+
+    #[test]
+    fn never_blows_up_its_own_king() {
+        let fen = "4k3/8/8/8/8/8/3n4/2B1K3 w - - 0 1";
+        let takes_knight = |state: &mut GameState| state.actions_at(coord!("c1"))
+            .iter()
+            .any(|action| *to_coordinate_for(action) == coord!("d2"));
+
+        assert!(takes_knight(&mut GameState::from_fen(fen).unwrap()));
+        assert!(!takes_knight(&mut position(fen, &Atomic)));
+
+        // Kings can't capture at all
+        let mut state = position("4k3/8/8/8/8/8/4n3/4K3 w - - 0 1", &Atomic);
+        assert!(state.parse_move("Kxe2").is_err());
+    }
+
+    #[test]
+    fn kings_next_to_each_other_are_not_in_check() {
+        let mut state = position("8/8/8/8/8/8/3kq3/4K3 w - - 0 1", &Atomic);
+        assert!(!engine::is_in_check(&state, Side::White));
+        assert!(!state.has_completed());
+    }
+
+    #[test]
+    fn blowing_up_the_other_king_beats_being_in_check() {
+        let fen = "3qk3/8/8/8/8/8/8/3RK2r w - - 0 1";
+        assert!(GameState::from_fen(fen).unwrap().parse_move("Rxd8").is_err());
+
+        let mut state = position(fen, &Atomic);
+        play(&mut state, &["Rxd8"]);
+        assert_eq!(state.outcome(), Some(Outcome::VariantWin(Side::White)));
+    }
+
+    #[test]
+    fn starts_the_horde_without_a_white_king() {
+        let mut state = GameState::for_variant(&Horde);
+        let white: Vec<Piece> = state.board().pieces_with_coordinates()
+            .into_iter()
+            .map(|(_coordinate, piece)| piece)
+            .filter(|piece| piece.side() == Side::White)
+            .collect();
+
+        assert_eq!(white.len(), 36);
+        assert!(white.iter().all(|piece| piece.rank() == Rank::Pawn));
+        assert_eq!(state.castling_rights(), vec![coord!("h8"), coord!("a8")]);
+        assert_eq!(state.status(), ::game::Status::InProgress);
+    }
+
+    #[test]
+    fn moves_horde_pawns_two_squares_from_the_first_rank() {
+        let mut state = position("4k3/8/8/8/8/8/8/P7 w - - 0 1", &Horde);
+        let pawn = Piece::pack(Side::White, Rank::Pawn);
+
+        let actions = state.actions_at(coord!("a1"));
+        assert!(actions.contains(&Action::MovePiece(pawn, coord!("a1"), coord!("a2"))));
+        assert!(actions.contains(&Action::MovePiece(pawn, coord!("a1"), coord!("a3"))));
+    }
+
+    #[test]
+    fn wins_for_black_once_the_horde_is_gone() {
+        let mut state = position("4k3/8/8/8/8/8/1q6/P7 b - - 0 1", &Horde);
+        assert_eq!(state.outcome(), None);
+
+        play(&mut state, &["Qxa1"]);
+        assert_eq!(state.outcome(), Some(Outcome::VariantWin(Side::Black)));
+    }
+
+    #[test]
+    fn pockets_captured_pieces_for_the_capturer() {
+        let mut state = GameState::for_variant(&Crazyhouse);
+        play(&mut state, &["e4", "d5", "exd5"]);
+        let pawn = Piece::pack(Side::White, Rank::Pawn);
+        assert_eq!(state.pockets().pieces(Side::White), vec![pawn]);
+        assert!(state.pockets().pieces(Side::Black).is_empty());
+
+        state.undo().unwrap();
+        assert!(state.pockets().is_empty());
+    }
+
+    #[test]
+    fn drops_on_empty_squares_but_not_pawns_on_the_back_ranks() {
+        let mut state = position("4k3/8/8/8/8/8/8/4K3[Pn] w - - 0 1", &Crazyhouse);
+        let pawn = Piece::pack(Side::White, Rank::Pawn);
+        let drops = state.legal_moves();
+
+        assert!(drops.contains(&Action::Drop(pawn, coord!("e4"))));
+        assert!(!drops.contains(&Action::Drop(pawn, coord!("a1"))));
+        assert!(!drops.contains(&Action::Drop(pawn, coord!("a8"))));
+        assert!(!drops.contains(&Action::Drop(pawn, coord!("e1"))));
+        assert!(!drops.iter().any(|action| match *action {
+            Action::Drop(piece, _) => piece.side() == Side::Black,
+            _ => false,
+        }));
+
+        play(&mut state, &["P@e4"]);
+        assert_eq!(*state.piece_at(coord!("e4")), Some(pawn));
+        assert!(state.pockets().pieces(Side::White).is_empty());
+
+        state.undo().unwrap();
+        assert_eq!(*state.piece_at(coord!("e4")), None);
+        assert_eq!(state.pockets().count(pawn), 1);
+    }
+
+    #[test]
+    fn drops_a_piece_to_block_check() {
+        let mut state = position("4r2k/8/8/8/8/8/8/4K3[N] w - - 0 1", &Crazyhouse);
+        let legal = state.legal_moves();
+        let knight = Piece::pack(Side::White, Rank::Knight);
+
+        assert!(legal.contains(&Action::Drop(knight, coord!("e4"))));
+        assert!(!legal.contains(&Action::Drop(knight, coord!("a4"))));
+    }
+
+    #[test]
+    fn pockets_promoted_pieces_as_pawns() {
+        let mut state = position("4k3/8/8/8/8/8/3q4/4K3 w - - 0 1", &Crazyhouse);
+        play(&mut state, &["Kxd2"]);
+        assert_eq!(state.pockets().pieces(Side::White), vec![Piece::pack(Side::White, Rank::Queen)]);
+
+        let mut state = position("4k3/8/8/8/8/8/3q~4/4K3 w - - 0 1", &Crazyhouse);
+        play(&mut state, &["Kxd2"]);
+        assert_eq!(state.pockets().pieces(Side::White), vec![Piece::pack(Side::White, Rank::Pawn)]);
+    }
+
+    #[test]
+    fn pockets_a_pawn_for_a_piece_promoted_on_the_board() {
+        let mut state = position("4k3/3P4/8/8/8/8/8/4K3 w - - 0 1", &Crazyhouse);
+        play(&mut state, &["d8=Q+"]);
+        assert!(state.is_promoted(coord!("d8")));
+
+        play(&mut state, &["Kxd8"]);
+        assert_eq!(state.pockets().pieces(Side::Black), vec![Piece::pack(Side::Black, Rank::Pawn)]);
+        assert!(state.pockets().pieces(Side::White).is_empty());
+
+        state.undo().unwrap();
+        assert!(state.pockets().is_empty());
+        assert_eq!(*state.piece_at(coord!("d8")), Some(Piece::pack(Side::White, Rank::Queen)));
+    }
+
+    #[test]
+    fn starts_capablanca_on_a_board_ten_files_wide() {
+        let mut state = GameState::for_variant(&Capablanca);
+        assert_eq!((state.board().width(), state.board().height()), (10, 8));
+        assert_eq!(state.to_fen(), CAPABLANCA);
+        assert_eq!(state.legal_moves().len(), 28);
+
+        play(&mut state, &["Ci3", "Ad6", "e4"]);
+        assert_eq!(*state.piece_at(coord!("i3")), Some(Piece::pack(Side::White, Rank::Chancellor)));
+        assert_eq!(*state.piece_at(coord!("d6")), Some(Piece::pack(Side::Black, Rank::Archbishop)));
+        assert_eq!(state.to_fen(), "rn1bqkbcnr/pppppppppp/3a6/10/4P5/8C1/PPPP1PPPPP/RNABQKB1NR b KQkq - 0 2");
+    }
+
+    #[test]
+    fn castles_three_squares_on_a_wide_board() {
+        let fen = "r4k3r/10/10/10/10/10/10/R4K3R w KQkq - 0 1";
+        let king = Piece::pack(Side::White, Rank::King);
+
+        let mut state = position(fen, &Capablanca);
+        play(&mut state, &["O-O"]);
+        assert_eq!(state.history()[0], Action::Castle(king, coord!("f1"), coord!("i1"), coord!("j1")));
+        assert_eq!(*state.piece_at(coord!("h1")), Some(Piece::pack(Side::White, Rank::Rook)));
+
+        let mut state = position(fen, &Capablanca);
+        play(&mut state, &["O-O-O"]);
+        assert_eq!(*state.piece_at(coord!("c1")), Some(king));
+        assert_eq!(*state.piece_at(coord!("d1")), Some(Piece::pack(Side::White, Rank::Rook)));
+    }
+
+    #[test]
+    fn finds_variants_by_name() {
+        for name in &["standard", "king-of-the-hill", "three-check", "atomic", "horde", "crazyhouse", "capablanca"] {
+            assert_eq!(variant_named(name).map(|variant| variant.name()), Some(*name));
+        }
+        assert!(variant_named("bughouse").is_none());
+    }
+}
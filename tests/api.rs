@@ -0,0 +1,48 @@
+extern crate terminal_chess;
+
+use terminal_chess::{Ai, Coordinate, FenError, Move, Outcome, Position, Rank, Side, Status};
+
+#[test]
+fn plays_through_a_position() {
+    let mut position = Position::new();
+    assert_eq!(position.legal_moves().len(), 20);
+
+    let e4 = position.parse_move("e4").unwrap();
+    assert_eq!(position.san(&e4), "e4");
+    position.play(e4).unwrap();
+    assert_eq!(position.next_to_move(), Side::Black);
+    assert_eq!(position.to_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+
+    position.undo().unwrap();
+    assert_eq!(position, Position::new());
+}
+
+#[test]
+fn sets_up_positions_from_fen() {
+    let mut position = Position::from_fen("7k/5Q2/6K1/8/8/8/8/8 w - - 12 40").unwrap();
+    let e4: Coordinate = "e4".parse().unwrap();
+    assert_eq!(*position.piece_at(e4), None);
+
+    let mate = position.parse_move("Qg7").unwrap();
+    match mate {
+        Move::MovePiece(piece, _, _) => assert!(piece.rank() == Rank::Queen),
+        _ => panic!("Expected a quiet queen move"),
+    }
+    position.play(mate).unwrap();
+
+    assert_eq!(position.status(), Status::Checkmate);
+    assert_eq!(position.outcome(), Some(Outcome::Checkmate(Side::White)));
+    assert_eq!(position.to_fen(), "7k/6Q1/6K1/8/8/8/8/8 b - - 13 40");
+
+    assert_eq!(Position::from_fen("8/8/8/8/8/8/8/8 w"), Err(FenError::MissingFields));
+}
+
+#[test]
+fn the_ai_moves_in_any_position() {
+    // Taking the queen is the only legal move
+    let mut position = Position::from_fen("4k3/8/8/8/8/8/4q3/4K3 w - - 0 1").unwrap();
+    let ai = Ai::new();
+
+    let reply = ai.make_move(&mut position).unwrap();
+    assert_eq!(position.legal_moves(), vec![reply]);
+}
@@ -0,0 +1,173 @@
+extern crate terminal_chess;
+extern crate termion;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use termion::event::{Event, Key};
+use terminal_chess::{Ai, Crazyhouse, Game, Level, Outcome, Position, ScriptedInput, SessionState, Side, Status};
+
+#[test]
+fn plays_a_game_to_checkmate() {
+    let mut session = terminal_chess::new_session();
+    let mut input = ScriptedInput::typing(":f3\n:e5\n:g4\n:Qh4\n:a3\n");
+
+    session.play(&mut input, &mut io::sink());
+
+    assert_eq!(session.game().history().len(), 4);
+    assert_eq!(session.status(), Status::Checkmate);
+    // The session stops at checkmate, before the move typed after it
+    assert_eq!(input.remaining(), 4);
+}
+
+#[test]
+fn moves_pieces_with_the_cursor() {
+    let mut session = terminal_chess::new_session();
+    // The cursor starts on a1: over to e2, select, up to e4, move
+    let keys = vec![Key::Right, Key::Right, Key::Right, Key::Right, Key::Up, Key::Char(' '), Key::Up, Key::Up, Key::Char(' ')];
+    let mut input = ScriptedInput::new(keys.into_iter().map(Event::Key).collect());
+
+    session.play(&mut input, &mut io::sink());
+
+    assert_eq!(session.game().history().len(), 1);
+    assert_eq!(session.game().current_turn(), Side::Black);
+    assert_eq!(session.cursor().to_coord().to_human(), "e4");
+}
+
+#[test]
+fn castles_with_the_cursor_on_the_rook() {
+    fn castle_from(fen: &str, keys: Vec<Key>) -> Vec<String> {
+        let start = Position::from_fen(fen).unwrap();
+        let mut session = terminal_chess::new_session().with_game(Game::starting_from(start));
+        let mut input = ScriptedInput::new(keys.into_iter().map(Event::Key).collect());
+        session.play(&mut input, &mut io::sink());
+        session.move_list().clone()
+    }
+
+    // The king on f1 could step to g1 or castle there: g1 steps, h1 castles
+    let right = |times| vec![Key::Right; times];
+    let step = [right(5), vec![Key::Char(' ')], right(1), vec![Key::Char(' ')]].concat();
+    assert_eq!(castle_from("4k3/8/8/8/8/8/8/5K1R w K - 0 1", step), vec!["Kg1"]);
+    let castle = [right(5), vec![Key::Char(' ')], right(2), vec![Key::Char(' ')]].concat();
+    assert_eq!(castle_from("4k3/8/8/8/8/8/8/5K1R w K - 0 1", castle), vec!["O-O"]);
+
+    // The king on g1 castles where it is, so picking it again only deselects
+    let deselect = [right(6), vec![Key::Char(' '), Key::Char(' ')]].concat();
+    assert!(castle_from("4k3/8/8/8/8/8/8/6KR w K - 0 1", deselect).is_empty());
+}
+
+#[test]
+fn drops_pieces_from_the_pocket() {
+    let start = Position::from_variant_fen("4k3/8/8/8/8/8/8/4K3[QN] w - - 0 1", &Crazyhouse).unwrap();
+    let mut session = terminal_chess::new_session().with_game(Game::starting_from(start));
+    // p picks the queen, p again the knight, then drop it from a1 up on a3
+    let keys = vec![Key::Char('p'), Key::Char('p'), Key::Up, Key::Up, Key::Char(' ')];
+    let mut input = ScriptedInput::new(keys.into_iter().map(Event::Key).collect());
+
+    session.play(&mut input, &mut io::sink());
+
+    assert_eq!(session.move_list()[0], "N@a3");
+    assert_eq!(session.game().state().pockets().all_pieces(Side::White).len(), 1);
+}
+
+#[test]
+fn the_ai_replies_to_each_move() {
+    let mut session = terminal_chess::new_ai_session(Ai::new().with_level(Level::Beginner));
+
+    // The AI thinks in the background, and the session lets it finish
+    // once the keys run out
+    session.play(&mut ScriptedInput::typing(":e4\n"), &mut io::sink());
+    assert_eq!(session.game().history().len(), 2);
+    session.play(&mut ScriptedInput::typing(":Nf3\n"), &mut io::sink());
+
+    assert_eq!(session.game().history().len(), 4);
+    assert_eq!(session.game().current_turn(), Side::White);
+    assert_eq!(session.move_list()[0], "e4");
+    assert_eq!(session.move_list()[2], "Nf3");
+}
+
+#[test]
+fn takes_back_the_ai_reply_with_the_move() {
+    let mut session = terminal_chess::new_ai_session(Ai::new().with_level(Level::Beginner));
+    let mut input = ScriptedInput::typing(":e4\nu");
+
+    session.play(&mut input, &mut io::sink());
+
+    assert!(session.game().history().is_empty());
+    assert_eq!(session.game().current_turn(), Side::White);
+}
+
+#[test]
+fn the_ai_moves_first_when_the_player_is_black() {
+    let mut session = terminal_chess::new_ai_session(Ai::new().with_level(Level::Beginner)).playing_as(Side::Black);
+
+    session.play(&mut ScriptedInput::new(vec![]), &mut io::sink());
+
+    assert_eq!(session.game().history().len(), 1);
+    assert_eq!(session.game().current_turn(), Side::Black);
+}
+
+#[test]
+fn does_not_move_for_the_player_against_the_ai() {
+    let mut session = terminal_chess::new_ai_session(Ai::new().with_level(Level::Beginner));
+    let mut input = ScriptedInput::typing("a");
+
+    session.play(&mut input, &mut io::sink());
+
+    assert!(session.game().history().is_empty());
+}
+
+#[test]
+fn rejects_illegal_typed_moves() {
+    let mut session = terminal_chess::new_session();
+    let mut input = ScriptedInput::typing(":e5\n");
+
+    session.play(&mut input, &mut io::sink());
+
+    assert!(session.game().history().is_empty());
+    assert_eq!(session.message().map(|message| message.as_str()), Some("Illegal move: e5"));
+}
+
+#[test]
+fn quits_on_q() {
+    let mut session = terminal_chess::new_session();
+    let mut input = ScriptedInput::typing(":e4\nq:e5\n");
+
+    session.play(&mut input, &mut io::sink());
+
+    assert_eq!(session.state(), &SessionState::WillQuit);
+    assert_eq!(session.game().history().len(), 1);
+    assert_eq!(input.remaining(), 4);
+}
+
+#[test]
+fn loses_on_time_when_the_flag_falls() {
+    let mut session = terminal_chess::new_session().with_clock("0.0001".parse().unwrap());
+    thread::sleep(Duration::from_millis(20));
+    // Something has to happen for the session to look at the clock
+    let mut input = ScriptedInput::new(vec![Event::Unsupported(vec![]), Event::Key(Key::Char('q'))]);
+
+    session.play(&mut input, &mut io::sink());
+
+    assert_eq!(session.outcome(), Some(Outcome::Timeout(Side::Black)));
+    assert_eq!(input.remaining(), 1);
+}
+
+#[test]
+fn saves_the_game_to_resume_later() {
+    let path = env::temp_dir().join("terminal-chess-saves-the-game.toml");
+    let mut session = terminal_chess::new_ai_session(Ai::new().with_level(Level::Beginner)).with_save_file(path.clone());
+    let mut input = ScriptedInput::typing(":d4\nq");
+
+    session.play(&mut input, &mut io::sink());
+    let resumed = terminal_chess::load(&path);
+    fs::remove_file(&path).unwrap();
+
+    let resumed = resumed.unwrap();
+    assert_eq!(resumed.game().history(), session.game().history());
+    assert_eq!(resumed.move_list()[0], "d4");
+    assert!(resumed.opponent().is_some());
+}